@@ -0,0 +1,33 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+// Mirrors `AccumulateConstants` in `accumulation.rs`
+struct AccumulateConstants {
+    float weight;
+    uint3 _pad;
+};
+
+struct Bindings {
+    UniformTexture2D input;
+    // Running average of the jittered frames, kept across frames
+    UniformRwTexture2D history;
+    UniformByteBuffer constants;
+};
+
+// Blends `input` into `history` with `weight` and outputs the result, a weight of `1` starts over
+float4 main(float4 position : SV_POSITION) : SV_Target0 {
+    Bindings bnd = loadBindings<Bindings>();
+
+    AccumulateConstants c = bnd.constants.load<AccumulateConstants>(0);
+    uint2 pixel = uint2(position.xy);
+
+    RWTexture2D<float4> history = bnd.history.rw<float4>();
+    if (c.weight == 0.0) {
+        return history[pixel];
+    }
+
+    float4 input = bnd.input.load<float4>(pixel);
+    // Not blended with a weight of `1`, the history may hold anything after a resize
+    float4 result = c.weight == 1.0 ? input : lerp(history[pixel], input, c.weight);
+    history[pixel] = result;
+    return result;
+}
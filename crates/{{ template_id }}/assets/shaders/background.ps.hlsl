@@ -0,0 +1,23 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+// Mirrors `BackgroundConstants` in `background.rs`
+struct BackgroundConstants {
+    float3 top;
+    float viewportHeight;
+    float3 bottom;
+    float _pad;
+};
+
+struct Bindings {
+    UniformByteBuffer constants;
+};
+
+// Vertical gradient from `top` to `bottom`, drawn before the main pass
+float4 main(float4 input : SV_POSITION) : SV_Target0 {
+    Bindings bnd = loadBindings<Bindings>();
+
+    BackgroundConstants c = bnd.constants.load<BackgroundConstants>(0);
+
+    float t = saturate(input.y / c.viewportHeight);
+    return float4(lerp(c.top, c.bottom, t), 1.0);
+}
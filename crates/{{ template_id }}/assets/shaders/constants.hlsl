@@ -0,0 +1,105 @@
+// Buffer layouts and shading shared by `main.ps.hlsl`, `geometry.vs.hlsl`, `deferred.ps.hlsl` and
+// `instance_colors.cs.hlsl`
+
+// Mirrors `RaytracerConstants` in `raytracer.rs`
+struct RaytracerConstants {
+    uint viewMode;
+    float wireframeOpacity;
+    float wireframeWidth;
+    uint viewFromLight;
+    float3 lightDirection;
+    float sceneRadius;
+    float3 sceneCenter;
+    float _pad0;
+    float2 viewportSize;
+    float gamma;
+    float contrast;
+    float saturation;
+    uint useViewDirection;
+    float3 viewDirection;
+    float rayTMin;
+    float rayTMax;
+    float _pad1;
+    // x, y, width, height
+    uint4 renderRegion;
+    uint fogMode;
+    float fogDensity;
+    float fogStart;
+    float fogEnd;
+    float3 fogColor;
+    float _pad2;
+    // One of the `DEBUG_MODE_*` in `main.ps.hlsl`
+    uint debugMode;
+    uint3 _pad3;
+};
+
+// Mirrors `CameraConstants` in `camera.rs`
+struct CameraConstants {
+    row_major float4x4 viewProj;
+    row_major float4x4 invViewProj;
+    float3 position;
+    uint enabled;
+};
+
+// Mirrors `InstanceMesh` in `scene.rs`
+struct InstanceMesh {
+    uint firstIndex;
+    uint indexCount;
+    uint baseVertex;
+    // The instance's `hitGroupOffset`, for the passes that don't read it from the TLAS
+    uint hitGroupOffset;
+};
+
+// Mirrors `USER_UNIFORM_LAYOUT` in `user_uniforms.rs`, values come from `--uniforms`
+struct UserUniforms {
+    float3 tint;
+    float brightness;
+};
+
+// Fraction of the albedo that surfaces facing away from the light still reflect
+static const float AMBIENT = 0.2;
+
+// Hit behaviors picked by `InstanceDescription::hit_group_offset`, see `Material` in `scene.rs`
+static const uint HIT_GROUP_MAGENTA = 0;
+static const uint HIT_GROUP_ORANGE = 1;
+// Colored by `InstanceID()` from `instanceColors`, used by `--instance-grid` and the default scene
+static const uint HIT_GROUP_INSTANCE_COLOR = 2;
+
+// Color of the material `hitGroupOffset` selects, `instanceColors` holds a `float4` per instance
+float3 materialAlbedo(uint hitGroupOffset, UniformByteBuffer instanceColors, uint instanceId) {
+    switch (hitGroupOffset) {
+    case HIT_GROUP_ORANGE:
+        return float3(1, 0.5, 0);
+    case HIT_GROUP_INSTANCE_COLOR:
+        return instanceColors.load<float4>(instanceId * 16).rgb;
+    case HIT_GROUP_MAGENTA:
+    default:
+        return float3(1, 0, 1);
+    }
+}
+
+// Distinct color per value, for telling neighboring instances apart
+float3 hashColor(uint value) {
+    value ^= value >> 16;
+    value *= 0x7feb352d;
+    value ^= value >> 15;
+    value *= 0x846ca68b;
+    value ^= value >> 16;
+    return float3(value & 0xff, (value >> 8) & 0xff, (value >> 16) & 0xff) / 255.0;
+}
+
+// Lambertian reflection of the directional light, which travels along `lightDirection`
+float3 lambert(float3 albedo, float3 normal, float3 lightDirection) {
+    float3 toLight = -lightDirection;
+    float diffuse = dot(toLight, toLight) > 0.0 ? saturate(dot(normal, normalize(toLight))) : 0.0;
+    return albedo * lerp(AMBIENT, 1.0, diffuse);
+}
+
+// Gamma, then contrast around mid gray, then saturation around the Rec. 709 luminance
+float3 applyColorGrading(float3 color, RaytracerConstants c) {
+    color = pow(saturate(color), 1.0 / c.gamma);
+    color = (color - 0.5) * c.contrast + 0.5;
+    float luminance = dot(color, float3(0.2126, 0.7152, 0.0722));
+    color = lerp(luminance, color, c.saturation);
+    return saturate(color);
+}
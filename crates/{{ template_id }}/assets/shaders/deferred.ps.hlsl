@@ -0,0 +1,101 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+#include "{{ template_id }}::constants.hlsl"
+
+// The three passes of `DrawMode::Deferred`, see `deferred.rs`: the G-buffer pass rasterizes the
+// scene's albedo, normals and depth, the lighting pass shades every covered pixel of it into an
+// HDR target and the tonemap pass maps that into the display's range over the background.
+
+// Intensity of the directional light. Brighter than the display's range, so that lit surfaces
+// end up above `1` in the HDR target and the tonemap has something to compress.
+static const float LIGHT_INTENSITY = 4.0;
+
+// Same layout as `GeometryBindings` in `geometry.vs.hlsl`, up to the instance meshes
+struct GBufferBindings {
+    UniformAccelerationStructure tlas;
+    UniformByteBuffer constants;
+    UniformByteBuffer userUniforms;
+    UniformByteBuffer camera;
+    UniformByteBuffer instanceColors;
+    UniformByteBuffer indices;
+    UniformByteBuffer normals;
+    UniformByteBuffer instanceMeshes;
+};
+
+// Mirrors `GBufferVertex` in `geometry.vs.hlsl`
+struct GBufferVertex {
+    float4 position : SV_POSITION;
+    float3 worldPosition : WORLD_POSITION;
+    float3 normal : NORMAL;
+    nointerpolation uint instanceId : INSTANCE_ID;
+};
+
+// Mirrors `GBuffer` in `deferred.rs`. Pixels that no triangle covers keep the cleared alpha of
+// `0` in both targets.
+struct GBufferOutput {
+    float4 albedo : SV_Target0;
+    // World-space normal facing the viewer
+    float4 normal : SV_Target1;
+};
+
+// `{{ template_id }}-gbuffer`
+GBufferOutput mainGBuffer(GBufferVertex input) {
+    GBufferBindings bnd = loadBindings<GBufferBindings>();
+
+    CameraConstants camera = bnd.camera.load<CameraConstants>(0);
+    InstanceMesh mesh = bnd.instanceMeshes.load<InstanceMesh>(input.instanceId * 16);
+
+    // Without a camera the view looks down +Z, like `clipPosition()` in `geometry.vs.hlsl`
+    float3 toViewer = camera.enabled ? camera.position - input.worldPosition : float3(0, 0, -1);
+    float3 normal = normalize(input.normal);
+    normal = dot(normal, toViewer) < 0.0 ? -normal : normal;
+
+    GBufferOutput output;
+    output.albedo = float4(materialAlbedo(mesh.hitGroupOffset, bnd.instanceColors, input.instanceId), 1.0);
+    output.normal = float4(normal, 1.0);
+    return output;
+}
+
+struct LightingBindings {
+    UniformTexture2D albedo;
+    UniformTexture2D normal;
+    UniformByteBuffer constants;
+};
+
+// `{{ template_id }}-deferred-lighting`, writes the lit color with the G-buffer's coverage in
+// alpha
+float4 mainLighting(float4 position : SV_POSITION) : SV_Target0 {
+    LightingBindings bnd = loadBindings<LightingBindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    uint2 pixel = uint2(position.xy);
+
+    float4 albedo = bnd.albedo.load<float4>(pixel);
+    if (albedo.a == 0.0) {
+        return 0.0;
+    }
+    float3 normal = bnd.normal.load<float4>(pixel).xyz;
+    return float4(lambert(albedo.rgb, normal, c.lightDirection) * LIGHT_INTENSITY, 1.0);
+}
+
+struct TonemapBindings {
+    UniformTexture2D hdr;
+    UniformByteBuffer constants;
+    UniformByteBuffer userUniforms;
+};
+
+// `{{ template_id }}-tonemap`, Reinhard followed by the same color grading as the raytraced view.
+// Uncovered pixels are discarded, leaving the background in place.
+float4 mainTonemap(float4 position : SV_POSITION) : SV_Target0 {
+    TonemapBindings bnd = loadBindings<TonemapBindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    UserUniforms u = bnd.userUniforms.load<UserUniforms>(0);
+
+    float4 hdr = bnd.hdr.load<float4>(uint2(position.xy));
+    if (hdr.a == 0.0) {
+        discard;
+    }
+    float3 color = hdr.rgb * u.tint * u.brightness;
+    return float4(applyColorGrading(color / (1.0 + color), c), 1.0);
+}
@@ -0,0 +1,11 @@
+// A single triangle covering the whole target, drawn by `FullscreenPass::draw()` in
+// `fullscreen.rs` with 3 vertices and no vertex buffers.
+//
+// Vertex 0, 1 and 2 are placed at (-1, 1), (3, 1) and (-1, -3) in clip space: the triangle's
+// right angle sits in the top-left corner and its legs are twice as long as the target, so the
+// rasterizer clips it to exactly the target. Unlike two triangles there is no diagonal seam
+// where pixel quads are shaded twice. Pixel shaders only receive `SV_POSITION`.
+float4 main(uint vertexId : SV_VertexID) : SV_POSITION {
+    float2 uv = float2((vertexId << 1) & 2, vertexId & 2);
+    return float4(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
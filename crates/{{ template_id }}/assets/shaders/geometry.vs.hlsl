@@ -0,0 +1,107 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+#include "{{ template_id }}::constants.hlsl"
+
+// Same layout as `Bindings` in `main.ps.hlsl`, followed by the scene's buffers, see
+// `DrawMode::SceneGeometry` in `raytracer.rs`
+struct GeometryBindings {
+    UniformAccelerationStructure tlas;
+    UniformByteBuffer constants;
+    UniformByteBuffer userUniforms;
+    UniformByteBuffer camera;
+    UniformByteBuffer instanceColors;
+    UniformByteBuffer indices;
+    UniformByteBuffer normals;
+    // `InstanceMesh` per instance
+    UniformByteBuffer instanceMeshes;
+    UniformByteBuffer positions;
+    // Row-major 3x4 object-to-world transform per instance
+    UniformByteBuffer instanceTransforms;
+};
+
+// World-space position and normal of one of the scene's vertices
+struct SceneVertex {
+    float3 position;
+    float3 normal;
+};
+
+// Vertices beyond the end of the instance's mesh are returned as `false`
+bool loadSceneVertex(GeometryBindings bnd, uint vertexId, uint instanceId, out SceneVertex vertex) {
+    vertex = (SceneVertex)0;
+
+    InstanceMesh mesh = bnd.instanceMeshes.load<InstanceMesh>(instanceId * 16);
+    if (vertexId >= mesh.indexCount) {
+        return false;
+    }
+
+    uint index = mesh.baseVertex + bnd.indices.load<uint>((mesh.firstIndex + vertexId) * 4);
+    float3 position = bnd.positions.load<float3>(index * 12);
+    float3 normal = bnd.normals.load<float3>(index * 12);
+
+    float3x4 transform;
+    [unroll]
+    for (uint row = 0; row < 3; row++) {
+        transform[row] = bnd.instanceTransforms.load<float4>(instanceId * 48 + row * 16);
+    }
+    vertex.position = mul(transform, float4(position, 1.0));
+    // Only exact for transforms without non-uniform scale, which the scene's instances don't have
+    vertex.normal = mul((float3x3)transform, normal);
+    return true;
+}
+
+float4 clipPosition(RaytracerConstants c, CameraConstants camera, float3 world) {
+    if (camera.enabled) {
+        return mul(camera.viewProj, float4(world, 1.0));
+    }
+
+    // Without a camera the raytracer looks straight down +Z through pixel space
+    float2 ndc = world.xy / c.viewportSize * 2.0 - 1.0;
+    return float4(ndc.x, -ndc.y, saturate(world.z / c.rayTMax), 1.0);
+}
+
+// Rasterizes the scene's triangles so that the pixel shader only traces rays for the pixels they
+// cover. The pixel shader writes its own depth, so only the coverage matters here.
+//
+// Every instance is drawn with the vertex count of the largest mesh, the vertices beyond the end
+// of a smaller mesh collapse into degenerate triangles that are never rasterized.
+float4 main(uint vertexId : SV_VertexID, uint instanceId : SV_InstanceID) : SV_POSITION {
+    GeometryBindings bnd = loadBindings<GeometryBindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    CameraConstants camera = bnd.camera.load<CameraConstants>(0);
+
+    SceneVertex vertex;
+    if (!loadSceneVertex(bnd, vertexId, instanceId, vertex)) {
+        return float4(0.0, 0.0, 0.0, 1.0);
+    }
+    return clipPosition(c, camera, vertex.position);
+}
+
+// Read by `mainGBuffer()` in `deferred.ps.hlsl`
+struct GBufferVertex {
+    float4 position : SV_POSITION;
+    float3 worldPosition : WORLD_POSITION;
+    float3 normal : NORMAL;
+    nointerpolation uint instanceId : INSTANCE_ID;
+};
+
+// The G-buffer pass of `DrawMode::Deferred`, drawn like `main()`
+GBufferVertex mainGBuffer(uint vertexId : SV_VertexID, uint instanceId : SV_InstanceID) {
+    GeometryBindings bnd = loadBindings<GeometryBindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    CameraConstants camera = bnd.camera.load<CameraConstants>(0);
+
+    GBufferVertex output = (GBufferVertex)0;
+    output.instanceId = instanceId;
+
+    SceneVertex vertex;
+    if (!loadSceneVertex(bnd, vertexId, instanceId, vertex)) {
+        output.position = float4(0.0, 0.0, 0.0, 1.0);
+        return output;
+    }
+    output.position = clipPosition(c, camera, vertex.position);
+    output.worldPosition = vertex.position;
+    output.normal = vertex.normal;
+    return output;
+}
@@ -0,0 +1,35 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+#include "{{ template_id }}::constants.hlsl"
+
+// Mirrors `InstanceColorConstants` in `instance_colors.rs`
+struct InstanceColorConstants {
+    uint instanceCount;
+    uint seed;
+    uint2 _pad;
+};
+
+struct Bindings {
+    UniformByteBuffer constants;
+    // One `float4` per instance, read by `main.ps.hlsl`
+    UniformRwByteBuffer colors;
+};
+
+// Distinct color per instance index, stable for the same `seed`
+float3 instanceColor(uint index, uint seed) {
+    return hashColor(index ^ seed) * 0.75 + 0.25;
+}
+
+[numthreads(64, 1, 1)]
+void main(uint3 dispatchThreadId : SV_DispatchThreadID) {
+    Bindings bnd = loadBindings<Bindings>();
+
+    InstanceColorConstants c = bnd.constants.load<InstanceColorConstants>(0);
+    uint index = dispatchThreadId.x;
+    if (index >= c.instanceCount) {
+        return;
+    }
+
+    RWByteAddressBuffer colors = bnd.colors.rw();
+    colors.Store<float4>(index * 16, float4(instanceColor(index, c.seed), 1.0));
+}
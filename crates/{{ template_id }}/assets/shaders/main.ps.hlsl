@@ -1,34 +1,339 @@
 #include "breda-render-backend-api::bindless.hlsl"
 
+#include "{{ template_id }}::constants.hlsl"
+
+static const uint FOG_OFF = 0;
+static const uint FOG_LINEAR = 1;
+static const uint FOG_EXPONENTIAL = 2;
+
+static const uint VIEW_MODE_SHADED = 0;
+static const uint VIEW_MODE_WIREFRAME = 1;
+static const uint VIEW_MODE_SHADED_WIREFRAME = 2;
+
+// Mirrors `RtDebugMode` in `raytracer.rs`
+static const uint DEBUG_MODE_OFF = 0;
+static const uint DEBUG_MODE_BARYCENTRICS = 1;
+static const uint DEBUG_MODE_INSTANCE_INDEX = 2;
+static const uint DEBUG_MODE_HIT_DISTANCE = 3;
+static const uint DEBUG_MODE_GEOMETRY_NORMAL = 4;
+static const uint DEBUG_MODE_SHADING_NORMAL = 5;
+
 struct Bindings {
     UniformAccelerationStructure tlas;
+    UniformByteBuffer constants;
+    UniformByteBuffer userUniforms;
+    UniformByteBuffer camera;
+    // One `float4` per instance, written by `instance_colors.cs.hlsl`
+    UniformByteBuffer instanceColors;
+    // `TriangleScene::shading_buffers()` in `scene.rs`
+    UniformByteBuffer indices;
+    // One `float3` per vertex
+    UniformByteBuffer normals;
+    // `InstanceMesh` per instance
+    UniformByteBuffer instanceMeshes;
 };
 
-float4 main(float4 input : SV_POSITION) : SV_Target0 {
-    uint2 launchIndex = input.xy;
+struct ShaderClockBindings {
+    UniformAccelerationStructure tlas;
+    UniformByteBuffer constants;
+    UniformByteBuffer userUniforms;
+    UniformByteBuffer camera;
+    UniformByteBuffer instanceColors;
+    UniformByteBuffer indices;
+    UniformByteBuffer normals;
+    UniformByteBuffer instanceMeshes;
+    // [min, max, sum_lo, sum_hi, count], see `shader_clock.rs`
+    UniformRwByteBuffer stats;
+};
 
-    Bindings bnd = loadBindings<Bindings>();
+// Everything `traceScene()` shades hits with, gathered from either bindings struct
+struct ShadingBuffers {
+    UniformByteBuffer instanceColors;
+    UniformByteBuffer indices;
+    UniformByteBuffer normals;
+    UniformByteBuffer instanceMeshes;
+};
+
+uint readShaderClock() {
+#ifdef __spirv__
+    return uint(vk::ReadClock(vk::SubgroupScope));
+#else
+    return 0;
+#endif
+}
+
+// Returns 1 on triangle edges and 0 inside, anti-aliased over roughly `width` pixels
+float wireframeEdge(float2 barycentrics, float width) {
+    float3 b = float3(1.0 - barycentrics.x - barycentrics.y, barycentrics);
+    float3 edge = smoothstep(0.0, fwidth(b) * width, b);
+    return 1.0 - min(edge.x, min(edge.y, edge.z));
+}
+
+// Orthographic camera looking along `direction`, framing the scene bounds
+RayDesc framedOrthographicRay(RaytracerConstants c, float3 direction, float2 pixelCenter) {
+    float3 forward = normalize(direction);
+    float3 up = abs(forward.y) < 0.99 ? float3(0, 1, 0) : float3(1, 0, 0);
+    float3 right = normalize(cross(up, forward));
+    up = cross(forward, right);
+
+    float2 ndc = pixelCenter / c.viewportSize * 2.0 - 1.0;
+    ndc.x *= c.viewportSize.x / c.viewportSize.y;
+
+    RayDesc ray;
+    ray.Origin = c.sceneCenter - forward * c.sceneRadius * 2.0 + (right * ndc.x + up * ndc.y) * c.sceneRadius;
+    ray.TMin = 0.0;
+    ray.TMax = c.sceneRadius * 4.0;
+    ray.Direction = forward;
+    return ray;
+}
+
+// Perspective ray through `pixelCenter`, unprojected with the orbit camera's matrix
+RayDesc cameraRay(RaytracerConstants c, CameraConstants camera, float2 pixelCenter) {
+    float2 ndc = pixelCenter / c.viewportSize * 2.0 - 1.0;
+    ndc.y = -ndc.y;
+    float4 farPoint = mul(camera.invViewProj, float4(ndc, 1.0, 1.0));
+
+    RayDesc ray;
+    ray.Origin = camera.position;
+    ray.TMin = c.rayTMin;
+    ray.TMax = c.rayTMax;
+    ray.Direction = normalize(farPoint.xyz / farPoint.w - camera.position);
+    return ray;
+}
 
+// Blends towards the fog color with distance, `distance` is infinite for misses
+float3 applyFog(float3 color, float distance, RaytracerConstants c) {
+    float fog = 0.0;
+    if (c.fogMode == FOG_LINEAR) {
+        fog = saturate((distance - c.fogStart) / (c.fogEnd - c.fogStart));
+    } else if (c.fogMode == FOG_EXPONENTIAL) {
+        fog = 1.0 - exp(-c.fogDensity * distance);
+    }
+    return lerp(color, c.fogColor, fog);
+}
+
+// World-space vertex normal interpolated across the hit triangle, flipped to face the ray
+float3 interpolatedNormal(ShadingBuffers buffers, uint instanceIndex, uint primitiveIndex, float2 barycentrics, float3x4 worldToObject, float3 rayDirection) {
+    InstanceMesh mesh = buffers.instanceMeshes.load<InstanceMesh>(instanceIndex * 16);
+    float3 weights = float3(1.0 - barycentrics.x - barycentrics.y, barycentrics);
+
+    float3 normal = 0.0;
+    [unroll]
+    for (uint corner = 0; corner < 3; corner++) {
+        uint index = mesh.baseVertex + buffers.indices.load<uint>((mesh.firstIndex + primitiveIndex * 3 + corner) * 4);
+        normal += buffers.normals.load<float3>(index * 12) * weights[corner];
+    }
+
+    // Multiplying from the left applies the transpose of the world-to-object matrix, which is the
+    // inverse transpose normals transform with
+    normal = normalize(mul(normal, (float3x3)worldToObject));
+    return dot(normal, rayDirection) > 0.0 ? -normal : normal;
+}
+
+// Raw hit data for `RtDebugMode`, without fog, wireframe or color grading
+float3 debugVisualization(uint mode, float2 barycentrics, uint instanceIndex, float t, RayDesc ray, float3 normal, float3 shadingNormal) {
+    switch (mode) {
+    case DEBUG_MODE_BARYCENTRICS:
+        return float3(1.0 - barycentrics.x - barycentrics.y, barycentrics);
+    case DEBUG_MODE_INSTANCE_INDEX:
+        return hashColor(instanceIndex);
+    case DEBUG_MODE_HIT_DISTANCE:
+        // Near the start of the ray interval is white, its end is black
+        return 1.0 - saturate((t - ray.TMin) / (ray.TMax - ray.TMin));
+    case DEBUG_MODE_SHADING_NORMAL:
+        return shadingNormal * 0.5 + 0.5;
+    case DEBUG_MODE_GEOMETRY_NORMAL:
+    default:
+        return normal * 0.5 + 0.5;
+    }
+}
+
+bool insideRenderRegion(RaytracerConstants c, uint2 launchIndex) {
+    uint2 offset = launchIndex - c.renderRegion.xy;
+    return all(launchIndex >= c.renderRegion.xy) && all(offset < c.renderRegion.zw);
+}
+
+// Also returns the depth of the hit in `depth`: projected through the camera when it generated the
+// ray, the hit distance relative to `TMax` for the orthographic views, and 1 for misses.
+// `showBackground` is set for misses that should leave the background pass' gradient visible.
+float3 traceScene(RaytracingAccelerationStructure tlas, RaytracerConstants c, UserUniforms u, CameraConstants camera, ShadingBuffers buffers, uint2 launchIndex, out float depth, out bool showBackground) {
     float2 pixelCenter = launchIndex + 0.5f;
 
     float3 wsPos = float3(pixelCenter, -1);
 
     RayDesc ray;
     ray.Origin = wsPos;
-    ray.TMin = 0.1;
-    ray.TMax = 1000.0;
+    ray.TMin = c.rayTMin;
+    ray.TMax = c.rayTMax;
     ray.Direction = float3(0, 0, 1);
 
+    if (c.viewFromLight) {
+        // "Shadow camera" showing the scene as seen by the light
+        ray = framedOrthographicRay(c, c.lightDirection, pixelCenter);
+    } else if (c.useViewDirection) {
+        ray = framedOrthographicRay(c, c.viewDirection, pixelCenter);
+    }
+
+    bool cameraView = camera.enabled && !c.viewFromLight && !c.useViewDirection;
+    if (cameraView) {
+        ray = cameraRay(c, camera, pixelCenter);
+    }
+
     float3 T = 0.0f;
 
-    RayQuery<RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH | RAY_FLAG_FORCE_OPAQUE> q;
-    q.TraceRayInline(bnd.tlas.topLevelTemporary(), 0, 0xff, ray);
-    q.Proceed(); // No looping needed thanks to ACCEPT_FIRST_HIT
+    // Lighting needs the closest hit for its normal, the light view and fog for its distance. Only
+    // the wireframe view makes do with any hit.
+    bool needsClosestHit = c.viewMode != VIEW_MODE_WIREFRAME || c.viewFromLight || c.fogMode != FOG_OFF;
+    uint rayFlags = needsClosestHit ? RAY_FLAG_NONE : RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH;
+
+    RayQuery<RAY_FLAG_NONE> q;
+    q.TraceRayInline(tlas, rayFlags, 0xff, ray);
+    // Only geometry without the opaque flag (`--geometry-flags` in `scene.rs`) produces
+    // candidates. There is no alpha testing, so every candidate is accepted: the image is the same
+    // but traversal has to return to the shader for every hit.
+    while (q.Proceed()) {
+        if (q.CandidateType() == CANDIDATE_NON_OPAQUE_TRIANGLE) {
+            q.CommitNonOpaqueTriangleHit();
+        }
+    }
+
+    bool hit = q.CommittedStatus() == COMMITTED_TRIANGLE_HIT;
+    // The light view shows misses as infinitely far away, and fog covers them completely
+    showBackground = !hit && !c.viewFromLight && c.fogMode == FOG_OFF;
+
+    depth = 1.0;
+    if (hit && cameraView) {
+        float3 position = ray.Origin + ray.Direction * q.CommittedRayT();
+        float4 clip = mul(camera.viewProj, float4(position, 1.0));
+        depth = clip.z / clip.w;
+    } else if (hit) {
+        depth = saturate(q.CommittedRayT() / ray.TMax);
+    }
+
+    float3 shadingNormal = 0.0;
+    if (hit) {
+        shadingNormal = interpolatedNormal(buffers, q.CommittedInstanceIndex(), q.CommittedPrimitiveIndex(), q.CommittedTriangleBarycentrics(), q.CommittedWorldToObject3x4(), ray.Direction);
+    }
+
+    if (c.viewFromLight) {
+        // Closer to the light is brighter, like a visualized shadow map
+        T = hit ? 1.0 - saturate((q.CommittedRayT() - c.sceneRadius) / (2.0 * c.sceneRadius)) : 0.0;
+    } else if (hit) {
+        // Inline raytracing has no shader binding table, branch on the instance's offset instead
+        T = materialAlbedo(q.CommittedInstanceContributionToHitGroupIndex(), buffers.instanceColors, q.CommittedInstanceID());
+        T = lambert(T, shadingNormal, c.lightDirection);
+    }
+
+    if (!c.viewFromLight && c.fogMode != FOG_OFF) {
+        T = applyFog(T, hit ? q.CommittedRayT() : asfloat(0x7f800000), c);
+    }
+
+    // Evaluated outside of the branch so the barycentric derivatives stay valid
+    float edge = hit ? wireframeEdge(q.CommittedTriangleBarycentrics(), c.wireframeWidth) : 0.0;
+
+    if (c.debugMode != DEBUG_MODE_OFF) {
+        // Faceted normal from the derivatives of the hit position, flipped to face the ray. Unlike
+        // `shadingNormal` it shows the triangles as they are traced.
+        float3 position = ray.Origin + ray.Direction * (hit ? q.CommittedRayT() : ray.TMax);
+        float3 normal = normalize(cross(ddy(position), ddx(position)));
+        normal = dot(normal, ray.Direction) > 0.0 ? -normal : normal;
+
+        // Misses are black so they stand out from every mode's output
+        showBackground = false;
+        return hit ? debugVisualization(c.debugMode, q.CommittedTriangleBarycentrics(), q.CommittedInstanceIndex(), q.CommittedRayT(), ray, normal, shadingNormal) : 0.0;
+    }
+
+    if (c.viewMode == VIEW_MODE_WIREFRAME) {
+        T = edge;
+    } else if (c.viewMode == VIEW_MODE_SHADED_WIREFRAME) {
+        T = lerp(T, 1.0, edge * c.wireframeOpacity);
+    }
+
+    return applyColorGrading(T * u.tint * u.brightness, c);
+}
+
+// Shared by the shaded, wireframe and scene geometry pipelines. The wireframe view mode is only
+// drawn by the wireframe pipeline, when it is missing the application falls back to the shaded
+// pipeline which shades it. The scene geometry pipeline honors every view mode.
+float4 tracePixel(float4 input, bool wireframePipeline, bool allViewModes, out float depth) {
+    uint2 launchIndex = input.xy;
+
+    Bindings bnd = loadBindings<Bindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    if (wireframePipeline) {
+        c.viewMode = VIEW_MODE_WIREFRAME;
+    } else if (!allViewModes && c.viewMode == VIEW_MODE_WIREFRAME) {
+        c.viewMode = VIEW_MODE_SHADED;
+    }
+    UserUniforms u = bnd.userUniforms.load<UserUniforms>(0);
+    CameraConstants camera = bnd.camera.load<CameraConstants>(0);
+
+    if (!insideRenderRegion(c, launchIndex)) {
+        depth = 1.0;
+        return float4(0, 0, 0, 1);
+    }
+
+    ShadingBuffers shading = { bnd.instanceColors, bnd.indices, bnd.normals, bnd.instanceMeshes };
+    bool showBackground;
+    float3 T = traceScene(bnd.tlas.topLevelTemporary(), c, u, camera, shading, launchIndex, depth, showBackground);
+    if (showBackground) {
+        discard;
+    }
+
+    return float4(T, 1.0f);
+}
+
+// `{{ template_id }}-raytracer`
+float4 main(float4 input : SV_POSITION, out float depth : SV_Depth) : SV_Target0 {
+    return tracePixel(input, false, false, depth);
+}
+
+// `{{ template_id }}-wireframe`
+float4 mainWireframe(float4 input : SV_POSITION, out float depth : SV_Depth) : SV_Target0 {
+    return tracePixel(input, true, false, depth);
+}
+
+// `{{ template_id }}-raytracer-geometry`, drawn with the scene's triangles from `geometry.vs.hlsl`
+float4 mainGeometry(float4 input : SV_POSITION, out float depth : SV_Depth) : SV_Target0 {
+    return tracePixel(input, false, true, depth);
+}
+
+// `{{ template_id }}-raytracer-shader-clock`, honors every view mode
+float4 mainShaderClock(float4 input : SV_POSITION, out float depth : SV_Depth) : SV_Target0 {
+    uint2 launchIndex = input.xy;
+
+    ShaderClockBindings bnd = loadBindings<ShaderClockBindings>();
+
+    RaytracerConstants c = bnd.constants.load<RaytracerConstants>(0);
+    UserUniforms u = bnd.userUniforms.load<UserUniforms>(0);
+    CameraConstants camera = bnd.camera.load<CameraConstants>(0);
+
+    if (!insideRenderRegion(c, launchIndex)) {
+        depth = 1.0;
+        return float4(0, 0, 0, 1);
+    }
+
+    ShadingBuffers shading = { bnd.instanceColors, bnd.indices, bnd.normals, bnd.instanceMeshes };
+    uint start = readShaderClock();
+    bool showBackground;
+    float3 T = traceScene(bnd.tlas.topLevelTemporary(), c, u, camera, shading, launchIndex, depth, showBackground);
+    uint cycles = readShaderClock() - start;
+
+    RWByteAddressBuffer stats = bnd.stats.rw();
+    stats.InterlockedMin(0, cycles);
+    stats.InterlockedMax(4, cycles);
+
+    // Emulate a 64-bit sum by carrying into the high word when the low word wraps
+    uint previousLo;
+    stats.InterlockedAdd(8, cycles, previousLo);
+    if (previousLo + cycles < previousLo) {
+        stats.InterlockedAdd(12, 1);
+    }
+    stats.InterlockedAdd(16, 1);
 
-    if (q.CommittedStatus() == COMMITTED_TRIANGLE_HIT) {
-        T += float3(1, 0, 1);
-    } else {
-        T += float3(0, 1, 0);
+    if (showBackground) {
+        discard;
     }
 
     return float4(T, 1.0f);
@@ -0,0 +1,59 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+struct Bindings {
+    // Multisampled depth target of the main pass, see `MsaaTarget::resolve_depth()` in `msaa.rs`
+    UniformTexture2DMS depth;
+};
+
+static const uint RESOLVE_MIN = 0;
+static const uint RESOLVE_MAX = 1;
+static const uint RESOLVE_SAMPLE0 = 2;
+
+// Combines the depth samples of the pixel as `mode` says, see `DepthResolve` in `msaa.rs`
+float resolveDepth(float4 position, uint mode) {
+    Bindings bnd = loadBindings<Bindings>();
+
+    Texture2DMS<float> depth = bnd.depth.ms<float>();
+    uint width, height, samples;
+    depth.GetDimensions(width, height, samples);
+
+    int2 pixel = int2(position.xy);
+    float resolved = depth.Load(pixel, 0);
+    if (mode == RESOLVE_SAMPLE0) {
+        return resolved;
+    }
+    for (uint i = 1; i < samples; i++) {
+        float value = depth.Load(pixel, i);
+        resolved = mode == RESOLVE_MIN ? min(resolved, value) : max(resolved, value);
+    }
+    return resolved;
+}
+
+// `{{ template_id }}-msaa-depth-resolve-min`
+float mainMin(float4 position : SV_POSITION) : SV_Target0 {
+    return resolveDepth(position, RESOLVE_MIN);
+}
+
+// `{{ template_id }}-msaa-depth-resolve-max`
+float mainMax(float4 position : SV_POSITION) : SV_Target0 {
+    return resolveDepth(position, RESOLVE_MAX);
+}
+
+// `{{ template_id }}-msaa-depth-resolve-sample0`
+float mainSample0(float4 position : SV_POSITION) : SV_Target0 {
+    return resolveDepth(position, RESOLVE_SAMPLE0);
+}
+
+struct ViewBindings {
+    // Written by one of the resolves above
+    UniformTexture2D resolved;
+};
+
+// `{{ template_id }}-resolved-depth-view`, for `--show-resolved-depth`. The projected depth packs
+// most of the scene close to 1, the root spreads it out so that distant surfaces stay visible.
+float4 mainView(float4 position : SV_POSITION) : SV_Target0 {
+    ViewBindings bnd = loadBindings<ViewBindings>();
+
+    float depth = bnd.resolved.load<float>(uint2(position.xy));
+    return float4((float3)pow(saturate(1.0 - depth), 0.25), 1.0);
+}
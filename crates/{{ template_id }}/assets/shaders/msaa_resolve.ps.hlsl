@@ -0,0 +1,37 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+struct Bindings {
+    // Multisampled color target of the main pass, see `MsaaTarget` in `msaa.rs`
+    UniformTexture2DMS input;
+};
+
+// Weight of a sample in the resolve, lower the brighter the sample is. Clamped for the negative
+// components of scRGB.
+float resolveWeight(float3 color) {
+    return 1.0 / (1.0 + max(max(color.r, max(color.g, color.b)), 0.0));
+}
+
+// Averages the samples of `input` weighted by `resolveWeight()`, for `--custom-resolve`. A plain
+// average lets a single sample that is much brighter than the display's range dominate an edge
+// pixel, which flickers as fireflies when the camera moves. Samples within the display's range
+// are weighted nearly equally, so this only differs from the hardware resolve on HDR content.
+float4 main(float4 position : SV_POSITION) : SV_Target0 {
+    Bindings bnd = loadBindings<Bindings>();
+
+    Texture2DMS<float4> input = bnd.input.ms<float4>();
+    uint width, height, samples;
+    input.GetDimensions(width, height, samples);
+
+    int2 pixel = int2(position.xy);
+    float3 color = 0.0;
+    float alpha = 0.0;
+    float weights = 0.0;
+    for (uint i = 0; i < samples; i++) {
+        float4 s = input.Load(pixel, i);
+        float weight = resolveWeight(s.rgb);
+        color += s.rgb * weight;
+        alpha += s.a;
+        weights += weight;
+    }
+    return float4(color / weights, alpha / samples);
+}
@@ -0,0 +1,33 @@
+#include "breda-render-backend-api::bindless.hlsl"
+
+// Mirrors `UpscaleConstants` in `pixel_perfect.rs`
+struct UpscaleConstants {
+    int2 offset;
+    uint2 baseSize;
+    uint scale;
+    uint3 _pad;
+};
+
+struct Bindings {
+    UniformTexture2D source;
+    UniformByteBuffer constants;
+};
+
+// Nearest-neighbor integer upscale of `source`, black outside of it
+float4 main(float4 input : SV_POSITION) : SV_Target0 {
+    Bindings bnd = loadBindings<Bindings>();
+
+    UpscaleConstants c = bnd.constants.load<UpscaleConstants>(0);
+
+    int2 pixel = int2(input.xy) - c.offset;
+    if (any(pixel < 0)) {
+        return float4(0, 0, 0, 1);
+    }
+
+    uint2 sourcePixel = uint2(pixel) / c.scale;
+    if (any(sourcePixel >= c.baseSize)) {
+        return float4(0, 0, 0, 1);
+    }
+
+    return bnd.source.load<float4>(sourcePixel);
+}
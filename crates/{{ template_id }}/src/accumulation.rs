@@ -0,0 +1,220 @@
+use anyhow::Result;
+use breda::{
+    egui,
+    render_graph::{RasterPass, RenderGraph, RenderGraphTexture},
+    renderer::{Device, Fence, Format, LoadOp, StoreOp, TextureCreateDesc},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+use clap::Args;
+
+use crate::{
+    fence_timeout::FenceTimeout, frame_ring::FrameRing, fullscreen::FullscreenPass,
+    raytracer::RaytracerSettings, rng::Rng,
+};
+
+pub const DEFAULT_ACCUMULATION_SAMPLES: u32 = 64;
+/// Past 2^24 samples the weight of another one is below the precision of the history's 32-bit
+/// floats, so it would no longer change the image. Up to here the sample count converts to `f32`
+/// exactly, so every weight is the correctly rounded `1 / n`, and the sample counter and the
+/// Halton index stay far from wrapping.
+pub const MAX_ACCUMULATION_SAMPLES: u32 = 1 << 24;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct AccumulationOpts {
+    /// Start with anti-aliasing accumulation enabled, averaging up to this many jittered frames
+    /// while the camera is static, at most 2^24
+    #[clap(long, value_name = "SAMPLES")]
+    pub accumulate: Option<u32>,
+}
+
+/// Mirrors `AccumulateConstants` in `accumulate.ps.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct AccumulateConstants {
+    /// Of the current frame, `1 / n` for the `n`th sample and `0` once converged
+    weight: f32,
+    _pad: [u32; 3],
+}
+
+/// Element `index` of the Halton sequence in `base`, in `0..1`
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Anti-aliases stills by jittering the camera's projection along a Halton sequence every frame
+/// and averaging the frames into a history texture, until [`Self::max_samples`] are blended. Every
+/// restart shifts the sequence by a random offset, so that the sample positions aren't the same.
+///
+/// Any camera movement, resize or change to the [`RaytracerSettings`] starts over. The history
+/// lives in the render graph's persistent store, so it belongs to the device rendered with.
+pub struct Accumulator {
+    pub enabled: bool,
+    pub max_samples: u32,
+    /// Frames blended into the history so far
+    samples: u32,
+    size: [u32; 2],
+    /// Added to every Halton sample, modulo 1, drawn again on every restart
+    rotation: [f32; 2],
+    /// What the history was rendered with
+    settings: Option<RaytracerSettings>,
+    /// Created by the first [`Self::record()`] on the device rendered with
+    constants: Option<FrameRing<AccumulateConstants>>,
+    fence_timeout: FenceTimeout,
+}
+
+impl Accumulator {
+    /// Of the targets created by [`Self::create_input()`]
+    pub const INPUT_FORMAT: Format = Format::R16g16b16a16Sfloat;
+
+    pub fn new(opts: &AccumulationOpts, fence_timeout: FenceTimeout) -> Self {
+        Self {
+            enabled: opts.accumulate.is_some(),
+            max_samples: opts
+                .accumulate
+                .unwrap_or(DEFAULT_ACCUMULATION_SAMPLES)
+                .clamp(1, MAX_ACCUMULATION_SAMPLES),
+            samples: 0,
+            size: [0, 0],
+            rotation: [0.0; 2],
+            settings: None,
+            constants: None,
+            fence_timeout,
+        }
+    }
+
+    /// Drops the history, the next frame is the first sample again
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    /// Call when switching devices. Drops the history along with the buffers of the old one.
+    pub fn device_changed(&mut self) {
+        self.reset();
+        self.constants = None;
+    }
+
+    pub fn is_converged(&self) -> bool {
+        self.samples >= self.max_samples
+    }
+
+    /// Call once per frame before recording the main pass into a target of `size`. Starts over
+    /// when `camera_moved` or when `size` or `settings` changed, and returns the jitter to set
+    /// on [`crate::camera::Camera::jitter`] for this frame.
+    pub fn begin_frame(
+        &mut self,
+        size: [u32; 2],
+        settings: &RaytracerSettings,
+        camera_moved: bool,
+        rng: &mut Rng,
+    ) -> [f32; 2] {
+        if camera_moved || size != self.size || self.settings.as_ref() != Some(settings) {
+            self.reset();
+            self.size = size;
+            self.settings = Some(settings.clone());
+        }
+        if !self.enabled || self.is_converged() {
+            return [0.0; 2];
+        }
+
+        if self.samples == 0 {
+            self.rotation = [rng.next_f32(), rng.next_f32()];
+        }
+
+        // Every offset stays within its pixel
+        let index = self.samples + 1;
+        let offset = std::array::from_fn::<_, 2, _>(|i| {
+            (halton(index, [2, 3][i]) + self.rotation[i]).fract() - 0.5
+        });
+        std::array::from_fn(|i| offset[i] * 2.0 / size[i].max(1) as f32)
+    }
+
+    /// The target to record this frame's main pass into, at the size given to
+    /// [`Self::begin_frame()`]. Float, so nothing is lost to quantization before blending.
+    pub fn create_input(&self, render_graph: &mut RenderGraph) -> RenderGraphTexture {
+        render_graph.create_texture(
+            "accumulation input",
+            &TextureCreateDesc::render_target_2d(self.size, Self::INPUT_FORMAT),
+        )
+    }
+
+    /// Blends `input`, as created by [`Self::create_input()`], into the history and writes the
+    /// result to the whole of `target`
+    pub fn record(
+        &mut self,
+        device: &dyn Device,
+        render_graph: &mut RenderGraph,
+        input: &RenderGraphTexture,
+        target: &RenderGraphTexture,
+        shader_db: &AssetsShaderDatabase,
+    ) -> Result<()> {
+        let weight = if self.is_converged() {
+            0.0
+        } else {
+            self.samples += 1;
+            1.0 / self.samples as f32
+        };
+        let fence_timeout = self.fence_timeout;
+        let constants = self.constants.get_or_insert_with(|| {
+            FrameRing::new(device, "accumulate constants", 1, fence_timeout)
+        });
+        let constants_rg = render_graph.import_buffer(constants.write(&[AccumulateConstants {
+            weight,
+            _pad: [0; 3],
+        }])?);
+
+        // Recreated by the persistent store when the size changes, `begin_frame()` has
+        // started over by then
+        let history = render_graph.persistent_texture(
+            "accumulation history",
+            &TextureCreateDesc::storage_2d(self.size, Format::R32g32b32a32Sfloat),
+        );
+
+        FullscreenPass::draw(
+            RasterPass::new("Accumulate", render_graph)
+                .render_target(target, LoadOp::Discard, StoreOp::Store)
+                .read_texture(input)
+                .rw_texture(&history)
+                .read_buffer(&constants_rg),
+            &shader_db.get_pipeline("{{ template_id }}-accumulate"),
+        );
+        Ok(())
+    }
+
+    /// Hands over the fence of the frame's submission, call once per frame
+    pub fn end_frame(&mut self, fence: Fence) {
+        if let Some(constants) = &mut self.constants {
+            constants.advance(fence);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        if ui.checkbox(&mut self.enabled, "Accumulate").changed() {
+            self.reset();
+        }
+        ui.add_enabled(
+            self.enabled,
+            egui::Slider::new(&mut self.max_samples, 1..=MAX_ACCUMULATION_SAMPLES)
+                .logarithmic(true)
+                .text("Samples"),
+        );
+        if self.enabled {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} / {} samples",
+                    self.samples.min(self.max_samples),
+                    self.max_samples
+                ));
+                if ui.button("Restart").clicked() {
+                    self.reset();
+                }
+            });
+        }
+    }
+}
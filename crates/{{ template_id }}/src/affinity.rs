@@ -0,0 +1,40 @@
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct AffinityOpts {
+    /// Pin the render loop thread to this CPU core to reduce frame time variance when
+    /// benchmarking. Ignored with a warning on platforms without thread affinity support
+    #[clap(long, value_name = "CORE")]
+    pub pin_core: Option<usize>,
+}
+
+impl AffinityOpts {
+    /// Pins the calling thread to the requested core, if any
+    pub fn apply_to_current_thread(&self) {
+        let Some(core) = self.pin_core else {
+            return;
+        };
+
+        let Some(core_ids) = core_affinity::get_core_ids() else {
+            log::warn!("--pin-core {core}: thread affinity is not supported on this platform");
+            return;
+        };
+
+        let Some(&core_id) = core_ids.iter().find(|core_id| core_id.id == core) else {
+            // The cores in the process' affinity mask, which skips offline and isolated ones
+            let available = core_ids
+                .iter()
+                .map(|core_id| core_id.id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            log::warn!("--pin-core {core}: no such core, available cores are {available}");
+            return;
+        };
+
+        if core_affinity::set_for_current(core_id) {
+            log::info!("Pinned render loop thread to core {core}");
+        } else {
+            log::warn!("--pin-core {core}: failed to set thread affinity");
+        }
+    }
+}
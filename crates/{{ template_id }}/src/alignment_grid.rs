@@ -0,0 +1,62 @@
+use breda::egui::{self, Align2, Color32, FontId, Id, LayerId, Order, Pos2, Rect, Stroke};
+use clap::Args;
+
+/// Grid lines are drawn at every multiple of this fraction of the screen
+const GRID_STEP: f32 = 0.1;
+
+/// Broadcast action-safe and title-safe areas, as fractions of the screen
+const SAFE_AREAS: [f32; 2] = [0.9, 0.8];
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct AlignmentGridOpts {
+    /// Draw a grid with percentage markers, a center crosshair and safe areas over the final
+    /// image
+    #[clap(long)]
+    pub alignment_grid: bool,
+}
+
+/// Paints the grid over everything else on the screen, including other egui windows
+pub fn draw_alignment_grid(ctx: &egui::Context) {
+    let screen = ctx.screen_rect();
+    let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("alignment grid")));
+
+    let grid = Stroke::new(1.0, Color32::from_white_alpha(64));
+    let safe_area = Stroke::new(1.0, Color32::from_rgba_unmultiplied(255, 200, 0, 160));
+    let crosshair = Stroke::new(1.5, Color32::from_rgb(255, 64, 64));
+    let font = FontId::monospace(10.0);
+
+    let steps = (1.0 / GRID_STEP).round() as u32;
+    for step in 1..steps {
+        let t = step as f32 * GRID_STEP;
+        let x = screen.left() + screen.width() * t;
+        let y = screen.top() + screen.height() * t;
+        painter.vline(x, screen.y_range(), grid);
+        painter.hline(screen.x_range(), y, grid);
+
+        let label = format!("{}%", step * 100 / steps);
+        painter.text(
+            Pos2::new(x + 2.0, screen.top() + 2.0),
+            Align2::LEFT_TOP,
+            &label,
+            font.clone(),
+            grid.color,
+        );
+        painter.text(
+            Pos2::new(screen.left() + 2.0, y + 2.0),
+            Align2::LEFT_TOP,
+            &label,
+            font.clone(),
+            grid.color,
+        );
+    }
+
+    for fraction in SAFE_AREAS {
+        let rect = Rect::from_center_size(screen.center(), screen.size() * fraction);
+        painter.rect_stroke(rect, 0.0, safe_area);
+    }
+
+    let center = screen.center();
+    let arm = screen.size().min_elem() * 0.03;
+    painter.hline(center.x - arm..=center.x + arm, center.y, crosshair);
+    painter.vline(center.x, center.y - arm..=center.y + arm, crosshair);
+}
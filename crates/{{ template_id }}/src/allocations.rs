@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use clap::Args;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Sum of every allocation logged so far. Frees aren't tracked, so this only grows, also when a
+/// resource is replaced on a resize or scene edit.
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct AllocationLogOpts {
+    /// Log every long-lived GPU buffer, texture and acceleration structure the app creates, with
+    /// its size, memory type and the cumulative total allocated so far
+    #[clap(long)]
+    pub log_allocations: bool,
+}
+
+impl AllocationLogOpts {
+    /// Enables or disables [`log_allocation()`] for the whole process
+    pub fn install(&self) {
+        ENABLED.store(self.log_allocations, Ordering::Relaxed);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ResourceKind {
+    Buffer,
+    Texture,
+    AccelerationStructure,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum MemoryKind {
+    DeviceLocal,
+    /// CPU-writable, GPU-readable
+    HostVisible,
+    /// GPU-writable, CPU-readable
+    Readback,
+}
+
+/// Records an allocation made by the app when `--log-allocations` is set.
+///
+/// Transient resources such as staging buffers aren't recorded. The per-frame constant buffers of a
+/// [`crate::frame_ring::FrameRing`] are, they are allocated once and reused every frame. The logged
+/// total is cumulative, not the app's current footprint: resources that are freed or replaced
+/// still count.
+pub fn log_allocation(kind: ResourceKind, name: &str, size: usize, memory: MemoryKind) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let total = ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+    log::info!(
+        "Allocated {kind:?} `{name}`: {size} bytes ({memory:?}), {:.2} MiB allocated so far",
+        total as f64 / (1024.0 * 1024.0)
+    );
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use breda::{render_graph::RenderGraphPersistentStore, renderer::Device};
+
+use crate::{
+    fence_timeout::FenceTimeout,
+    gpu_timer::GpuTimer,
+    msaa::supported_samples,
+    pixel_perfect::PixelPerfectTarget,
+    raytracer::MainPassBuffers,
+    scene::{SceneDescription, SceneOpts, TriangleScene},
+    shader_clock::ShaderClock,
+    viewport_preview::ViewportPreview,
+};
+
+/// The options [`reinit_device()`] needs, cloned out of the command line before the render loop
+#[derive(Clone, Debug)]
+pub struct DeviceResourceOpts {
+    pub scene: SceneOpts,
+    pub fence_timeout: FenceTimeout,
+    pub shader_clock: bool,
+    pub gpu_timer: bool,
+    pub pixel_perfect: Option<[u32; 2]>,
+    pub viewport_preview: Option<[u32; 2]>,
+    /// As requested by --msaa, before clamping to what the device supports
+    pub msaa: u32,
+}
+
+/// Everything the main loop renders with that lives on a specific device
+pub struct AppResources {
+    pub device: Arc<dyn Device>,
+    pub render_graph_persistent_store: RenderGraphPersistentStore,
+    pub scene: TriangleScene,
+    pub egui_renderer: breda::egui::Renderer,
+    pub shader_clock: Option<ShaderClock>,
+    pub gpu_timer: Option<GpuTimer>,
+    pub pixel_perfect: Option<PixelPerfectTarget>,
+    pub main_pass_buffers: MainPassBuffers,
+    /// Registered with [`Self::egui_renderer`]
+    pub viewport_preview: Option<ViewportPreview>,
+    /// MSAA sample count of the main pass, clamped to what [`Self::device`] supports
+    pub msaa_samples: u32,
+}
+
+/// Creates the per-device resources of the main loop on `device`, including uploading the scene
+/// and building its acceleration structures. The streaming system must already be initialized for
+/// `device`.
+pub fn reinit_device(device: &Arc<dyn Device>, opts: &DeviceResourceOpts) -> Result<AppResources> {
+    let scene = TriangleScene::new(
+        device.as_ref(),
+        &opts.scene,
+        opts.fence_timeout,
+        &SceneDescription::from_opts(&opts.scene)?,
+    )?;
+    let mut egui_renderer = breda::egui::Renderer::new(device.as_ref());
+    let viewport_preview = opts.viewport_preview.map(|size| {
+        ViewportPreview::new(
+            device.as_ref(),
+            &mut egui_renderer,
+            size,
+            opts.fence_timeout,
+        )
+    });
+
+    Ok(AppResources {
+        device: device.clone(),
+        render_graph_persistent_store: RenderGraphPersistentStore::new(device.as_ref()),
+        scene,
+        egui_renderer,
+        shader_clock: opts
+            .shader_clock
+            .then(|| ShaderClock::new(device.as_ref(), opts.fence_timeout))
+            .flatten(),
+        gpu_timer: opts
+            .gpu_timer
+            .then(|| GpuTimer::new(device.as_ref(), opts.fence_timeout))
+            .flatten(),
+        pixel_perfect: opts
+            .pixel_perfect
+            .map(|size| PixelPerfectTarget::new(device.as_ref(), size, opts.fence_timeout)),
+        main_pass_buffers: MainPassBuffers::new(device.as_ref(), opts.fence_timeout),
+        viewport_preview,
+        msaa_samples: supported_samples(opts.msaa, device.as_ref()),
+    })
+}
@@ -0,0 +1,1193 @@
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use breda::{
+    egui::WindowSettings,
+    input::InputProvider,
+    render_graph::{ExecutedRenderGraphSignalFenceWith, RenderGraph, RenderGraphPersistentStore},
+    render_loop::v2::event::{EventReceiver, EventSender, RenderLoopEvent},
+    renderer::{Device, PresentStatus, QueueSubmitInfo},
+    shader_database::AssetsShaderDatabase,
+    shader_database_api::ShaderDatabaseAsset,
+    streaming_system::{Handle, StreamingSystem},
+};
+
+use crate::{
+    accumulation::{AccumulationOpts, Accumulator},
+    alignment_grid,
+    app_resources::{reinit_device, AppResources, DeviceResourceOpts},
+    camera::{Camera, CameraInputOpts},
+    camera_bookmarks::CameraBookmarks,
+    color_mode::ColorMode,
+    crash_report,
+    device_loss::{recover_from_device_loss, wait_unless_lost},
+    dropped_frames::{DroppedFrameDetector, DroppedFrameOpts},
+    error::AppError,
+    frame_pacer::{FramePacer, FramePacerOpts},
+    gpu_timer::GpuTimer,
+    gpu_watchdog::GpuWatchdog,
+    init_streaming_system,
+    input_recording::{InputRecordingOpts, InputTape},
+    latency::{LatencyOpts, LatencyTest},
+    msaa::{draw_depth_view, DepthResolve, MsaaTarget},
+    pixel_perfect::PixelPerfectTarget,
+    pixel_probe::{PixelProbe, PixelProbeOpts},
+    present_mode::{PresentMode, PresentModeSelector},
+    raytracer::{record_main_pass, MainPassBuffers, RaytracerOpts, RaytracerSettings},
+    render_graph_debug::{GraphDump, RenderGraphDebugOpts},
+    replay::{ReplayBuffer, ReplayOpts},
+    resize_stress::{ResizeStress, ResizeStressOpts, ResizeStressStep},
+    rng::{Rng, SeedOpts},
+    scene::{InstanceEdit, TriangleScene},
+    screenshot::Screenshot,
+    settings::{OpenWindows, Settings},
+    shader_clock::ShaderClock,
+    shader_reload::ShaderReload,
+    startup::{AsyncWorkspaceBuild, LoadingPhase, StartupOpts},
+    ui_scale::{UiScale, UiScaleOpts},
+    user_uniforms::UserUniformFile,
+    viewport_preview::ViewportPreview,
+    CommandlineOpts,
+};
+
+/// The options [`AppState::new()`] needs, cloned out of the command line before the render loop
+#[derive(Clone, Debug)]
+pub struct AppStateOpts {
+    pub title: String,
+    pub color_mode: ColorMode,
+    pub present_mode: PresentMode,
+    pub device_resources: DeviceResourceOpts,
+    pub raytracer: RaytracerOpts,
+    pub startup: StartupOpts,
+    pub replay: ReplayOpts,
+    pub pixel_probe: PixelProbeOpts,
+    pub frame_pacer: FramePacerOpts,
+    pub dropped_frames: DroppedFrameOpts,
+    pub latency: LatencyOpts,
+    pub alignment_grid: bool,
+    pub custom_msaa_resolve: bool,
+    pub msaa_depth_resolve: Option<DepthResolve>,
+    pub show_resolved_depth: bool,
+    pub ui_scale: UiScaleOpts,
+    pub render_graph_debug: RenderGraphDebugOpts,
+    pub user_uniforms: Option<PathBuf>,
+    pub resize_stress: ResizeStressOpts,
+    pub accumulation: AccumulationOpts,
+    pub seed: SeedOpts,
+    pub input_recording: InputRecordingOpts,
+    pub camera_input: CameraInputOpts,
+}
+
+impl AppStateOpts {
+    pub fn new(opts: &CommandlineOpts) -> Self {
+        Self {
+            title: opts.title().to_string(),
+            color_mode: opts.color_mode.color_mode,
+            present_mode: opts.present_mode.present_mode,
+            device_resources: DeviceResourceOpts {
+                scene: opts.scene.clone(),
+                fence_timeout: opts.fence_timeout.timeout(),
+                shader_clock: opts.shader_clock.shader_clock,
+                gpu_timer: opts.gpu_timer.gpu_timer,
+                pixel_perfect: opts.pixel_perfect.pixel_perfect,
+                viewport_preview: opts.viewport_preview.viewport_preview,
+                msaa: opts.msaa.samples(),
+            },
+            raytracer: opts.raytracer.clone(),
+            startup: opts.startup.clone(),
+            replay: opts.replay.clone(),
+            pixel_probe: opts.pixel_probe.clone(),
+            frame_pacer: opts.frame_pacer.clone(),
+            dropped_frames: opts.dropped_frames.clone(),
+            latency: opts.latency.clone(),
+            alignment_grid: opts.alignment_grid.alignment_grid,
+            custom_msaa_resolve: opts.msaa.custom_resolve,
+            msaa_depth_resolve: opts.msaa.depth_resolve,
+            show_resolved_depth: opts.msaa.show_resolved_depth,
+            ui_scale: opts.ui_scale.clone(),
+            render_graph_debug: opts.render_graph_debug.clone(),
+            user_uniforms: opts.user_uniforms.uniforms.clone(),
+            resize_stress: opts.resize_stress.clone(),
+            accumulation: opts.accumulation.clone(),
+            seed: opts.seed.clone(),
+            input_recording: opts.input_recording.clone(),
+            camera_input: opts.camera_input.clone(),
+        }
+    }
+}
+
+/// Whether the render loop keeps going after [`AppState::render_frame()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameOutcome {
+    Continue,
+    Exit,
+}
+
+/// Everything the interactive render loop keeps across frames
+pub struct AppState {
+    streaming_system: Arc<StreamingSystem>,
+    /// Devices that can be switched between in the UI
+    rt_devices: Vec<Arc<dyn Device>>,
+    device_resource_opts: DeviceResourceOpts,
+    shader_db_timeout: Duration,
+    color_mode: ColorMode,
+    workspace_build: AsyncWorkspaceBuild,
+
+    // Replaced as a whole when a different device is picked in the UI, see [`AppResources`]
+    device: Arc<dyn Device>,
+    /// Only [`None`] while a frame's render graph has taken it
+    render_graph_persistent_store: Option<RenderGraphPersistentStore>,
+    scene: TriangleScene,
+    egui_renderer: breda::egui::Renderer,
+    shader_clock: Option<ShaderClock>,
+    gpu_timer: Option<GpuTimer>,
+    pixel_perfect: Option<PixelPerfectTarget>,
+    main_pass_buffers: MainPassBuffers,
+    viewport_preview: Option<ViewportPreview>,
+    msaa_samples: u32,
+
+    shader_handle: Option<Handle<ShaderDatabaseAsset>>,
+    /// Since when the shader database is being streamed in without one to render with yet, at
+    /// startup or after switching devices. Fails the frame after [`Self::shader_db_timeout`].
+    shader_db_wait_start: Option<Instant>,
+    shader_reload: Option<ShaderReload>,
+    requested_device: Option<Arc<dyn Device>>,
+    device_error: Option<String>,
+
+    camera: Camera,
+    camera_bookmarks: CameraBookmarks,
+    accumulator: Accumulator,
+    /// Clicked in the UI, applied at the start of the next frame before anything imports the TLAS
+    instance_edit: Option<InstanceEdit>,
+    /// Why the last [`Self::instance_edit`] was rejected, shown next to the instance buttons
+    instance_error: Option<String>,
+    /// Whose material is shown in the "Current GPU" window
+    selected_instance: usize,
+    /// Seeded by --seed, all randomness of the render loop is drawn from here
+    rng: Rng,
+    spin_start: Instant,
+    input_processor: InputProvider,
+    /// Dropped once a --replay has played back, live input is used from then on
+    input_tape: Option<InputTape>,
+    /// Toggled with F1, for screenshots without the UI
+    ui_visible: bool,
+    /// Persisted through [`Settings`], like the parts of [`Self::raytracer_settings`] it covers
+    windows: OpenWindows,
+    raytracer_settings: RaytracerSettings,
+    user_uniform_file: Option<UserUniformFile>,
+
+    replay: Option<ReplayBuffer>,
+    pixel_probe: Option<PixelProbe>,
+    screenshot: Screenshot,
+    frame_pacer: FramePacer,
+    gpu_watchdog: GpuWatchdog,
+    present_mode: PresentModeSelector,
+    dropped_frames: DroppedFrameDetector,
+    latency_test: Option<LatencyTest>,
+    alignment_grid: bool,
+    custom_msaa_resolve: bool,
+    msaa_depth_resolve: Option<DepthResolve>,
+    show_resolved_depth: bool,
+    ui_scale: UiScale,
+    render_graph_debug: RenderGraphDebugOpts,
+    /// Requested with F8 and by --dump-graph for the first frame
+    graph_dump: GraphDump,
+    resize_stress: Option<ResizeStress>,
+}
+
+impl AppState {
+    /// Builds the workspace for `device` (in the background with `--async-startup`) and creates
+    /// everything the render loop draws with. `rt_devices` are offered in the device picker.
+    pub fn new(
+        device: &Arc<dyn Device>,
+        streaming_system: &Arc<StreamingSystem>,
+        rt_devices: Vec<Arc<dyn Device>>,
+        opts: &AppStateOpts,
+    ) -> Result<Self> {
+        let shader_db_timeout = opts.startup.shader_db_timeout();
+        let fence_timeout = opts.device_resources.fence_timeout;
+
+        let workspace_build = if opts.startup.async_startup {
+            AsyncWorkspaceBuild::spawn(streaming_system, device.as_ref())
+        } else {
+            init_streaming_system(streaming_system, device, shader_db_timeout)?;
+            AsyncWorkspaceBuild::finished()
+        };
+
+        let AppResources {
+            device,
+            render_graph_persistent_store,
+            scene,
+            egui_renderer,
+            shader_clock,
+            gpu_timer,
+            pixel_perfect,
+            main_pass_buffers,
+            viewport_preview,
+            msaa_samples,
+        } = reinit_device(device, &opts.device_resources)?;
+
+        let mut rng = opts.seed.rng();
+        let mut raytracer_settings = RaytracerSettings::new(&opts.raytracer, &mut rng);
+        let settings = Settings::load();
+        settings.apply(&mut raytracer_settings, &opts.raytracer);
+
+        let user_uniform_file = match &opts.user_uniforms {
+            Some(path) => {
+                let (file, values) = UserUniformFile::load(path)?;
+                raytracer_settings.user_uniforms = values;
+                Some(file)
+            }
+            None => None,
+        };
+
+        let mut camera = Camera::framing(&scene.bounds);
+        camera.input_filter.settings = settings.camera_input.with_opts(&opts.camera_input);
+
+        Ok(Self {
+            streaming_system: streaming_system.clone(),
+            rt_devices,
+            device_resource_opts: opts.device_resources.clone(),
+            shader_db_timeout,
+            color_mode: opts.color_mode,
+            workspace_build,
+
+            device,
+            render_graph_persistent_store: Some(render_graph_persistent_store),
+            camera,
+            camera_bookmarks: settings.camera_bookmarks.clone(),
+            scene,
+            egui_renderer,
+            shader_clock,
+            gpu_timer,
+            pixel_perfect,
+            main_pass_buffers,
+            viewport_preview,
+            msaa_samples,
+
+            shader_handle: None,
+            shader_db_wait_start: None,
+            shader_reload: ShaderReload::new(),
+            requested_device: None,
+            device_error: None,
+
+            accumulator: Accumulator::new(&opts.accumulation, fence_timeout),
+            instance_edit: None,
+            instance_error: None,
+            selected_instance: 0,
+            rng,
+            spin_start: Instant::now(),
+            input_processor: InputProvider::default(),
+            input_tape: InputTape::new(&opts.input_recording)?,
+            ui_visible: true,
+            windows: settings.windows,
+            raytracer_settings,
+            user_uniform_file,
+
+            replay: ReplayBuffer::new(&opts.replay, opts.color_mode, fence_timeout),
+            pixel_probe: PixelProbe::new(&opts.pixel_probe, fence_timeout),
+            screenshot: Screenshot::new(&opts.title, opts.color_mode, fence_timeout),
+            frame_pacer: FramePacer::new(&opts.frame_pacer),
+            gpu_watchdog: GpuWatchdog::new(fence_timeout),
+            present_mode: PresentModeSelector::new(opts.present_mode),
+            dropped_frames: DroppedFrameDetector::new(&opts.dropped_frames),
+            latency_test: LatencyTest::new(&opts.latency, fence_timeout),
+            alignment_grid: opts.alignment_grid,
+            custom_msaa_resolve: opts.custom_msaa_resolve,
+            msaa_depth_resolve: opts.msaa_depth_resolve,
+            show_resolved_depth: opts.show_resolved_depth,
+            ui_scale: UiScale::new(&opts.ui_scale, settings.ui_scale),
+            render_graph_debug: opts.render_graph_debug.clone(),
+            graph_dump: GraphDump::new(&opts.render_graph_debug),
+            resize_stress: ResizeStress::new(&opts.resize_stress),
+        })
+    }
+
+    /// The device that is currently rendered with, events must be received for it
+    pub fn device(&self) -> &Arc<dyn Device> {
+        &self.device
+    }
+
+    pub fn dropped_frames(&self) -> &DroppedFrameDetector {
+        &self.dropped_frames
+    }
+
+    /// The UI state to write on exit
+    pub fn settings(&self) -> Settings {
+        Settings::new(
+            &self.windows,
+            &self.raytracer_settings,
+            &self.ui_scale,
+            &self.camera,
+            &self.camera_bookmarks,
+        )
+    }
+
+    /// Renders and presents the frame for `event`, including the UI and switching devices when one
+    /// was picked
+    pub fn render_frame(
+        &mut self,
+        event: RenderLoopEvent,
+        event_receiver: &mut EventReceiver,
+        event_sender: &EventSender,
+    ) -> Result<FrameOutcome> {
+        let Self {
+            streaming_system,
+            rt_devices,
+            device_resource_opts,
+            shader_db_timeout,
+            color_mode,
+            workspace_build,
+            device: current_device,
+            render_graph_persistent_store,
+            scene,
+            egui_renderer,
+            shader_clock,
+            gpu_timer,
+            pixel_perfect,
+            main_pass_buffers,
+            viewport_preview,
+            msaa_samples,
+            shader_handle,
+            shader_db_wait_start,
+            shader_reload,
+            requested_device,
+            device_error,
+            camera,
+            camera_bookmarks,
+            accumulator,
+            instance_edit,
+            instance_error,
+            selected_instance,
+            rng,
+            spin_start,
+            input_processor,
+            input_tape,
+            ui_visible,
+            windows,
+            raytracer_settings,
+            user_uniform_file,
+            replay,
+            pixel_probe,
+            screenshot,
+            frame_pacer,
+            gpu_watchdog,
+            present_mode,
+            dropped_frames,
+            latency_test,
+            alignment_grid,
+            custom_msaa_resolve,
+            msaa_depth_resolve,
+            show_resolved_depth,
+            ui_scale,
+            render_graph_debug,
+            graph_dump,
+            resize_stress,
+        } = self;
+        crash_report::begin_frame();
+
+        let device_arc = current_device.clone();
+        let device = device_arc.as_ref();
+        let queue = device.get_gfx_queue();
+
+        let RenderLoopEvent {
+            swapchain,
+            swapchain_sync,
+            present_index,
+            mut state,
+        } = event;
+
+        // Swapchain recreation is driven by the render loop: before handing out an event it
+        // recreates the swapchain if the window was resized or moved to a display with a
+        // different DPI, or if the last present reported it as out of date or suboptimal
+        // through `with_status()` at the end of the frame. A resize can still land between
+        // recreation and acquiring, handing out an image of the old size that would be
+        // stretched when presented. Such frames are dropped and reported as out of date,
+        // so the next event comes with a swapchain matching the window again. The image is
+        // acquired already and only goes back to the swapchain by presenting it, so it is
+        // presented cleared instead of rendered.
+        let present_image = swapchain.present_image(present_index);
+        if present_image.size() != swapchain.size() {
+            log::debug!(
+                "Skipping frame: present image is {:?} but the swapchain is {:?}",
+                present_image.size(),
+                swapchain.size()
+            );
+            let mut cmd = queue.lock().create_command_buffer();
+            let mut render_graph = RenderGraph::new(
+                render_graph_persistent_store
+                    .take()
+                    .expect("Put back by the previous frame"),
+            );
+            let present_image_rg = render_graph.import_texture(&present_image);
+            render_graph.clear_texture(&present_image_rg, [0.0, 0.0, 0.0, 1.0]);
+            let (executed_rg, signal_fence) = render_graph
+                .compile(&[&present_image_rg], None)
+                .execute(device, &mut cmd);
+            *render_graph_persistent_store = Some(executed_rg.release_store());
+            queue.lock().submit(
+                vec![cmd],
+                QueueSubmitInfo::swapchain_only_sync(swapchain_sync)
+                    .with_render_graph_signal_fence(signal_fence),
+            );
+            if matches!(
+                swapchain.present(&queue, present_index, swapchain_sync),
+                PresentStatus::Lost
+            ) {
+                return Err(AppError::SwapchainLost.into());
+            }
+            event_receiver.with_status(PresentStatus::OutOfDate);
+            return Ok(FrameOutcome::Continue);
+        }
+        // Checked every frame, moving the window to another display recreates the swapchain
+        color_mode.check_swapchain_format(present_image.format())?;
+        present_mode.frame(
+            swapchain.supported_present_modes(),
+            swapchain.present_mode(),
+            event_sender,
+        );
+
+        if let Some(resize_stress) = resize_stress.as_mut() {
+            match resize_stress.frame(device, swapchain.size())? {
+                ResizeStressStep::Continue => {}
+                ResizeStressStep::Resize(size) => event_sender.request_window_size(size),
+                ResizeStressStep::Finished => return Ok(FrameOutcome::Exit),
+            }
+        }
+
+        // Before the events reach the camera or egui, so that a replay drives both
+        if input_tape
+            .as_mut()
+            .map(|input_tape| input_tape.frame(&mut state))
+            .transpose()?
+            == Some(true)
+        {
+            *input_tape = None;
+        }
+        let egui = state.apply(input_processor);
+
+        if egui
+            .as_ref()
+            .is_some_and(|ctx| ctx.input(|i| i.key_pressed(breda::egui::Key::F1)))
+        {
+            *ui_visible = !*ui_visible;
+        }
+        // egui still runs while hidden, but nothing is drawn into it
+        let overlay = egui.as_ref().filter(|_| *ui_visible);
+
+        // A letter key, so not while it's being typed into a text field
+        if egui.as_ref().is_some_and(|ctx| {
+            !ctx.wants_keyboard_input() && ctx.input(|i| i.key_pressed(breda::egui::Key::F))
+        }) {
+            camera.auto_fit_camera(&scene.bounds);
+        }
+        if let Some(ctx) = &egui {
+            camera_bookmarks.handle_keys(ctx, camera);
+        }
+
+        // Dragging a window or slider shouldn't also move the camera
+        let camera_moved = camera.handle_input(
+            input_processor,
+            overlay.is_some_and(|ctx| ctx.wants_pointer_input()),
+            event_sender,
+        );
+
+        let latency_flash = latency_test.as_mut().is_some_and(|latency_test| {
+            let triggered = egui
+                .as_ref()
+                .is_some_and(|ctx| ctx.input(|i| i.key_pressed(breda::egui::Key::Space)));
+            latency_test.begin_frame(triggered)
+        });
+
+        if egui
+            .as_ref()
+            .is_some_and(|ctx| ctx.input(|i| i.key_pressed(breda::egui::Key::F12)))
+        {
+            screenshot.request();
+        }
+        if egui
+            .as_ref()
+            .is_some_and(|ctx| ctx.input(|i| i.key_pressed(breda::egui::Key::F8)))
+        {
+            graph_dump.request();
+        }
+
+        // Physical pixel under the cursor, for the pixel probe
+        let cursor_pixel = egui.as_ref().and_then(|ctx| {
+            let pixels_per_point = ctx.pixels_per_point();
+            ctx.input(|i| i.pointer.hover_pos())
+                .filter(|pos| pos.x >= 0.0 && pos.y >= 0.0)
+                .map(|pos| [pos.x, pos.y].map(|c| (c * pixels_per_point) as u32))
+        });
+
+        if let Some(values) = user_uniform_file.as_mut().and_then(UserUniformFile::poll) {
+            raytracer_settings.user_uniforms = values;
+        }
+
+        streaming_system.update();
+        let mut streaming_context = streaming_system.create_context(&device_arc);
+
+        // Edited shaders are rebuilt in the background like --async-startup does
+        if let Some(shader_reload) = shader_reload.as_mut() {
+            if !workspace_build.is_running() && shader_reload.poll_changes() {
+                log::info!("Shader sources changed, rebuilding the workspace");
+                *workspace_build = AsyncWorkspaceBuild::spawn(streaming_system, device);
+            }
+        }
+
+        let workspace_ready = match workspace_build.poll() {
+            Ok(ready) => ready,
+            // A broken edit only fails the reload, the last good shaders stay in use
+            Err(e) if shader_handle.is_some() => {
+                log::error!("Failed to rebuild shaders: {e:?}");
+                if let Some(shader_reload) = shader_reload.as_mut() {
+                    shader_reload.failed(&e);
+                }
+                true
+            }
+            Err(e) => return Err(e),
+        };
+
+        // With --async-startup the shader database doesn't exist until the workspace
+        // build finishes, and is then only available once it has been streamed in. The
+        // previous version is kept until a rebuilt one replaces it.
+        if workspace_ready {
+            let shader_db = streaming_context
+                .load_versioned::<_, ShaderDatabaseAsset>(&streaming_system.get_shader_db_cid()?);
+            if let Some(handle) = shader_db.downgrade().upgrade() {
+                *shader_handle = Some(handle);
+                if let Some(shader_reload) = shader_reload.as_mut() {
+                    shader_reload.loaded();
+                }
+            }
+        }
+        let shader_db = shader_handle.as_ref().and_then(|shader_handle| {
+            streaming_system
+                .assets
+                .borrow::<AssetsShaderDatabase>(shader_handle)
+        });
+
+        if shader_db.is_some() {
+            dropped_frames.frame();
+        } else {
+            dropped_frames.skip();
+        }
+
+        wait_unless_lost(device, || gpu_watchdog.begin_frame())?;
+        let mut cmd = queue.lock().create_command_buffer();
+
+        // A rejected edit leaves the scene as it was, so the frame goes on without it
+        if let Some(edit) = instance_edit.take() {
+            match scene.edit_instances(device, &mut cmd, edit) {
+                Ok(()) => {
+                    *instance_error = None;
+                    accumulator.reset();
+                }
+                Err(e) => {
+                    log::error!("Failed to apply {edit:?}: {e:?}");
+                    *instance_error = Some(format!("{e:#}"));
+                }
+            }
+        }
+        // Refit before the render graph imports the TLAS, the first update replaces it
+        if let Some(speed) = device_resource_opts.scene.spin {
+            scene.spin(device, &mut cmd, spin_start.elapsed().as_secs_f32() * speed)?;
+        }
+
+        let mut render_graph = RenderGraph::new(
+            render_graph_persistent_store
+                .take()
+                .expect("Put back by the previous frame"),
+        );
+        render_graph_debug.configure(&mut render_graph);
+
+        let present_image_rg = render_graph.import_texture(&present_image);
+
+        if let Some(shader_db) = &shader_db {
+            let (scene_target_rg, scene_target_size) = match pixel_perfect.as_ref() {
+                Some(pixel_perfect) => (
+                    pixel_perfect.import(&mut render_graph),
+                    pixel_perfect.size(),
+                ),
+                None => (present_image_rg.clone(), swapchain.size()),
+            };
+            camera.set_viewport_size(scene_target_size);
+
+            // A spinning scene changes every frame, so it never converges
+            let scene_changed = device_resource_opts.scene.spin.is_some();
+            camera.jitter = accumulator.begin_frame(
+                scene_target_size,
+                raytracer_settings,
+                camera_moved || scene_changed,
+                rng,
+            );
+            let (main_target_rg, main_target_format) = if accumulator.enabled {
+                (
+                    accumulator.create_input(&mut render_graph),
+                    Accumulator::INPUT_FORMAT,
+                )
+            } else {
+                let format = pixel_perfect
+                    .as_ref()
+                    .map_or_else(|| present_image.format(), PixelPerfectTarget::format);
+                (scene_target_rg.clone(), format)
+            };
+            let msaa = MsaaTarget::create(
+                &mut render_graph,
+                scene_target_size,
+                main_target_format,
+                *msaa_samples,
+                *custom_msaa_resolve,
+                *msaa_depth_resolve,
+            );
+
+            let scene_depth_rg = record_main_pass(
+                &mut render_graph,
+                &main_target_rg,
+                scene_target_size,
+                scene,
+                shader_db,
+                raytracer_settings,
+                Some(&*camera),
+                shader_clock
+                    .as_mut()
+                    .map(ShaderClock::begin_frame)
+                    .transpose()?,
+                msaa.as_ref(),
+                main_pass_buffers,
+            )?;
+            // The template's only reader of the resolved depth, passes that depend on depth like
+            // SSAO would take it from here
+            if let Some(scene_depth_rg) = scene_depth_rg.filter(|_| *show_resolved_depth) {
+                draw_depth_view(
+                    &mut render_graph,
+                    &scene_depth_rg,
+                    &main_target_rg,
+                    shader_db,
+                );
+            }
+
+            if accumulator.enabled {
+                accumulator.record(
+                    device,
+                    &mut render_graph,
+                    &main_target_rg,
+                    &scene_target_rg,
+                    shader_db,
+                )?;
+            }
+
+            if let Some(viewport_preview) = viewport_preview.as_mut() {
+                let (preview_rg, preview_size, preview_buffers) =
+                    viewport_preview.prepare(device, egui_renderer, &mut render_graph);
+                let mut preview_camera = camera.clone();
+                preview_camera.set_viewport_size(preview_size);
+                preview_camera.jitter = [0.0; 2];
+                record_main_pass(
+                    &mut render_graph,
+                    &preview_rg,
+                    preview_size,
+                    scene,
+                    shader_db,
+                    raytracer_settings,
+                    Some(&preview_camera),
+                    None,
+                    None,
+                    preview_buffers,
+                )?;
+            }
+
+            if let Some(pixel_perfect) = pixel_perfect.as_mut() {
+                pixel_perfect.record_upscale(
+                    &mut render_graph,
+                    &scene_target_rg,
+                    &present_image_rg,
+                    swapchain.size(),
+                    shader_db,
+                )?;
+            }
+        } else {
+            render_graph.clear_texture(&present_image_rg, [0.0, 0.0, 0.0, 1.0]);
+        }
+
+        if let Some(ctx) = &egui {
+            ui_scale.apply(ctx);
+        }
+
+        let loading_phase = match (&shader_db, workspace_ready) {
+            (Some(_), _) => None,
+            (None, false) => Some(LoadingPhase::BuildingWorkspace),
+            (None, true) => {
+                let wait_start = shader_db_wait_start.get_or_insert_with(Instant::now);
+                if wait_start.elapsed() > *shader_db_timeout {
+                    return Err(AppError::StreamingTimeout {
+                        timeout: *shader_db_timeout,
+                    }
+                    .into());
+                }
+                Some(LoadingPhase::LoadingShaders)
+            }
+        };
+        // Shown with the UI hidden too, nothing else is drawn until the shaders are there
+        if let (Some(ctx), Some(loading_phase)) = (egui.as_ref(), loading_phase) {
+            ctx.window(
+                "Loading",
+                &mut true,
+                &WindowSettings::from_window_size([250.0, 60.0]),
+                |ui| loading_phase.ui(ui),
+            );
+        }
+
+        if let (Some(ctx), Some(shader_reload)) = (overlay, shader_reload.as_ref()) {
+            if shader_reload.is_pending() || shader_reload.error().is_some() {
+                ctx.window(
+                    "Shader reload",
+                    &mut true,
+                    &WindowSettings::from_window_size([250.0, 60.0]),
+                    |ui| {
+                        if shader_reload.is_pending() {
+                            ui.horizontal(|ui| {
+                                ui.spinner();
+                                ui.label("Reloading shaders...");
+                            });
+                        }
+                        if let Some(error) = shader_reload.error() {
+                            ui.colored_label(breda::egui::Color32::RED, error);
+                        }
+                    },
+                );
+            }
+        }
+
+        if let Some(ctx) = overlay {
+            ctx.window(
+                "Windows",
+                &mut true,
+                &WindowSettings::from_window_size([180.0, 280.0]),
+                |ui| windows.ui(ui),
+            );
+
+            ctx.window(
+                "Current GPU",
+                &mut windows.current_gpu,
+                &WindowSettings::from_window_size([500.0, 140.0]),
+                |ui| {
+                    let driver_info = device.driver_info();
+                    ui.label(format!(
+                        "{} {}",
+                        driver_info.vendor, driver_info.device_name
+                    ));
+                    ui.label(format!(
+                        "{} `{}` @ {}",
+                        driver_info.driver_id,
+                        driver_info.driver_name,
+                        driver_info.version.map_or_else(
+                            || "Invalid driver version".to_string(),
+                            |v| v.to_string()
+                        )
+                    ));
+                    if !driver_info.driver_extra_info.is_empty() {
+                        ui.label(driver_info.driver_extra_info);
+                    }
+                    if let Some(shader_compiler_version) = driver_info.shader_compiler_version {
+                        ui.small(format!("Driver shader compiler: {shader_compiler_version}"));
+                    }
+                    ui.label(format!("Color mode: {}", color_mode.name()));
+                    if color_mode.is_hdr() {
+                        if let Some(max_luminance) = swapchain.max_luminance() {
+                            ui.label(format!("Display max luminance: {max_luminance:.0} nits"));
+                        }
+                    }
+                    ui.horizontal(|ui| {
+                        ui.label(format!(
+                            "TLAS instances: {} / {}",
+                            scene.instance_count, scene.instance_limit
+                        ));
+                        let can_add = scene.instance_count < scene.instance_limit;
+                        if ui
+                            .add_enabled(can_add, breda::egui::Button::new("Add instance"))
+                            .clicked()
+                        {
+                            *instance_edit = Some(InstanceEdit::Add);
+                        }
+                        let can_remove = scene.instance_count > 0;
+                        if ui
+                            .add_enabled(can_remove, breda::egui::Button::new("Remove instance"))
+                            .clicked()
+                        {
+                            *instance_edit = Some(InstanceEdit::Remove);
+                        }
+                    });
+                    if let Some(edit) = scene.material_ui(ui, selected_instance) {
+                        *instance_edit = Some(edit);
+                    }
+                    if let Some(error) = instance_error.as_ref() {
+                        ui.colored_label(breda::egui::Color32::RED, error);
+                    }
+                    if scene.instance_count < scene.requested_instance_count {
+                        ui.colored_label(
+                            breda::egui::Color32::YELLOW,
+                            format!(
+                                "{} instances dropped",
+                                scene.requested_instance_count - scene.instance_count
+                            ),
+                        );
+                    }
+
+                    // Switching rebuilds the workspace, which must not race with
+                    // --async-startup or a shader reload building it
+                    if rt_devices.len() > 1 && !workspace_build.is_running() {
+                        breda::egui::ComboBox::from_label("Device")
+                            .selected_text(device.driver_info().device_name)
+                            .show_ui(ui, |ui| {
+                                for candidate in rt_devices.iter() {
+                                    let selected = Arc::ptr_eq(candidate, &device_arc);
+                                    let name = candidate.driver_info().device_name;
+                                    if ui.selectable_label(selected, name).clicked() && !selected {
+                                        *requested_device = Some(candidate.clone());
+                                    }
+                                }
+                            });
+                    }
+                    if let Some(error) = device_error.as_ref() {
+                        ui.colored_label(breda::egui::Color32::RED, error);
+                    }
+
+                    ui.separator();
+                    ui_scale.ui(ui);
+                },
+            );
+
+            ctx.window(
+                "Raytracer",
+                &mut windows.raytracer,
+                &WindowSettings::from_window_size([300.0, 120.0]),
+                |ui| raytracer_settings.ui(ui),
+            );
+
+            ctx.window(
+                "Camera",
+                &mut windows.camera,
+                &WindowSettings::from_window_size([300.0, 100.0]),
+                |ui| {
+                    camera.ui(ui);
+                    ui.collapsing("Bookmarks", |ui| camera_bookmarks.ui(ui, camera));
+                },
+            );
+
+            ctx.window(
+                "Accumulation",
+                &mut windows.accumulation,
+                &WindowSettings::from_window_size([250.0, 80.0]),
+                |ui| accumulator.ui(ui),
+            );
+
+            if let Some(viewport_preview) = viewport_preview.as_mut() {
+                ctx.window(
+                    "Viewport preview",
+                    &mut windows.viewport_preview,
+                    &WindowSettings::from_window_size([400.0, 300.0]),
+                    |ui| viewport_preview.ui(ui),
+                );
+            }
+
+            if *alignment_grid {
+                alignment_grid::draw_alignment_grid(ctx);
+            }
+
+            if let Some(latency_test) = latency_test.as_ref() {
+                ctx.window(
+                    "Latency",
+                    &mut windows.latency,
+                    &WindowSettings::from_window_size([300.0, 80.0]),
+                    |ui| latency_test.ui(ui),
+                );
+            }
+
+            ctx.window(
+                "Dropped frames",
+                &mut windows.dropped_frames,
+                &WindowSettings::from_window_size([250.0, 180.0]),
+                |ui| dropped_frames.ui(ui),
+            );
+
+            ctx.window(
+                "Memory",
+                &mut windows.memory,
+                &WindowSettings::from_window_size([250.0, 200.0]),
+                |ui| scene.memory_stats().ui(ui, device),
+            );
+
+            ctx.window(
+                "Frame time",
+                &mut windows.frame_time,
+                &WindowSettings::from_window_size([300.0, 190.0]),
+                |ui| {
+                    present_mode.ui(ui);
+                    ui.separator();
+                    frame_pacer.ui(ui);
+                    if let Some(gpu_timer) = gpu_timer.as_ref() {
+                        ui.separator();
+                        ui.strong("GPU");
+                        gpu_timer.ui(ui);
+                    }
+                },
+            );
+
+            if let Some(shader_clock) = shader_clock.as_ref() {
+                ctx.window(
+                    "Shader clock",
+                    &mut windows.shader_clock,
+                    &WindowSettings::from_window_size([300.0, 100.0]),
+                    |ui| match shader_clock.last_stats() {
+                        Some(stats) => {
+                            ui.label(format!("Min: {} cycles", stats.min_cycles));
+                            ui.label(format!("Avg: {:.1} cycles", stats.avg_cycles));
+                            ui.label(format!("Max: {} cycles", stats.max_cycles));
+                        }
+                        None => {
+                            ui.label("Waiting for results...");
+                        }
+                    },
+                );
+            }
+
+            if let Some(pixel_probe) = pixel_probe.as_ref() {
+                ctx.window(
+                    "Pixel probe",
+                    &mut windows.pixel_probe,
+                    &WindowSettings::from_window_size([300.0, 100.0]),
+                    |ui| {
+                        pixel_probe.ui(
+                            ui,
+                            raytracer_settings.pixel_decoder(&scene.bounds, present_image.format()),
+                        )
+                    },
+                );
+            }
+        }
+
+        // Outside of the overlay so F9 and pending saves keep working while it's hidden
+        if let Some(replay) = replay.as_mut() {
+            let mut save = egui
+                .as_ref()
+                .is_some_and(|ctx| ctx.input(|i| i.key_pressed(breda::egui::Key::F9)));
+            if let Some(ctx) = overlay {
+                ctx.window(
+                    "Replay",
+                    &mut windows.replay,
+                    &WindowSettings::from_window_size([300.0, 100.0]),
+                    |ui| {
+                        ui.label(format!(
+                            "{:.1} s buffered, {:.1} MB",
+                            replay.buffered_duration().as_secs_f32(),
+                            replay.buffered_bytes() as f32 / (1024.0 * 1024.0)
+                        ));
+                        ui.add_enabled_ui(!replay.is_saving(), |ui| {
+                            save |= ui.button("Save GIF (F9)").clicked();
+                        });
+                    },
+                );
+            }
+            if save {
+                replay.save();
+            }
+            replay.poll_save();
+        }
+
+        // compile and execute render graph
+        if latency_flash {
+            render_graph.clear_texture(&present_image_rg, [1.0, 1.0, 1.0, 1.0]);
+        }
+
+        let compiled_rg = tracing::trace_span!("render_graph_compile")
+            .in_scope(|| render_graph.compile(&[&present_image_rg], None));
+        let pass_names = compiled_rg.pass_names().map(str::to_owned).collect();
+        graph_dump.write_if_requested(&compiled_rg);
+        if let Some(gpu_timer) = gpu_timer.as_mut() {
+            gpu_timer.begin(&mut cmd)?;
+        }
+        let (executed_rg, signal_fence) = tracing::trace_span!("render_graph_execute")
+            .in_scope(|| compiled_rg.execute(device, &mut cmd));
+        if let Some(gpu_timer) = gpu_timer.as_mut() {
+            gpu_timer.end(&mut cmd);
+        }
+
+        *render_graph_persistent_store = Some(executed_rg.release_store());
+
+        if let Some(pixel_probe) = pixel_probe.as_mut() {
+            pixel_probe.record(device, &mut cmd, &present_image, cursor_pixel)?;
+        }
+
+        // Ended even while hidden, keeping egui's state consistent for when it's shown
+        if let Some(mut ctx) = egui {
+            let (platform_output, render_input) = ctx.end_frame();
+            event_sender.send(platform_output);
+            if *ui_visible {
+                egui_renderer.render(device, &mut cmd, None, &present_image, render_input);
+            }
+        }
+
+        if let Some(replay) = replay.as_mut() {
+            replay.capture(device, &mut cmd, &present_image)?;
+        }
+        screenshot.capture(device, &mut cmd, &present_image)?;
+
+        let fence = queue.lock().submit(
+            vec![cmd],
+            QueueSubmitInfo::swapchain_only_sync(swapchain_sync)
+                .with_render_graph_signal_fence(signal_fence),
+        );
+        gpu_watchdog.submitted(fence.clone(), pass_names);
+        if let Some(latency_test) = latency_test.as_mut() {
+            wait_unless_lost(device, || latency_test.submitted(&fence))?;
+        }
+        if let Some(replay) = replay.as_mut() {
+            replay.end_frame(fence.clone());
+        }
+        if let Some(pixel_probe) = pixel_probe.as_mut() {
+            pixel_probe.end_frame(fence.clone());
+        }
+        wait_unless_lost(device, || screenshot.end_frame(&fence))?;
+        if let Some(gpu_timer) = gpu_timer.as_mut() {
+            gpu_timer.end_frame(fence.clone());
+        }
+        if let Some(shader_clock) = shader_clock.as_mut() {
+            shader_clock.end_frame(fence.clone());
+        }
+        main_pass_buffers.end_frame(fence.clone());
+        scene.end_frame(fence.clone());
+        accumulator.end_frame(fence.clone());
+        if let Some(viewport_preview) = viewport_preview.as_mut() {
+            viewport_preview.end_frame(egui_renderer, fence.clone());
+        }
+        if let Some(pixel_perfect) = pixel_perfect.as_mut() {
+            pixel_perfect.end_frame(fence.clone());
+        }
+        let present_status = swapchain.present(&queue, present_index, swapchain_sync);
+        if let Some(latency_test) = latency_test.as_mut() {
+            latency_test.presented();
+        }
+        // Nothing can be presented to a surface that is gone, unlike an outdated swapchain
+        if matches!(present_status, PresentStatus::Lost) {
+            return Err(AppError::SwapchainLost.into());
+        }
+        if matches!(
+            present_status,
+            PresentStatus::OutOfDate | PresentStatus::Suboptimal
+        ) {
+            log::debug!("Swapchain is {present_status:?}, recreating it for the next frame");
+        }
+        // Hands the status back to the render loop, which recreates the swapchain if needed
+        event_receiver.with_status(present_status);
+
+        // Checked once the frame is submitted and presented, which is where a reset or removed GPU
+        // reports it. Nothing on a lost device finishes, so none of the waits above blocked on
+        // its fences, see `wait_unless_lost()`.
+        let new_resources = if device.is_lost() {
+            *requested_device = None;
+            Some(recover_from_device_loss(
+                &device_arc,
+                rt_devices,
+                streaming_system,
+                *shader_db_timeout,
+                device_resource_opts,
+            )?)
+        } else if let Some(new_device) = requested_device.take() {
+            let name = new_device.driver_info().device_name;
+            // Nothing on the old device may be dropped while its last frame is in flight
+            device_resource_opts
+                .fence_timeout
+                .wait(&fence, "finishing the frame before switching devices")?;
+            match init_streaming_system(streaming_system, &new_device, *shader_db_timeout)
+                .map_err(anyhow::Error::from)
+                .and_then(|()| reinit_device(&new_device, device_resource_opts))
+            {
+                Ok(resources) => {
+                    log::info!("Switched to `{name}`");
+                    Some(resources)
+                }
+                Err(e) => {
+                    log::error!("Failed to switch to `{name}`: {e:?}");
+                    *device_error = Some(format!("Failed to switch to `{name}`: {e:#}"));
+                    // Keep rendering on the current device with its own shaders
+                    init_streaming_system(streaming_system, &device_arc, *shader_db_timeout)?;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Some(resources) = new_resources {
+            *current_device = resources.device;
+            crash_report::set_device(current_device.as_ref());
+            *render_graph_persistent_store = Some(resources.render_graph_persistent_store);
+            *scene = resources.scene;
+            *egui_renderer = resources.egui_renderer;
+            *shader_clock = resources.shader_clock;
+            *gpu_timer = resources.gpu_timer;
+            *pixel_perfect = resources.pixel_perfect;
+            *main_pass_buffers = resources.main_pass_buffers;
+            *viewport_preview = resources.viewport_preview;
+            *msaa_samples = resources.msaa_samples;
+            // The history lived in the old device's persistent store
+            accumulator.device_changed();
+            gpu_watchdog.reset();
+            if let Some(replay) = replay.as_mut() {
+                replay.reset(device)?;
+            }
+            if let Some(pixel_probe) = pixel_probe.as_mut() {
+                pixel_probe.reset(device)?;
+            }
+            // Loaded again for the new device on the next frame
+            *shader_handle = None;
+            *shader_db_wait_start = None;
+            *device_error = None;
+        }
+
+        frame_pacer.end_frame();
+        Ok(FrameOutcome::Continue)
+    }
+}
+
+#[cfg(all(test, not(target_os = "android")))]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::CommandlineOpts;
+
+    /// [`AppState::render_frame()`] needs a swapchain and with it a window, so only construction
+    /// is covered. Run with `cargo test -- --ignored` on a machine with such a GPU.
+    #[test]
+    #[ignore = "needs a GPU with inline raytracing"]
+    fn new_creates_resources_on_a_headless_device() -> Result<()> {
+        let opts = CommandlineOpts::parse_from(["app_state_test"]);
+        let breda = breda::Breda::new("app_state_test", opts.breda.into())?;
+        let rt_devices = breda
+            .devices()
+            .filter(|d| d.capabilities().supports_inline_ray_tracing)
+            .cloned()
+            .collect::<Vec<_>>();
+        let device = rt_devices
+            .first()
+            .cloned()
+            .expect("No device supports inline raytracing");
+
+        let state = AppState::new(
+            &device,
+            &breda.streaming_system(),
+            rt_devices,
+            &AppStateOpts::new(&opts),
+        )?;
+        assert!(Arc::ptr_eq(state.device(), &device));
+        assert!(state.scene.instance_count > 0);
+        assert!(!state.workspace_build.is_running());
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, ensure, Context, Result};
+use breda::{
+    egui,
+    render_graph::{RasterPass, RenderGraph, RenderGraphTexture},
+    renderer::{LoadOp, StoreOp},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+
+use crate::{frame_ring::FrameRing, fullscreen::FullscreenPass};
+
+/// Parses `r,g,b,a` with every component in `0..=1`, for `--clear-color`
+pub fn parse_color(s: &str) -> Result<[f32; 4]> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Expected four comma-separated numbers like `0.1,0.2,0.3,1.0`")?;
+    let color: [f32; 4] = values
+        .try_into()
+        .map_err(|values: Vec<_>| anyhow!("Expected 4 components, got {}", values.len()))?;
+    ensure!(
+        color.iter().all(|c| (0.0..=1.0).contains(c)),
+        "Color components must be between 0 and 1"
+    );
+    Ok(color)
+}
+
+/// What the scene is drawn over, shown wherever the main view's rays miss: a vertical gradient,
+/// or a solid clear color
+#[derive(Clone, Debug, PartialEq)]
+pub struct Background {
+    pub top: [f32; 3],
+    pub bottom: [f32; 3],
+    /// Clear to [`Self::clear_color`] instead of drawing the gradient
+    pub solid: bool,
+    pub clear_color: [f32; 4],
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self {
+            top: [0.1, 0.12, 0.18],
+            bottom: [0.45, 0.5, 0.58],
+            solid: false,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Mirrors `BackgroundConstants` in `background.ps.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct BackgroundConstants {
+    top: [f32; 3],
+    viewport_height: f32,
+    bottom: [f32; 3],
+    _pad: f32,
+}
+
+impl Background {
+    /// A solid background of `clear_color`
+    pub fn from_clear_color(clear_color: [f32; 4]) -> Self {
+        Self {
+            solid: true,
+            clear_color,
+            ..Self::default()
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.solid, "Solid color");
+            ui.add_enabled_ui(self.solid, |ui| {
+                ui.color_edit_button_rgba_unmultiplied(&mut self.clear_color);
+            });
+        });
+        ui.add_enabled_ui(!self.solid, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Top");
+                ui.color_edit_button_rgb(&mut self.top);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Bottom");
+                ui.color_edit_button_rgb(&mut self.bottom);
+            });
+        });
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+    }
+
+    /// Records the gradient or clear into the whole of `target`, the main pass then loads it and
+    /// only overwrites the pixels its rays hit. `samples` is that of `target`.
+    pub fn record(
+        &self,
+        render_graph: &mut RenderGraph,
+        target: &RenderGraphTexture,
+        target_size: [u32; 2],
+        samples: u32,
+        constants: &mut FrameRing<BackgroundConstants>,
+        shader_db: &AssetsShaderDatabase,
+    ) -> Result<()> {
+        if self.solid {
+            render_graph.clear_texture(target, self.clear_color);
+            return Ok(());
+        }
+
+        let constants_rg = render_graph.import_buffer(constants.write(&[BackgroundConstants {
+            top: self.top,
+            viewport_height: target_size[1] as f32,
+            bottom: self.bottom,
+            _pad: 0.0,
+        }])?);
+
+        FullscreenPass::draw(
+            RasterPass::new("Background", render_graph)
+                .sample_count(samples)
+                .render_target(target, LoadOp::Load, StoreOp::Store)
+                .read_buffer(&constants_rg),
+            &shader_db.get_pipeline("{{ template_id }}-background"),
+        );
+        Ok(())
+    }
+}
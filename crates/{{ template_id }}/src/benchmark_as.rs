@@ -0,0 +1,78 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, ensure, Result};
+use breda::renderer::Device;
+use clap::Args;
+
+use crate::{
+    scene::{default_instances, ProcScene, SceneDescription, TriangleScene},
+    CommandlineOpts,
+};
+
+pub const DEFAULT_BENCHMARK_ITERATIONS: u32 = 20;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct BenchmarkAsOpts {
+    /// Build the BLAS of a generated plane with at least this many triangles repeatedly, print
+    /// the GPU build times and throughput and exit
+    #[clap(long, value_name = "TRIANGLES")]
+    pub benchmark_as: Option<usize>,
+    /// Number of timed builds of --benchmark-as
+    #[clap(long, value_name = "N")]
+    pub benchmark_iterations: Option<u32>,
+}
+
+pub fn run_benchmark_as(
+    device: &Arc<dyn Device>,
+    opts: &CommandlineOpts,
+    triangles: usize,
+) -> Result<()> {
+    ensure!(triangles > 0, "--benchmark-as needs at least one triangle");
+    let iterations = opts
+        .benchmark_as
+        .benchmark_iterations
+        .unwrap_or(DEFAULT_BENCHMARK_ITERATIONS)
+        .max(1);
+    let fence_timeout = opts.fence_timeout.timeout();
+
+    // A plane of `n * n` quads has `2 * n * n` triangles
+    let subdivisions = (triangles as f64 / 2.0).sqrt().ceil() as u32;
+    let description = SceneDescription {
+        meshes: vec![ProcScene::Plane.mesh(subdivisions)],
+        instances: default_instances(),
+    };
+    // Building the scene also builds the BLAS once, which warms up the driver
+    let mut scene = TriangleScene::new(device.as_ref(), &opts.scene, fence_timeout, &description)?;
+
+    let times = scene
+        .raytracing
+        .time_blas_builds(device.as_ref(), iterations, fence_timeout)?;
+    let Some(times) = times else {
+        bail!(
+            "--benchmark-as needs timestamp queries, which `{}` does not support",
+            device.driver_info().device_name
+        );
+    };
+
+    let triangle_count = scene.triangle_count;
+    let total = times.iter().sum::<Duration>();
+    let average = total / times.len() as u32;
+    let min = times.iter().min().copied().unwrap_or_default();
+    let max = times.iter().max().copied().unwrap_or_default();
+
+    println!(
+        "BLAS build of {triangle_count} triangles on {}, {iterations} iterations:",
+        device.driver_info().device_name
+    );
+    println!(
+        "    min {:.3} ms, avg {:.3} ms, max {:.3} ms",
+        min.as_secs_f64() * 1000.0,
+        average.as_secs_f64() * 1000.0,
+        max.as_secs_f64() * 1000.0
+    );
+    println!(
+        "    {:.1} M triangles/s at the average",
+        triangle_count as f64 / average.as_secs_f64().max(f64::EPSILON) / 1_000_000.0
+    );
+    Ok(())
+}
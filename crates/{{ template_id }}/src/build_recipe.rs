@@ -0,0 +1,67 @@
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use breda::{
+    renderer::Device,
+    workspace_recipe::{ShaderCompileTarget, WorkspaceBuildRecipe},
+};
+use clap::Args;
+
+static OVERRIDES: OnceLock<BuildRecipeOpts> = OnceLock::new();
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct BuildRecipeOpts {
+    /// Compile shaders for this target instead of the device's preferred one, like `spirv` or
+    /// `dxil`
+    #[clap(long, value_name = "TARGET")]
+    pub compile_target: Option<String>,
+    /// Also build the shaders of this crate in the workspace, can be repeated
+    #[clap(long, value_name = "CRATE")]
+    pub extra_crate: Vec<String>,
+}
+
+impl BuildRecipeOpts {
+    /// Makes [`crate::workspace_build_recipe()`] apply these overrides from now on. Fails when
+    /// `device` doesn't support --compile-target, rather than when the workspace is built.
+    pub fn install(&self, device: &dyn Device) -> Result<()> {
+        if let Some(name) = &self.compile_target {
+            find_compile_target(device, name)?;
+        }
+        // Only the first call has an effect, there is a single command line
+        let _ = OVERRIDES.set(self.clone());
+        Ok(())
+    }
+}
+
+fn find_compile_target(device: &dyn Device, name: &str) -> Result<ShaderCompileTarget> {
+    let supported = device.supported_compile_targets();
+    supported
+        .iter()
+        .find(|target| format!("{target:?}").eq_ignore_ascii_case(name))
+        .cloned()
+        .with_context(|| {
+            format!(
+                "--compile-target {name} is not supported by `{}`, expected one of {:?}",
+                device.driver_info().device_name,
+                supported
+            )
+        })
+}
+
+/// Adds the installed --extra-crate names to `recipe` and replaces its compile targets with
+/// --compile-target. A device picked later that lacks the target keeps its preferred one.
+pub fn apply_overrides(recipe: &mut WorkspaceBuildRecipe, device: &dyn Device) {
+    let Some(opts) = OVERRIDES.get() else {
+        return;
+    };
+
+    recipe
+        .root_crate_names
+        .extend(opts.extra_crate.iter().cloned());
+    if let Some(name) = &opts.compile_target {
+        match find_compile_target(device, name) {
+            Ok(target) => recipe.shader_compile_targets = vec![target],
+            Err(e) => log::warn!("{e:#}, compiling for the preferred target instead"),
+        }
+    }
+}
@@ -0,0 +1,610 @@
+use std::{
+    f32::consts::{FRAC_PI_2, PI},
+    time::Instant,
+};
+
+use breda::{
+    egui,
+    input::{InputProvider, KeyCode, MouseButton},
+    render_loop::v2::event::EventSender,
+};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    math::{cross, dot, mul_mat4, normalize, sub, Mat4},
+    mesh::Aabb,
+};
+
+/// Radians of rotation per pixel of mouse movement
+const ROTATE_SPEED: f32 = 0.005;
+/// Fraction of the distance zoomed per scroll step
+const ZOOM_SPEED: f32 = 0.1;
+/// Multiplies [`FlyCamera::speed`] while shift is held
+const FAST_MULTIPLIER: f32 = 4.0;
+/// Longest frame that movement is integrated over, so a hitch doesn't fling the camera away
+const MAX_MOVE_DT: f32 = 0.1;
+/// Scenes live in the raytracer's Y-down pixel space, see `fit_to_view()` in `scene.rs`
+const UP: [f32; 3] = [0.0, -1.0, 0.0];
+/// Keeps the pitch away from the poles, where the view basis would flip
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+/// Scales the bounding sphere [`Camera::auto_fit_camera()`] fits into the view, leaving a border
+/// around the scene
+const FIT_MARGIN: f32 = 1.1;
+/// Smoothed input that is left over below this is applied at once, so that it doesn't trickle in
+/// over many frames
+const MIN_PENDING_INPUT: f32 = 1e-3;
+
+pub const DEFAULT_CAMERA_DEAD_ZONE: f32 = 0.0;
+/// Short enough that at 60 fps almost 90% of a frame's movement is applied in that same frame
+pub const DEFAULT_CAMERA_SMOOTHING_MS: f32 = 8.0;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct CameraInputOpts {
+    /// Mouse movements of up to this many pixels per frame don't move the camera, and larger ones
+    /// are reduced by it, to hide the jitter of noisy input devices. Replaces the dead-zone
+    /// restored from the last run.
+    #[clap(long, value_name = "PIXELS")]
+    pub camera_dead_zone: Option<f32>,
+    /// Time constant of the exponential smoothing of the mouse movement and scrolling that moves
+    /// the camera, `0` applies them unsmoothed. Replaces the smoothing restored from the last run.
+    #[clap(long, value_name = "MS")]
+    pub camera_smoothing_ms: Option<f32>,
+}
+
+/// The parameters of [`CameraInputFilter`], persisted through [`crate::settings::Settings`]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraInputSettings {
+    /// In pixels per frame, see --camera-dead-zone
+    pub dead_zone: f32,
+    /// Time constant in milliseconds, see --camera-smoothing-ms
+    pub smoothing_ms: f32,
+}
+
+impl Default for CameraInputSettings {
+    fn default() -> Self {
+        Self {
+            dead_zone: DEFAULT_CAMERA_DEAD_ZONE,
+            smoothing_ms: DEFAULT_CAMERA_SMOOTHING_MS,
+        }
+    }
+}
+
+impl CameraInputSettings {
+    /// `self` with what was given on the command line replaced
+    pub fn with_opts(self, opts: &CameraInputOpts) -> Self {
+        Self {
+            dead_zone: opts.camera_dead_zone.unwrap_or(self.dead_zone).max(0.0),
+            smoothing_ms: opts
+                .camera_smoothing_ms
+                .unwrap_or(self.smoothing_ms)
+                .max(0.0),
+        }
+    }
+}
+
+/// Applies a dead-zone to the mouse movement that moves the camera, and smooths it and the
+/// scrolling exponentially.
+///
+/// Every frame's input is added to what is pending, of which a share that grows with the frame
+/// time is applied, so that nothing is lost: the camera ends up where unsmoothed input would have
+/// put it, a few frames later at most. After a pause the first frame's input applies almost
+/// entirely, smoothing only delays continuous movement.
+#[derive(Clone, Debug, Default)]
+pub struct CameraInputFilter {
+    pub settings: CameraInputSettings,
+    mouse: [f32; 2],
+    scroll: f32,
+    last_input: Option<Instant>,
+}
+
+impl CameraInputFilter {
+    /// Returns the mouse and scroll deltas to move the camera by this frame, for the raw deltas
+    /// of this frame
+    fn filter(&mut self, mouse: [f32; 2], scroll: f32) -> ([f32; 2], f32) {
+        let now = Instant::now();
+        let dt = self
+            .last_input
+            .map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last_input = Some(now);
+
+        let [dx, dy] = mouse;
+        let length = (dx * dx + dy * dy).sqrt();
+        if length > self.settings.dead_zone {
+            let scale = (length - self.settings.dead_zone) / length;
+            self.mouse = std::array::from_fn(|i| self.mouse[i] + mouse[i] * scale);
+        }
+        self.scroll += scroll;
+
+        let share = if self.settings.smoothing_ms > 0.0 {
+            1.0 - (-dt * 1000.0 / self.settings.smoothing_ms).exp()
+        } else {
+            1.0
+        };
+        let take = |pending: &mut f32| {
+            let applied = if pending.abs() < MIN_PENDING_INPUT {
+                *pending
+            } else {
+                *pending * share
+            };
+            *pending -= applied;
+            applied
+        };
+        let [x, y] = &mut self.mouse;
+        ([take(x), take(y)], take(&mut self.scroll))
+    }
+
+    /// Drops pending input, like when the camera switches modes
+    fn reset(&mut self) {
+        self.mouse = [0.0; 2];
+        self.scroll = 0.0;
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.settings.dead_zone, 0.0..=10.0)
+                .text("Mouse dead-zone (px)"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.settings.smoothing_ms, 0.0..=100.0).text("Smoothing (ms)"),
+        );
+    }
+}
+
+/// Unit vector for `yaw` around [`UP`] and `pitch` towards it, `+Z` at zero
+fn direction(yaw: f32, pitch: f32) -> [f32; 3] {
+    let (sin_yaw, cos_yaw) = yaw.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+    [cos_pitch * sin_yaw, sin_pitch, cos_pitch * cos_yaw]
+}
+
+/// Right, up and forward vectors of a view looking along `forward`
+fn basis(forward: [f32; 3]) -> [[f32; 3]; 3] {
+    let forward = normalize(forward);
+    let right = normalize(cross(forward, UP));
+    let up = cross(right, forward);
+    [right, up, forward]
+}
+
+/// How [`Camera`] is controlled, switchable in its UI
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    #[default]
+    Orbit,
+    Fly,
+}
+
+impl CameraMode {
+    pub const ALL: [Self; 2] = [Self::Orbit, Self::Fly];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Orbit => "Orbit",
+            Self::Fly => "Fly",
+        }
+    }
+}
+
+/// Orbits around [`Self::target`], rotated by dragging with the left mouse button and zoomed with
+/// the scroll wheel
+#[derive(Clone, Debug)]
+pub struct OrbitCamera {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub target: [f32; 3],
+}
+
+impl OrbitCamera {
+    /// Returns whether the camera moved
+    pub fn handle_input(&mut self, input: &InputProvider, filter: &mut CameraInputFilter) -> bool {
+        let mouse = if input.mouse_button_held(MouseButton::Left) {
+            input.mouse_delta()
+        } else {
+            [0.0; 2]
+        };
+        let ([dx, dy], scroll) = filter.filter(mouse, input.scroll_delta());
+
+        let mut moved = false;
+        if dx != 0.0 || dy != 0.0 {
+            self.yaw -= dx * ROTATE_SPEED;
+            self.pitch = (self.pitch + dy * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+            moved = true;
+        }
+        if scroll != 0.0 {
+            self.distance = (self.distance * (1.0 - scroll * ZOOM_SPEED)).max(f32::EPSILON);
+            moved = true;
+        }
+        moved
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        let offset = direction(self.yaw, self.pitch);
+        std::array::from_fn(|i| self.target[i] + offset[i] * self.distance)
+    }
+
+    fn forward(&self) -> [f32; 3] {
+        sub(self.target, self.position())
+    }
+
+    /// Clip planes hugging the target, the raytracer ignores depth so they only matter to
+    /// consumers of [`Camera::view_proj()`]
+    fn near_far(&self) -> (f32, f32) {
+        (self.distance * 0.01, self.distance * 100.0)
+    }
+}
+
+/// Moves with WASD along its view, looks around with the mouse while the right mouse button holds
+/// the cursor captured, and moves [`FAST_MULTIPLIER`] times faster while shift is held
+#[derive(Clone, Debug)]
+pub struct FlyCamera {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    /// In scene units per second
+    pub speed: f32,
+    /// Radius of the scene it was framed for, scales the clip planes
+    scene_radius: f32,
+    /// Whether the cursor is captured for mouse-look
+    grabbed: bool,
+    last_input: Option<Instant>,
+}
+
+impl FlyCamera {
+    /// Returns whether the camera moved. Asks the render loop to capture the cursor while the
+    /// right mouse button is held, and to release it again.
+    pub fn handle_input(
+        &mut self,
+        input: &InputProvider,
+        filter: &mut CameraInputFilter,
+        event_sender: &EventSender,
+    ) -> bool {
+        let now = Instant::now();
+        let dt = self
+            .last_input
+            .map_or(0.0, |last| (now - last).as_secs_f32().min(MAX_MOVE_DT));
+        self.last_input = Some(now);
+
+        let mut moved = false;
+        let grab = input.mouse_button_held(MouseButton::Right);
+        let mouse = if grab != self.grabbed {
+            // Capturing and releasing warps the cursor, which would show up as a jump in this
+            // frame's delta. Looking starts with the next frame's relative motion instead.
+            self.set_grabbed(grab, event_sender);
+            [0.0; 2]
+        } else if grab {
+            input.mouse_delta()
+        } else {
+            [0.0; 2]
+        };
+        let ([dx, dy], _) = filter.filter(mouse, 0.0);
+        if dx != 0.0 || dy != 0.0 {
+            self.yaw += dx * ROTATE_SPEED;
+            self.pitch = (self.pitch + dy * ROTATE_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+            moved = true;
+        }
+
+        let [right, _, forward] = basis(direction(self.yaw, self.pitch));
+        let axis = |positive, negative| {
+            input.key_held(positive) as i32 as f32 - input.key_held(negative) as i32 as f32
+        };
+        let (forward_amount, right_amount) =
+            (axis(KeyCode::W, KeyCode::S), axis(KeyCode::D, KeyCode::A));
+        if forward_amount != 0.0 || right_amount != 0.0 {
+            let fast = input.key_held(KeyCode::ShiftLeft) || input.key_held(KeyCode::ShiftRight);
+            let step = self.speed * dt * if fast { FAST_MULTIPLIER } else { 1.0 };
+            for i in 0..3 {
+                self.position[i] += (forward[i] * forward_amount + right[i] * right_amount) * step;
+            }
+            moved = true;
+        }
+        moved
+    }
+
+    fn set_grabbed(&mut self, grabbed: bool, event_sender: &EventSender) {
+        self.grabbed = grabbed;
+        event_sender.set_cursor_grab(grabbed);
+    }
+
+    fn forward(&self) -> [f32; 3] {
+        direction(self.yaw, self.pitch)
+    }
+
+    /// Fixed to the scene, there is no target to hug
+    fn near_far(&self) -> (f32, f32) {
+        (self.scene_radius * 0.001, self.scene_radius * 100.0)
+    }
+}
+
+/// Where a [`Camera`] is and where it looks, regardless of its mode, see [`Camera::pose()`]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub position: [f32; 3],
+    /// View direction, like [`FlyCamera::yaw`] and [`FlyCamera::pitch`]
+    pub yaw: f32,
+    pub pitch: f32,
+    /// From [`Self::position`] to the target of the orbit camera
+    pub distance: f32,
+}
+
+/// Perspective camera that is either an [`OrbitCamera`] or a [`FlyCamera`], the render path only
+/// sees the matrices of [`Self::constants()`]
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub orbit: OrbitCamera,
+    pub fly: FlyCamera,
+    /// Vertical field of view in radians
+    pub fov_y: f32,
+    /// Subpixel offset of the projection in NDC, see [`crate::accumulation::Accumulator`]
+    pub jitter: [f32; 2],
+    /// Shared by both modes
+    pub input_filter: CameraInputFilter,
+    aspect_ratio: f32,
+    /// Fitted again once [`Self::set_viewport_size()`] provides the aspect ratio
+    pending_fit: Option<Aabb>,
+    /// Set by the UI, [`Self::auto_fit_camera()`] and [`Self::set_pose()`], reported as movement
+    /// by the next [`Self::handle_input()`]
+    changed: bool,
+}
+
+impl Camera {
+    /// Orbits the center of `bounds` from far enough away to see all of it, from the same side
+    /// as the pixel space view the camera replaces, see [`Self::auto_fit_camera()`]
+    pub fn framing(bounds: &Aabb) -> Self {
+        let radius = bounds.radius().max(f32::EPSILON);
+        let orbit = OrbitCamera {
+            yaw: PI,
+            pitch: 0.0,
+            distance: radius,
+            target: bounds.center(),
+        };
+        let mut camera = Self {
+            mode: CameraMode::Orbit,
+            fly: FlyCamera {
+                position: [0.0; 3],
+                yaw: 0.0,
+                pitch: 0.0,
+                speed: radius,
+                scene_radius: radius,
+                grabbed: false,
+                last_input: None,
+            },
+            orbit,
+            fov_y: 60f32.to_radians(),
+            jitter: [0.0; 2],
+            input_filter: CameraInputFilter::default(),
+            aspect_ratio: 1.0,
+            pending_fit: Some(*bounds),
+            changed: false,
+        };
+        camera.auto_fit_camera(bounds);
+        camera.changed = false;
+        camera
+    }
+
+    pub fn set_viewport_size(&mut self, size: [u32; 2]) {
+        self.aspect_ratio = size[0] as f32 / size[1].max(1) as f32;
+        if let Some(bounds) = self.pending_fit.take() {
+            self.auto_fit_camera(&bounds);
+        }
+    }
+
+    /// Looks at the center of `bounds` from far enough away that its bounding sphere, scaled by
+    /// [`FIT_MARGIN`], fits the narrower of the two fields of view. Keeps the current view
+    /// direction, and the fly camera's speed relative to the size of the scene.
+    pub fn auto_fit_camera(&mut self, bounds: &Aabb) {
+        if bounds.is_empty() {
+            return;
+        }
+        let radius = bounds.radius().max(f32::EPSILON);
+        let half_fov_y = self.fov_y * 0.5;
+        let half_fov_x = (half_fov_y.tan() * self.aspect_ratio).atan();
+
+        if self.mode == CameraMode::Fly {
+            self.orbit_from_fly();
+        }
+        self.orbit.target = bounds.center();
+        self.orbit.distance = radius * FIT_MARGIN / half_fov_x.min(half_fov_y).sin();
+        self.fly.speed *= radius / self.fly.scene_radius;
+        self.fly.scene_radius = radius;
+        self.fly_from_orbit();
+        self.changed = true;
+    }
+
+    /// Returns whether the camera moved, switched modes or was fitted. Input is ignored while
+    /// `ui_has_pointer`, unless the fly camera has captured the cursor.
+    pub fn handle_input(
+        &mut self,
+        input: &InputProvider,
+        ui_has_pointer: bool,
+        event_sender: &EventSender,
+    ) -> bool {
+        let changed = std::mem::take(&mut self.changed);
+        if ui_has_pointer && !self.fly.grabbed {
+            return changed;
+        }
+        let moved = match self.mode {
+            CameraMode::Orbit => {
+                if self.fly.grabbed {
+                    self.fly.set_grabbed(false, event_sender);
+                }
+                self.orbit.handle_input(input, &mut self.input_filter)
+            }
+            CameraMode::Fly => self
+                .fly
+                .handle_input(input, &mut self.input_filter, event_sender),
+        };
+        changed || moved
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        let (yaw, pitch) = match self.mode {
+            CameraMode::Orbit => (self.orbit.yaw + PI, -self.orbit.pitch),
+            CameraMode::Fly => (self.fly.yaw, self.fly.pitch),
+        };
+        CameraPose {
+            position: self.position(),
+            yaw,
+            pitch,
+            distance: self.orbit.distance,
+        }
+    }
+
+    /// Moves both modes to `pose`, the next [`Self::handle_input()`] reports the move
+    pub fn set_pose(&mut self, pose: &CameraPose) {
+        self.fly.position = pose.position;
+        self.fly.yaw = pose.yaw;
+        self.fly.pitch = pose.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.orbit.distance = pose.distance.max(f32::EPSILON);
+        self.orbit_from_fly();
+        self.input_filter.reset();
+        self.changed = true;
+    }
+
+    /// Puts the fly camera at the orbit camera's eye, looking the same way
+    fn fly_from_orbit(&mut self) {
+        self.fly.position = self.orbit.position();
+        self.fly.yaw = self.orbit.yaw + PI;
+        self.fly.pitch = -self.orbit.pitch;
+    }
+
+    /// Makes the orbit camera look from the fly camera's eye, at its current distance
+    fn orbit_from_fly(&mut self) {
+        let forward = self.fly.forward();
+        self.orbit.yaw = self.fly.yaw - PI;
+        self.orbit.pitch = -self.fly.pitch;
+        self.orbit.target =
+            std::array::from_fn(|i| self.fly.position[i] + forward[i] * self.orbit.distance);
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.position(),
+            CameraMode::Fly => self.fly.position,
+        }
+    }
+
+    fn near_far(&self) -> (f32, f32) {
+        match self.mode {
+            CameraMode::Orbit => self.orbit.near_far(),
+            CameraMode::Fly => self.fly.near_far(),
+        }
+    }
+
+    /// Right, up and forward vectors of the view
+    fn basis(&self) -> [[f32; 3]; 3] {
+        basis(match self.mode {
+            CameraMode::Orbit => self.orbit.forward(),
+            CameraMode::Fly => self.fly.forward(),
+        })
+    }
+
+    /// Right-handed view-projection with a 0-1 depth range
+    pub fn view_proj(&self) -> Mat4 {
+        let eye = self.position();
+        let [right, up, forward] = self.basis();
+        let view = [
+            [right[0], right[1], right[2], -dot(eye, right)],
+            [up[0], up[1], up[2], -dot(eye, up)],
+            [-forward[0], -forward[1], -forward[2], dot(eye, forward)],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        let t = 1.0 / (self.fov_y * 0.5).tan();
+        let (near, far) = self.near_far();
+        // The jitter is added to the projected position after the divide by `w = -z`
+        let proj = [
+            [t / self.aspect_ratio, 0.0, -self.jitter[0], 0.0],
+            [0.0, t, -self.jitter[1], 0.0],
+            [0.0, 0.0, far / (near - far), near * far / (near - far)],
+            [0.0, 0.0, -1.0, 0.0],
+        ];
+
+        mul_mat4(&proj, &view)
+    }
+
+    /// Inverse of [`Self::view_proj()`], for unprojecting primary rays
+    pub fn inv_view_proj(&self) -> Mat4 {
+        let eye = self.position();
+        let [right, up, forward] = self.basis();
+        let inv_view = [
+            [right[0], up[0], -forward[0], eye[0]],
+            [right[1], up[1], -forward[1], eye[1]],
+            [right[2], up[2], -forward[2], eye[2]],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        let t = 1.0 / (self.fov_y * 0.5).tan();
+        let (near, far) = self.near_far();
+        let inv_proj = [
+            [
+                self.aspect_ratio / t,
+                0.0,
+                0.0,
+                -self.jitter[0] * self.aspect_ratio / t,
+            ],
+            [0.0, 1.0 / t, 0.0, -self.jitter[1] / t],
+            [0.0, 0.0, 0.0, -1.0],
+            [0.0, 0.0, (near - far) / (near * far), 1.0 / near],
+        ];
+
+        mul_mat4(&inv_view, &inv_proj)
+    }
+
+    pub fn constants(&self) -> CameraConstants {
+        CameraConstants {
+            view_proj: self.view_proj(),
+            inv_view_proj: self.inv_view_proj(),
+            position: self.position(),
+            enabled: 1,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let previous = self.mode;
+        egui::ComboBox::from_label("Mode")
+            .selected_text(self.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in CameraMode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.name());
+                }
+            });
+        if self.mode != previous {
+            // Continues from the same eye, so switching doesn't jump
+            match self.mode {
+                CameraMode::Orbit => self.orbit_from_fly(),
+                CameraMode::Fly => self.fly_from_orbit(),
+            }
+            self.input_filter.reset();
+            self.changed = true;
+        }
+        match self.mode {
+            CameraMode::Orbit => {
+                ui.label("Drag with the left mouse button to orbit, scroll to zoom");
+            }
+            CameraMode::Fly => {
+                ui.add(
+                    egui::Slider::new(&mut self.fly.speed, 0.01..=10000.0)
+                        .logarithmic(true)
+                        .text("Speed"),
+                );
+                ui.label("Hold the right mouse button to look, WASD to move, shift to speed up");
+            }
+        }
+        ui.label("Press F to frame the whole scene");
+        ui.collapsing("Input", |ui| self.input_filter.ui(ui));
+    }
+}
+
+/// Mirrors `CameraConstants` in `constants.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct CameraConstants {
+    pub view_proj: Mat4,
+    pub inv_view_proj: Mat4,
+    pub position: [f32; 3],
+    /// Without a camera the main view traces straight rays through pixel space
+    pub enabled: u32,
+}
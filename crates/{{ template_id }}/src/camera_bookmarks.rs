@@ -0,0 +1,105 @@
+use breda::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::camera::{Camera, CameraPose};
+
+/// The number keys that select a slot, `1` is the first
+const SLOT_KEYS: [egui::Key; 9] = [
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Num4,
+    egui::Key::Num5,
+    egui::Key::Num6,
+    egui::Key::Num7,
+    egui::Key::Num8,
+    egui::Key::Num9,
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraBookmark {
+    /// Shown and edited in the list, `Bookmark <n>` until renamed
+    pub name: String,
+    pub pose: CameraPose,
+}
+
+/// Camera poses saved to numbered slots with Ctrl+1 to Ctrl+9 (Cmd on macOS) and jumped to with
+/// 1 to 9, persisted in [`crate::settings::Settings`]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraBookmarks {
+    pub slots: [Option<CameraBookmark>; SLOT_KEYS.len()],
+}
+
+impl CameraBookmarks {
+    /// Saves `camera`'s pose into `slot`, keeping the name of the bookmark it replaces
+    fn save(&mut self, slot: usize, camera: &Camera) {
+        let name = self.slots[slot].take().map_or_else(
+            || format!("Bookmark {}", slot + 1),
+            |bookmark| bookmark.name,
+        );
+        log::info!("Saved camera bookmark {} `{name}`", slot + 1);
+        self.slots[slot] = Some(CameraBookmark {
+            name,
+            pose: camera.pose(),
+        });
+    }
+
+    /// Handles the number keys, unless a text field like a bookmark's name has keyboard focus
+    pub fn handle_keys(&mut self, ctx: &egui::Context, camera: &mut Camera) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        for (slot, key) in SLOT_KEYS.into_iter().enumerate() {
+            let (pressed, save) = ctx.input(|i| (i.key_pressed(key), i.modifiers.command));
+            if !pressed {
+                continue;
+            }
+            if save {
+                self.save(slot, camera);
+            } else if let Some(bookmark) = &self.slots[slot] {
+                camera.set_pose(&bookmark.pose);
+            }
+        }
+    }
+
+    /// Lists the slots, with their names editable and buttons to jump to, overwrite or delete them
+    pub fn ui(&mut self, ui: &mut egui::Ui, camera: &mut Camera) {
+        ui.label("Ctrl+1 to Ctrl+9 saves the camera, 1 to 9 jumps back to it");
+        let (mut save, mut delete) = (None, None);
+        egui::Grid::new("camera bookmarks").show(ui, |ui| {
+            for (slot, bookmark) in self.slots.iter_mut().enumerate() {
+                ui.label(format!("{}", slot + 1));
+                match bookmark {
+                    Some(bookmark) => {
+                        ui.text_edit_singleline(&mut bookmark.name);
+                        if ui.button("Go").clicked() {
+                            camera.set_pose(&bookmark.pose);
+                        }
+                        if ui.button("Save").clicked() {
+                            save = Some(slot);
+                        }
+                        if ui.button("Delete").clicked() {
+                            delete = Some(slot);
+                        }
+                    }
+                    None => {
+                        ui.weak("Empty");
+                        ui.label("");
+                        if ui.button("Save").clicked() {
+                            save = Some(slot);
+                        }
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(slot) = save {
+            self.save(slot, camera);
+        }
+        if let Some(slot) = delete {
+            log::info!("Deleted camera bookmark {}", slot + 1);
+            self.slots[slot] = None;
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use breda::egui;
+
+/// Simple look adjustments applied to the final color, see `applyColorGrading()` in
+/// `main.ps.hlsl`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorGrading {
+    /// Power curve applied per channel, `1.0` leaves the image unchanged
+    pub gamma: f32,
+    /// Scales the distance from mid gray
+    pub contrast: f32,
+    /// Scales the distance from the pixel's luminance, `0.0` is grayscale
+    pub saturation: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            gamma: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl ColorGrading {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.add(egui::Slider::new(&mut self.gamma, 0.2..=5.0).text("Gamma"));
+        ui.add(egui::Slider::new(&mut self.contrast, 0.0..=2.0).text("Contrast"));
+        ui.add(egui::Slider::new(&mut self.saturation, 0.0..=2.0).text("Saturation"));
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+    }
+}
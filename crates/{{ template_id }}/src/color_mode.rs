@@ -0,0 +1,284 @@
+use anyhow::{bail, ensure, Result};
+use breda::renderer::{Format, SwapchainColorMode};
+use clap::{Args, ValueEnum};
+use half::f16;
+
+use crate::readback::{unpack_rgb10a2, ReadbackFormat};
+
+/// Brightness in nits that linear value 1 stands for, as defined by scRGB. HDR10's absolute PQ
+/// values are scaled to match, so both modes tonemap the same.
+const SDR_WHITE_NITS: f32 = 80.0;
+
+/// Peak brightness of the PQ transfer function, that an encoded value of 1 decodes to
+const PQ_MAX_NITS: f32 = 10000.0;
+
+/// Linear Rec. 2020 to linear Rec. 709, which shares its primaries with sRGB
+const REC2020_TO_REC709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+/// Color space and precision of the swapchain
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// 8 bits per channel, encoded to sRGB when written
+    #[default]
+    Srgb8,
+    /// 8 bits per channel, written as is
+    Unorm8,
+    /// 10 bits per channel with the PQ transfer function in the Rec. 2020 color space
+    Hdr10,
+    /// Linear 16-bit float in the sRGB primaries, values above 1 are brighter than SDR white
+    Scrgb,
+}
+
+impl ColorMode {
+    /// Spelled like on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Srgb8 => "srgb8",
+            Self::Unorm8 => "unorm8",
+            Self::Hdr10 => "hdr10",
+            Self::Scrgb => "scrgb",
+        }
+    }
+
+    pub fn swapchain_color_mode(self) -> SwapchainColorMode {
+        match self {
+            Self::Srgb8 => SwapchainColorMode::ForceSrgb8Bit,
+            Self::Unorm8 => SwapchainColorMode::ForceUnorm8Bit,
+            Self::Hdr10 => SwapchainColorMode::ForceHdr10,
+            Self::Scrgb => SwapchainColorMode::ForceScRgb,
+        }
+    }
+
+    pub fn is_hdr(self) -> bool {
+        matches!(self, Self::Hdr10 | Self::Scrgb)
+    }
+
+    /// Swapchain formats that mean this mode was granted
+    fn formats(self) -> &'static [Format] {
+        match self {
+            Self::Srgb8 => &[Format::B8g8r8a8Srgb, Format::R8g8b8a8Srgb],
+            Self::Unorm8 => &[Format::B8g8r8a8Unorm, Format::R8g8b8a8Unorm],
+            Self::Hdr10 => &[Format::A2b10g10r10UnormPack32],
+            Self::Scrgb => &[Format::R16g16b16a16Sfloat],
+        }
+    }
+
+    /// The render loop falls back to another format when the surface doesn't support the requested
+    /// one, which would silently render with the wrong transfer function
+    pub fn check_swapchain_format(self, format: Format) -> Result<()> {
+        if !self.formats().contains(&format) {
+            bail!(
+                "--color-mode {} is not supported by this device and display, the swapchain was \
+                 created as {format:?} instead",
+                self.name()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Converts `bytes`, tightly packed texels of a swapchain image in `format` that was created for
+/// `color_mode`, to 8-bit sRGB RGBA as stored in PNGs.
+///
+/// 8-bit modes are copied as they are, only reordering channels: sRGB swapchains already hold
+/// encoded values, and the display shows UNORM values as sRGB too. HDR modes are decoded to
+/// linear Rec. 709, tonemapped with Reinhard (`x / (1 + x)`) and then sRGB encoded. Alpha is
+/// copied over.
+pub fn encode_framebuffer(
+    bytes: &[u8],
+    format: ReadbackFormat,
+    color_mode: ColorMode,
+) -> Result<Vec<u8>> {
+    ensure!(
+        bytes.len() % format.bytes_per_pixel() == 0,
+        "{} bytes are not a whole number of {format:?} texels",
+        bytes.len()
+    );
+
+    let encoded = match (color_mode, format) {
+        (ColorMode::Srgb8 | ColorMode::Unorm8, ReadbackFormat::Rgba8) => bytes.to_vec(),
+        (ColorMode::Srgb8 | ColorMode::Unorm8, ReadbackFormat::Bgra8) => bytes
+            .chunks_exact(4)
+            .flat_map(|c| [c[2], c[1], c[0], c[3]])
+            .collect(),
+        (ColorMode::Hdr10, ReadbackFormat::Rgb10a2) => bytes
+            .chunks_exact(4)
+            .flat_map(|c| {
+                let [r, g, b, a] = unpack_rgb10a2([c[0], c[1], c[2], c[3]]);
+                let rec2020 = [r, g, b].map(|c| pq_to_nits(c) / SDR_WHITE_NITS);
+                let rec709 = REC2020_TO_REC709
+                    .map(|row| row.iter().zip(rec2020).map(|(m, c)| m * c).sum::<f32>());
+                encode_hdr_texel(rec709, a)
+            })
+            .collect(),
+        (ColorMode::Scrgb, ReadbackFormat::Rgba16f) => bytes
+            .chunks_exact(8)
+            .flat_map(|c| {
+                let [r, g, b, a] =
+                    std::array::from_fn(|i| f16::from_le_bytes([c[i * 2], c[i * 2 + 1]]).to_f32());
+                encode_hdr_texel([r, g, b], a)
+            })
+            .collect(),
+        _ => bail!(
+            "{format:?} is not a swapchain format of --color-mode {}",
+            color_mode.name()
+        ),
+    };
+    Ok(encoded)
+}
+
+/// Inverse of the SMPTE ST 2084 (PQ) transfer function, in nits
+fn pq_to_nits(encoded: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let e = encoded.clamp(0.0, 1.0).powf(1.0 / M2);
+    ((e - C1).max(0.0) / (C2 - C3 * e)).powf(1.0 / M1) * PQ_MAX_NITS
+}
+
+/// Inverse of [`linear_to_srgb()`], for values read back from an sRGB texture, which are stored
+/// encoded
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Whether writes to `format` are encoded to sRGB, so that reading it back gives the encoded value
+pub fn is_srgb_format(format: Format) -> bool {
+    matches!(format, Format::B8g8r8a8Srgb | Format::R8g8b8a8Srgb)
+}
+
+fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Tonemaps linear Rec. 709 `rgb` and encodes it with `alpha` into 8-bit sRGB
+fn encode_hdr_texel(rgb: [f32; 3], alpha: f32) -> [u8; 4] {
+    let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let [r, g, b] = rgb.map(|c| {
+        let c = c.max(0.0);
+        to_u8(linear_to_srgb(c / (1.0 + c)))
+    });
+    [r, g, b, to_u8(alpha)]
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ColorModeOpts {
+    /// Color space and precision of the window's swapchain
+    #[clap(long, value_enum, default_value_t)]
+    pub color_mode: ColorMode,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb10a2(r: u32, g: u32, b: u32, a: u32) -> [u8; 4] {
+        (r | g << 10 | b << 20 | a << 30).to_le_bytes()
+    }
+
+    fn rgba16f(rgba: [f32; 4]) -> Vec<u8> {
+        rgba.iter()
+            .flat_map(|c| f16::from_f32(*c).to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn unpack_rgb10a2_puts_red_in_the_lowest_bits() {
+        assert_eq!(
+            unpack_rgb10a2(rgb10a2(1023, 0, 1023, 3)),
+            [1.0, 0.0, 1.0, 1.0]
+        );
+        assert_eq!(unpack_rgb10a2(rgb10a2(0, 1023, 0, 0)), [0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn pq_to_nits_matches_st_2084() {
+        assert_eq!(pq_to_nits(0.0), 0.0);
+        assert!((pq_to_nits(1.0) - PQ_MAX_NITS).abs() < 0.5);
+        assert!((pq_to_nits(0.5) - 92.25).abs() < 0.01);
+        // Out of range values are clamped
+        assert_eq!(pq_to_nits(-1.0), 0.0);
+    }
+
+    #[test]
+    fn srgb_to_linear_inverts_linear_to_srgb() {
+        for linear in [0.0, 0.002, 0.0031308, 0.2, 0.5, 1.0] {
+            assert!((srgb_to_linear(linear_to_srgb(linear)) - linear).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn srgb8_and_unorm8_are_copied() {
+        let rgba = [10, 20, 30, 40];
+        let bgra = [30, 20, 10, 40];
+        for color_mode in [ColorMode::Srgb8, ColorMode::Unorm8] {
+            assert_eq!(
+                encode_framebuffer(&rgba, ReadbackFormat::Rgba8, color_mode).unwrap(),
+                rgba
+            );
+            assert_eq!(
+                encode_framebuffer(&bgra, ReadbackFormat::Bgra8, color_mode).unwrap(),
+                rgba
+            );
+        }
+    }
+
+    #[test]
+    fn hdr10_is_decoded_and_tonemapped() {
+        // Code 520 is PQ for about 100 nits, 1.25 times SDR white, which Reinhard maps to 0.56
+        let bytes = [
+            rgb10a2(0, 0, 0, 3),
+            rgb10a2(520, 520, 520, 3),
+            rgb10a2(520, 0, 0, 1),
+            rgb10a2(1023, 1023, 1023, 3),
+        ]
+        .concat();
+        assert_eq!(
+            encode_framebuffer(&bytes, ReadbackFormat::Rgb10a2, ColorMode::Hdr10).unwrap(),
+            [
+                [0, 0, 0, 255],
+                [197, 197, 197, 255],
+                // Rec. 2020 red is outside of Rec. 709, its negative green and blue are clipped
+                [214, 0, 0, 85],
+                [254, 254, 254, 255],
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn scrgb_is_tonemapped() {
+        let bytes = [
+            rgba16f([0.0, 0.0, 0.0, 1.0]),
+            rgba16f([1.0, 3.0, -1.0, 0.5]),
+        ]
+        .concat();
+        assert_eq!(
+            encode_framebuffer(&bytes, ReadbackFormat::Rgba16f, ColorMode::Scrgb).unwrap(),
+            // Reinhard maps 1 to 0.5 and 3 to 0.75, negative values are clipped
+            [[0, 0, 0, 255], [188, 225, 0, 128]].concat()
+        );
+    }
+
+    #[test]
+    fn mismatched_formats_are_rejected() {
+        assert!(encode_framebuffer(&[0; 4], ReadbackFormat::Rgb10a2, ColorMode::Srgb8).is_err());
+        assert!(encode_framebuffer(&[0; 4], ReadbackFormat::Rgba8, ColorMode::Scrgb).is_err());
+        assert!(encode_framebuffer(&[0; 6], ReadbackFormat::Rgba16f, ColorMode::Scrgb).is_err());
+    }
+}
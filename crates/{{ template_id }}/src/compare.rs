@@ -0,0 +1,175 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context, Result};
+use breda::{renderer::Device, streaming_system::StreamingSystem};
+use clap::Args;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    headless::HeadlessRenderer,
+    init_streaming_system,
+    raytracer::RaytracerSettings,
+    scene::{SceneDescription, TriangleScene},
+    CommandlineOpts,
+};
+
+/// Default maximum per-channel difference for a pixel to still be considered equal
+pub const DEFAULT_CHANNEL_TOLERANCE: u8 = 2;
+/// Default fraction of pixels that may exceed the channel tolerance before the comparison fails
+pub const DEFAULT_MAX_MISMATCH_FRACTION: f64 = 0.001;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct CompareOpts {
+    /// Render a single frame headlessly and compare it against this reference image. The process
+    /// exits with a failure code when the images differ beyond the configured tolerances
+    #[clap(long, value_name = "REFERENCE_PNG")]
+    pub compare: Option<PathBuf>,
+    /// Maximum per-channel difference (0-255) for a pixel to be considered matching
+    #[clap(long, value_name = "TOLERANCE")]
+    pub compare_channel_tolerance: Option<u8>,
+    /// Maximum fraction (0-1) of mismatching pixels for the comparison to pass
+    #[clap(long, value_name = "FRACTION")]
+    pub compare_max_mismatch: Option<f64>,
+    /// Where to write the image highlighting mismatching pixels
+    #[clap(long, value_name = "PATH", default_value = "compare_diff.png")]
+    pub compare_diff: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CompareResult {
+    pub mismatching_pixels: usize,
+    pub total_pixels: usize,
+    pub max_channel_difference: u8,
+}
+
+impl CompareResult {
+    pub fn mismatch_fraction(&self) -> f64 {
+        self.mismatching_pixels as f64 / self.total_pixels as f64
+    }
+}
+
+/// Compares `actual` against `reference` pixel by pixel, returning the statistics and a diff image
+/// where mismatching pixels are red and matching pixels are a dimmed grayscale of the reference
+pub fn compare_images(
+    reference: &RgbaImage,
+    actual: &RgbaImage,
+    channel_tolerance: u8,
+) -> Result<(CompareResult, RgbaImage)> {
+    if reference.dimensions() != actual.dimensions() {
+        bail!(
+            "Reference is {:?} but the rendered image is {:?}",
+            reference.dimensions(),
+            actual.dimensions()
+        );
+    }
+
+    let mut mismatching_pixels = 0;
+    let mut max_channel_difference = 0;
+    let diff = RgbaImage::from_fn(reference.width(), reference.height(), |x, y| {
+        let expected = reference.get_pixel(x, y);
+        let got = actual.get_pixel(x, y);
+
+        let difference = expected
+            .0
+            .iter()
+            .zip(got.0)
+            .map(|(&e, g)| e.abs_diff(g))
+            .max()
+            .unwrap_or(0);
+        max_channel_difference = max_channel_difference.max(difference);
+
+        if difference > channel_tolerance {
+            mismatching_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [r, g, b, _] = expected.0;
+            let luma = ((u16::from(r) + u16::from(g) + u16::from(b)) / 3 / 4) as u8;
+            Rgba([luma, luma, luma, 255])
+        }
+    });
+
+    Ok((
+        CompareResult {
+            mismatching_pixels,
+            total_pixels: reference.pixels().len(),
+            max_channel_difference,
+        },
+        diff,
+    ))
+}
+
+/// Renders one frame at the size of `reference` and fails if it doesn't match within the
+/// tolerances from `opts`
+pub fn run_headless_compare(
+    device: &Arc<dyn Device>,
+    streaming_system: &StreamingSystem,
+    opts: &CommandlineOpts,
+    reference: &Path,
+) -> Result<()> {
+    let fence_timeout = opts.fence_timeout.timeout();
+
+    let reference_image = image::open(reference)
+        .with_context(|| format!("Failed to load reference image `{}`", reference.display()))?
+        .to_rgba8();
+
+    init_streaming_system(streaming_system, device, opts.startup.shader_db_timeout())?;
+    let scene = TriangleScene::new(
+        device.as_ref(),
+        &opts.scene,
+        fence_timeout,
+        &SceneDescription::from_opts(&opts.scene)?,
+    )?;
+
+    let mut renderer = HeadlessRenderer::new(
+        device,
+        reference_image.dimensions().into(),
+        opts.readback.readback_format,
+        &opts.render_graph_debug,
+        fence_timeout,
+    );
+    let rendered = renderer
+        .render_frame(
+            streaming_system,
+            &scene,
+            &RaytracerSettings::new(&opts.raytracer, &mut opts.seed.rng()),
+            None,
+        )?
+        .to_rgba8();
+
+    let (result, diff) = compare_images(
+        &reference_image,
+        &rendered,
+        opts.compare
+            .compare_channel_tolerance
+            .unwrap_or(DEFAULT_CHANNEL_TOLERANCE),
+    )?;
+    diff.save(&opts.compare.compare_diff)
+        .with_context(|| format!("Failed to write `{}`", opts.compare.compare_diff.display()))?;
+
+    let max_mismatch = opts
+        .compare
+        .compare_max_mismatch
+        .unwrap_or(DEFAULT_MAX_MISMATCH_FRACTION);
+    log::info!(
+        "{} of {} pixels mismatch ({:.4}%), max channel difference {}",
+        result.mismatching_pixels,
+        result.total_pixels,
+        result.mismatch_fraction() * 100.0,
+        result.max_channel_difference
+    );
+
+    if result.mismatch_fraction() > max_mismatch {
+        bail!(
+            "Rendered image does not match `{}`: {:.4}% of pixels mismatch, at most {:.4}% allowed. See `{}`",
+            reference.display(),
+            result.mismatch_fraction() * 100.0,
+            max_mismatch * 100.0,
+            opts.compare.compare_diff.display()
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::{
+    backtrace::Backtrace,
+    panic::PanicHookInfo,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+use breda::renderer::Device;
+
+/// Formatted driver info of the device that is rendered with, see [`set_device()`]
+static DEVICE_INFO: Mutex<Option<String>> = Mutex::new(None);
+/// Frames started so far, see [`begin_frame()`]
+static FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// Installs a panic hook that reports the panic along with the device set by [`set_device()`] and
+/// the last frame index, with a backtrace when `RUST_BACKTRACE` is set.
+///
+/// The report goes to stderr and to `<crate>_crash_<timestamp>.txt` in the working directory, or
+/// to logcat on Android. Install before creating any devices, panics before [`set_device()`] is
+/// called report the device as unavailable.
+pub fn install() {
+    std::panic::set_hook(Box::new(|info| {
+        let report = format_report(info);
+
+        #[cfg(target_os = "android")]
+        log::error!("{report}");
+
+        #[cfg(not(target_os = "android"))]
+        {
+            eprintln!("{report}");
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = format!("{}_crash_{timestamp}.txt", env!("CARGO_PKG_NAME"));
+            match std::fs::write(&path, &report) {
+                Ok(()) => eprintln!("Crash report written to `{path}`"),
+                Err(e) => eprintln!("Failed to write the crash report to `{path}`: {e}"),
+            }
+        }
+    }));
+}
+
+/// Includes `device` in crash reports from now on, call whenever a different device is picked
+pub fn set_device(device: &dyn Device) {
+    let driver_info = device.driver_info();
+    let info = format!(
+        "{} {}, {} `{}` @ {}{}",
+        driver_info.vendor,
+        driver_info.device_name,
+        driver_info.driver_id,
+        driver_info.driver_name,
+        driver_info
+            .version
+            .map_or_else(|| "invalid driver version".to_string(), |v| v.to_string()),
+        if driver_info.driver_extra_info.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", driver_info.driver_extra_info)
+        }
+    );
+    *DEVICE_INFO.lock().unwrap_or_else(PoisonError::into_inner) = Some(info);
+}
+
+/// Call at the start of every frame of the render loop
+pub fn begin_frame() {
+    FRAMES.fetch_add(1, Ordering::Relaxed);
+}
+
+fn format_report(info: &PanicHookInfo<'_>) -> String {
+    let device = DEVICE_INFO
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+        .unwrap_or_else(|| "unavailable, no device was selected yet".to_string());
+    let frame = match FRAMES.load(Ordering::Relaxed) {
+        0 => "none rendered yet".to_string(),
+        frames => (frames - 1).to_string(),
+    };
+
+    format!(
+        "{} {} panicked: {info}\nDevice: {device}\nFrame: {frame}\n{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        Backtrace::capture()
+    )
+}
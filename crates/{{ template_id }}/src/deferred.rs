@@ -0,0 +1,76 @@
+use breda::{
+    render_graph::{RasterPass, RenderGraph, RenderGraphBuffer, RenderGraphTexture},
+    renderer::{Format, LoadOp, StoreOp, TextureCreateDesc},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+
+use crate::fullscreen::FullscreenPass;
+
+/// Render targets of the G-buffer pass of [`crate::raytracer::DrawMode::Deferred`], mirrored by
+/// `GBufferOutput` in `deferred.ps.hlsl`. Created per frame in the render graph, like the main
+/// pass' depth target, which the G-buffer pass depth tests against.
+pub struct GBuffer {
+    /// Material color, with an alpha of `1` where a triangle covers the pixel
+    pub albedo: RenderGraphTexture,
+    /// World-space normal facing the viewer
+    pub normal: RenderGraphTexture,
+}
+
+impl GBuffer {
+    pub const ALBEDO_FORMAT: Format = Format::R8g8b8a8Unorm;
+    pub const NORMAL_FORMAT: Format = Format::R16g16b16a16Sfloat;
+    /// Written by the lighting pass and read by the tonemap, so that lit surfaces can exceed `1`
+    pub const HDR_FORMAT: Format = Format::R16g16b16a16Sfloat;
+
+    /// Both targets cleared to transparent black, which marks the pixels no triangle covers
+    pub fn create(render_graph: &mut RenderGraph, size: [u32; 2]) -> Self {
+        let albedo = render_graph.create_texture(
+            "g-buffer albedo",
+            &TextureCreateDesc::render_target_2d(size, Self::ALBEDO_FORMAT),
+        );
+        let normal = render_graph.create_texture(
+            "g-buffer normal",
+            &TextureCreateDesc::render_target_2d(size, Self::NORMAL_FORMAT),
+        );
+        for texture in [&albedo, &normal] {
+            render_graph.clear_texture(texture, [0.0; 4]);
+        }
+        Self { albedo, normal }
+    }
+
+    /// Records the lighting pass, which shades the G-buffer into an HDR target with the light of
+    /// the raytracer's `constants`, and the tonemap of the result into `target`. Only the covered
+    /// pixels of `target` are written, the background drawn into it before stays elsewhere.
+    pub fn record_lighting(
+        &self,
+        render_graph: &mut RenderGraph,
+        target: &RenderGraphTexture,
+        target_size: [u32; 2],
+        constants: &RenderGraphBuffer,
+        user_uniforms: &RenderGraphBuffer,
+        shader_db: &AssetsShaderDatabase,
+    ) {
+        let hdr = render_graph.create_texture(
+            "deferred hdr target",
+            &TextureCreateDesc::render_target_2d(target_size, Self::HDR_FORMAT),
+        );
+
+        FullscreenPass::draw(
+            RasterPass::new("Deferred lighting", render_graph)
+                .render_target(&hdr, LoadOp::Discard, StoreOp::Store)
+                .read_texture(&self.albedo)
+                .read_texture(&self.normal)
+                .read_buffer(constants),
+            &shader_db.get_pipeline("{{ template_id }}-deferred-lighting"),
+        );
+
+        FullscreenPass::draw(
+            RasterPass::new("Tonemap", render_graph)
+                .render_target(target, LoadOp::Load, StoreOp::Store)
+                .read_texture(&hdr)
+                .read_buffer(constants)
+                .read_buffer(user_uniforms),
+            &shader_db.get_pipeline("{{ template_id }}-tonemap"),
+        );
+    }
+}
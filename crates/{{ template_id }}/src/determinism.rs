@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use breda::{renderer::Device, streaming_system::StreamingSystem};
+use clap::Args;
+use image::DynamicImage;
+
+use crate::{
+    headless::HeadlessRenderer,
+    init_streaming_system,
+    raytracer::RaytracerSettings,
+    scene::{SceneDescription, TriangleScene},
+    turntable::orbit_direction,
+    CommandlineOpts,
+};
+
+pub const DEFAULT_DETERMINISM_FRAMES: u32 = 100;
+
+/// What the target is cleared to before the first and the second render of a frame. The renders
+/// share a target, so without different contents a pixel that neither writes would still match.
+const FILLS: [[f32; 4]; 2] = [[1.0, 0.0, 1.0, 1.0], [0.0, 1.0, 0.0, 0.0]];
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct DeterminismOpts {
+    /// Render every one of --frames frames twice and fail unless both are byte-identical, to
+    /// catch render graph aliasing and uninitialized memory bugs
+    #[clap(long)]
+    pub determinism_check: bool,
+}
+
+/// Returns the coordinates of the first pixel that differs between `a` and `b`
+fn first_mismatch(a: &DynamicImage, b: &DynamicImage) -> Option<[u32; 2]> {
+    let bytes_per_pixel = usize::from(a.color().bytes_per_pixel());
+    a.as_bytes()
+        .chunks_exact(bytes_per_pixel)
+        .zip(b.as_bytes().chunks_exact(bytes_per_pixel))
+        .position(|(a, b)| a != b)
+        .map(|index| {
+            let index = index as u32;
+            [index % a.width(), index / a.width()]
+        })
+}
+
+pub fn run_determinism_check(
+    device: &Arc<dyn Device>,
+    streaming_system: &StreamingSystem,
+    opts: &CommandlineOpts,
+) -> Result<()> {
+    let fence_timeout = opts.fence_timeout.timeout();
+    let frames = opts
+        .headless
+        .frames
+        .unwrap_or(DEFAULT_DETERMINISM_FRAMES)
+        .max(1);
+
+    init_streaming_system(streaming_system, device, opts.startup.shader_db_timeout())?;
+    let scene = TriangleScene::new(
+        device.as_ref(),
+        &opts.scene,
+        fence_timeout,
+        &SceneDescription::from_opts(&opts.scene)?,
+    )?;
+
+    let mut renderer = HeadlessRenderer::new(
+        device,
+        opts.headless.size(),
+        opts.readback.readback_format,
+        &opts.render_graph_debug,
+        fence_timeout,
+    );
+    let mut settings = RaytracerSettings::new(&opts.raytracer, &mut opts.seed.rng());
+
+    for frame in 0..frames {
+        // Vary the view between frames so that more than a single image is covered
+        settings.view_direction = (frame > 0).then(|| orbit_direction(frame, frames));
+
+        let [first_fill, second_fill] = FILLS;
+        let first = renderer.render_frame_over_fill(
+            streaming_system,
+            &scene,
+            &settings,
+            None,
+            first_fill,
+        )?;
+        let second = renderer.render_frame_over_fill(
+            streaming_system,
+            &scene,
+            &settings,
+            None,
+            second_fill,
+        )?;
+
+        if let Some([x, y]) = first_mismatch(&first, &second) {
+            bail!(
+                "Frame {frame} is not deterministic: pixel ({x}, {y}) is {:?} in the first render and {:?} in the second",
+                first.to_rgba32f().get_pixel(x, y).0,
+                second.to_rgba32f().get_pixel(x, y).0
+            );
+        }
+    }
+
+    log::info!("All {frames} frames rendered identically twice");
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{bail, Result};
+use breda::{renderer::Device, streaming_system::StreamingSystem};
+
+use crate::{
+    app_resources::{reinit_device, AppResources, DeviceResourceOpts},
+    init_streaming_system,
+};
+
+/// How often every device is tried before giving up on a lost device
+pub const MAX_RECOVERY_ATTEMPTS: u32 = 5;
+
+/// Wait after the first round of failed attempts, doubled after every following round
+const INITIAL_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Runs `wait`, which waits on a fence of `device`, unless the device is already lost.
+///
+/// Nothing on a lost device signals its fences, so waiting on them would only time out and fail
+/// the frame before the render loop gets to [`recover_from_device_loss()`]. A wait that fails
+/// because the device got lost in the meantime is skipped the same way.
+pub fn wait_unless_lost(device: &dyn Device, wait: impl FnOnce() -> Result<()>) -> Result<()> {
+    if device.is_lost() {
+        return Ok(());
+    }
+    match wait() {
+        Err(e) if device.is_lost() => {
+            log::warn!("Device was lost while waiting on it: {e:?}");
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Recreates the per-device resources after `lost` stopped responding, like after a driver timeout
+/// or when an external GPU is unplugged.
+///
+/// `lost` itself is tried first in case the driver brought it back, then the other `rt_devices`.
+/// Rounds over all of them are separated by an exponentially growing wait, and after
+/// [`MAX_RECOVERY_ATTEMPTS`] rounds the loss is returned as an error instead of retrying forever.
+pub fn recover_from_device_loss(
+    lost: &Arc<dyn Device>,
+    rt_devices: &[Arc<dyn Device>],
+    streaming_system: &StreamingSystem,
+    shader_db_timeout: Duration,
+    opts: &DeviceResourceOpts,
+) -> Result<AppResources> {
+    let lost_name = lost.driver_info().device_name;
+    log::error!(
+        "`{lost_name}` was lost, dropping its scene, acceleration structures, render graph store, \
+         egui renderer and GPU timing buffers"
+    );
+
+    let candidates = std::iter::once(lost)
+        .chain(
+            rt_devices
+                .iter()
+                .filter(|device| !Arc::ptr_eq(device, lost)),
+        )
+        .collect::<Vec<_>>();
+
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 1..=MAX_RECOVERY_ATTEMPTS {
+        for device in candidates.iter().filter(|device| !device.is_lost()) {
+            let name = device.driver_info().device_name;
+            match init_streaming_system(streaming_system, device, shader_db_timeout)
+                .map_err(anyhow::Error::from)
+                .and_then(|()| reinit_device(device, opts))
+            {
+                Ok(resources) => {
+                    log::info!("Recovered from losing `{lost_name}` on `{name}`");
+                    return Ok(resources);
+                }
+                Err(e) => log::warn!("Recovery attempt {attempt} on `{name}` failed: {e:?}"),
+            }
+        }
+
+        if attempt < MAX_RECOVERY_ATTEMPTS {
+            log::warn!("No device available yet, retrying in {backoff:?}");
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    bail!(
+        "`{lost_name}` was lost and no device could take over after {MAX_RECOVERY_ATTEMPTS} \
+         attempts"
+    )
+}
@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use breda::renderer::Device;
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct DiagnosticsOpts {
+    /// Print which raytracing features every device supports and exit
+    #[clap(long)]
+    pub rt_features: bool,
+}
+
+/// Prints the raytracing related capabilities of `devices`, marking the one the app would pick.
+/// Devices are numbered like `--gpu` expects.
+pub fn print_rt_features<'a>(
+    devices: impl IntoIterator<Item = &'a Arc<dyn Device>>,
+    selected: Option<&Arc<dyn Device>>,
+) {
+    for (index, device) in devices.into_iter().enumerate() {
+        let driver_info = device.driver_info();
+        let capabilities = device.capabilities();
+        let is_selected = selected.is_some_and(|selected| Arc::ptr_eq(selected, device));
+
+        println!(
+            "{index}: {} {}{}",
+            driver_info.vendor,
+            driver_info.device_name,
+            if is_selected { " (selected)" } else { "" }
+        );
+        for (feature, supported) in [
+            (
+                "Inline ray tracing",
+                capabilities.supports_inline_ray_tracing,
+            ),
+            (
+                "Ray tracing pipelines",
+                capabilities.supports_ray_tracing_pipeline,
+            ),
+            (
+                "Vertex position fetch",
+                capabilities.supports_ray_tracing_position_fetch,
+            ),
+            ("Opacity micromaps", capabilities.supports_opacity_micromaps),
+            (
+                "Displacement micromaps",
+                capabilities.supports_displacement_micromaps,
+            ),
+            ("Shader clock", capabilities.supports_shader_clock),
+        ] {
+            println!("    {feature:<24} {}", if supported { "yes" } else { "no" });
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use breda::egui;
+use clap::Args;
+
+pub const DEFAULT_TARGET_FPS: f32 = 60.0;
+
+/// A frame counts as dropped when it takes longer than this many target frame intervals
+const DROPPED_FRAME_FACTOR: f32 = 2.0;
+
+/// Number of dropped frames listed in the UI
+const RECENT_DROPS: usize = 8;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct DroppedFrameOpts {
+    /// Frame rate the app is expected to run at, frames taking more than twice as long are
+    /// reported as dropped
+    #[clap(long, value_name = "FPS")]
+    pub target_fps: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DroppedFrame {
+    /// Time since the detector started
+    pub at: Duration,
+    pub frame_time: Duration,
+}
+
+/// Flags frames that took much longer than the target frame interval as likely hitches
+pub struct DroppedFrameDetector {
+    threshold: Duration,
+    start: Instant,
+    last_frame: Option<Instant>,
+    total: usize,
+    recent: Vec<DroppedFrame>,
+}
+
+impl DroppedFrameDetector {
+    pub fn new(opts: &DroppedFrameOpts) -> Self {
+        let target_fps = opts.target_fps.unwrap_or(DEFAULT_TARGET_FPS).max(1.0);
+        Self {
+            threshold: Duration::from_secs_f32(DROPPED_FRAME_FACTOR / target_fps),
+            start: Instant::now(),
+            last_frame: None,
+            total: 0,
+            recent: Vec::new(),
+        }
+    }
+
+    /// Call once at the same point of every frame
+    pub fn frame(&mut self) {
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame.replace(now) {
+            let frame_time = now - last_frame;
+            if frame_time > self.threshold {
+                let dropped = DroppedFrame {
+                    at: now - self.start,
+                    frame_time,
+                };
+                log::warn!(
+                    "Dropped frame at {:.3} s: took {:.2} ms",
+                    dropped.at.as_secs_f64(),
+                    frame_time.as_secs_f64() * 1000.0
+                );
+
+                self.total += 1;
+                if self.recent.len() == RECENT_DROPS {
+                    self.recent.remove(0);
+                }
+                self.recent.push(dropped);
+            }
+        }
+    }
+
+    /// Stops the next frame from being measured, for frames that are known to stall such as
+    /// while shaders are still compiling
+    pub fn skip(&mut self) {
+        self.last_frame = None;
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label(format!(
+            "{} dropped (> {:.1} ms)",
+            self.total,
+            self.threshold.as_secs_f64() * 1000.0
+        ));
+        for dropped in self.recent.iter().rev() {
+            ui.small(format!(
+                "{:.3} s: {:.2} ms",
+                dropped.at.as_secs_f64(),
+                dropped.frame_time.as_secs_f64() * 1000.0
+            ));
+        }
+    }
+}
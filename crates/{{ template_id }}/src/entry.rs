@@ -1,11 +1,42 @@
 use crate::CommandlineOpts;
 use clap::Parser;
 
+/// Bundled in the APK's `assets/` directory, rendered in place of the default scene when present
+/// since there is no command line to pass --scene on
+#[cfg(target_os = "android")]
+const SCENE_ASSET: &str = "scene.glb";
+
 #[cfg(target_os = "android")]
 #[unsafe(no_mangle)]
 fn android_main(app: android_activity::AndroidApp) {
-    if let Err(err) = crate::internal_main(&CommandlineOpts::parse(), app) {
-        log::error!("evolve exited with failure: {err:?}");
+    let mut opts = CommandlineOpts::parse();
+    match load_scene_asset(&app) {
+        Ok(scene) => opts.scene.embedded_scene = scene,
+        Err(err) => log::error!("Failed to load the `{SCENE_ASSET}` asset: {err:?}"),
     }
+
+    if let Err(err) = crate::internal_main(&opts, app) {
+        log::error!("evolve exited with failure: {:?}", anyhow::Error::from(err));
+    }
+}
+
+#[cfg(target_os = "android")]
+fn load_scene_asset(
+    app: &android_activity::AndroidApp,
+) -> anyhow::Result<Option<crate::scene::EmbeddedScene>> {
+    use std::io::Read;
+
+    let name = std::ffi::CString::new(SCENE_ASSET)?;
+    let Some(mut asset) = app.asset_manager().open(&name) else {
+        log::info!("No `{SCENE_ASSET}` asset bundled, rendering the default scene");
+        return Ok(None);
+    };
+    let mut bytes = Vec::new();
+    asset.read_to_end(&mut bytes)?;
+
+    Ok(Some(crate::scene::EmbeddedScene {
+        name: SCENE_ASSET.to_owned(),
+        bytes: bytes.into(),
+    }))
 }
 
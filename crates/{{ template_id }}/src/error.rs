@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+/// Failures that apps embedding this crate may want to handle, returned from
+/// [`crate::internal_main()`] and [`crate::init_streaming_system()`].
+///
+/// Everything else ends up in [`AppError::Other`]. Inside the crate errors stay [`anyhow::Error`],
+/// an `AppError` that is propagated through one is recovered by `From<anyhow::Error>` at the
+/// boundary. Converting back into an [`anyhow::Error`] goes through anyhow's blanket `From` impl
+/// for error types, so a `main()` returning [`anyhow::Result`] can use `?` on it.
+#[derive(Debug, thiserror::Error)]
+pub enum AppError {
+    /// No device supports inline raytracing
+    #[error("No device found that supports inline raytracing")]
+    NoSuitableDevice,
+    /// Building the workspace, and with it the shaders, failed
+    #[error("Failed to build the workspace's shaders")]
+    ShaderBuildFailed(#[source] anyhow::Error),
+    /// The shader database didn't finish streaming in within --shader-db-timeout-secs
+    #[error(
+        "Shader database did not load within {} s, raise --shader-db-timeout-secs if the workspace \
+         is just slow to build",
+        timeout.as_secs()
+    )]
+    StreamingTimeout { timeout: Duration },
+    /// The window's surface went away, like when its display was disconnected, and no swapchain
+    /// can be recreated for it
+    #[error("The swapchain was lost")]
+    SwapchainLost,
+    /// A primitive of a loaded glTF file has indices or attributes that don't match its vertices
+    #[error("Primitive {primitive} of mesh `{mesh}` is malformed: {reason}")]
+    MalformedPrimitive {
+        mesh: String,
+        primitive: usize,
+        reason: String,
+    },
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast::<Self>().unwrap_or_else(Self::Other)
+    }
+}
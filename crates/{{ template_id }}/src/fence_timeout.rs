@@ -0,0 +1,47 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use breda::renderer::Fence;
+use clap::Args;
+
+pub const DEFAULT_FENCE_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct FenceTimeoutOpts {
+    /// Fail with an error instead of hanging when the GPU doesn't signal a fence we explicitly
+    /// wait on within this many milliseconds, like for uploads and acceleration structure builds
+    /// at startup, or for the frames of the render loop
+    #[clap(long, visible_alias = "gpu-timeout-ms", value_name = "MS")]
+    pub fence_timeout_ms: Option<u64>,
+}
+
+impl FenceTimeoutOpts {
+    pub fn timeout(&self) -> FenceTimeout {
+        FenceTimeout(Duration::from_millis(
+            self.fence_timeout_ms.unwrap_or(DEFAULT_FENCE_TIMEOUT_MS),
+        ))
+    }
+}
+
+/// Upper bound on how long a CPU-side fence wait may take, turning GPU hangs into errors
+#[derive(Clone, Copy, Debug)]
+pub struct FenceTimeout(pub Duration);
+
+impl Default for FenceTimeout {
+    fn default() -> Self {
+        Self(Duration::from_millis(DEFAULT_FENCE_TIMEOUT_MS))
+    }
+}
+
+impl FenceTimeout {
+    /// Waits for `fence` to be signaled, `what` describes the GPU work in the error on expiry
+    pub fn wait(self, fence: &Fence, what: &str) -> Result<()> {
+        if !fence.wait_timeout(self.0) {
+            bail!(
+                "GPU did not finish {what} within {} ms, the device may be hung",
+                self.0.as_millis()
+            );
+        }
+        Ok(())
+    }
+}
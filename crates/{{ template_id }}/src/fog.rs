@@ -0,0 +1,80 @@
+use breda::egui;
+
+/// How fog density is derived from the hit distance, see `applyFog()` in `main.ps.hlsl`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FogMode {
+    #[default]
+    Off = 0,
+    /// Ramps from no fog at [`Fog::start`] to full fog at [`Fog::end`]
+    Linear = 1,
+    /// `1 - exp(-density * distance)`
+    Exponential = 2,
+}
+
+impl FogMode {
+    pub const ALL: [Self; 3] = [Self::Off, Self::Linear, Self::Exponential];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Linear => "Linear",
+            Self::Exponential => "Exponential",
+        }
+    }
+}
+
+/// Distance fog over the shaded view. Rays that miss count as infinitely far away, so the
+/// background fades into [`Self::color`] and the horizon matches fogged geometry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub color: [f32; 3],
+    pub density: f32,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::default(),
+            color: [0.6, 0.65, 0.7],
+            density: 0.005,
+            start: 0.0,
+            end: 500.0,
+        }
+    }
+}
+
+impl Fog {
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Fog")
+            .selected_text(self.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in FogMode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.name());
+                }
+            });
+        ui.add_enabled_ui(self.mode != FogMode::Off, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Fog color");
+                ui.color_edit_button_rgb(&mut self.color);
+            });
+        });
+        ui.add_enabled(
+            self.mode == FogMode::Exponential,
+            egui::Slider::new(&mut self.density, 0.0001..=0.1)
+                .logarithmic(true)
+                .text("Fog density"),
+        );
+        ui.add_enabled(
+            self.mode == FogMode::Linear,
+            egui::Slider::new(&mut self.start, 0.0..=1000.0).text("Fog start"),
+        );
+        ui.add_enabled(
+            self.mode == FogMode::Linear,
+            egui::Slider::new(&mut self.end, 0.0..=1000.0).text("Fog end"),
+        );
+    }
+}
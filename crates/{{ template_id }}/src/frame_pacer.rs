@@ -0,0 +1,90 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use breda::egui;
+use clap::Args;
+
+/// Number of frames the CPU frame time is averaged over
+const AVERAGE_FRAMES: usize = 60;
+
+/// The OS sleep is only trusted up to this long before the deadline, the rest is spun away
+const SPIN_MARGIN: Duration = Duration::from_millis(1);
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct FramePacerOpts {
+    /// Limit the frame rate by sleeping after every present until the frame interval has passed
+    #[clap(long, value_name = "FPS")]
+    pub fps_cap: Option<f32>,
+}
+
+/// Measures how long the CPU spends on every frame and, with `--fps-cap`, sleeps away the rest of
+/// the frame interval
+pub struct FramePacer {
+    interval: Option<Duration>,
+    frame_start: Instant,
+    cpu_times: VecDeque<Duration>,
+}
+
+impl FramePacer {
+    pub fn new(opts: &FramePacerOpts) -> Self {
+        Self {
+            interval: opts
+                .fps_cap
+                .map(|fps| Duration::from_secs_f32(1.0 / fps.max(1.0))),
+            frame_start: Instant::now(),
+            cpu_times: VecDeque::with_capacity(AVERAGE_FRAMES),
+        }
+    }
+
+    /// Call after presenting. Records the time since the previous call as this frame's CPU time,
+    /// then sleeps until the frame's deadline if there is one.
+    ///
+    /// Only the time left in the interval is slept, and a frame that overran its deadline starts
+    /// the next interval from now instead of trying to catch up.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        if self.cpu_times.len() == AVERAGE_FRAMES {
+            self.cpu_times.pop_front();
+        }
+        self.cpu_times.push_back(now - self.frame_start);
+
+        let Some(interval) = self.interval else {
+            self.frame_start = now;
+            return;
+        };
+        let deadline = self.frame_start + interval;
+        if now >= deadline {
+            self.frame_start = now;
+            return;
+        }
+
+        if let Some(sleep) = (deadline - now).checked_sub(SPIN_MARGIN) {
+            std::thread::sleep(sleep);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+        self.frame_start = deadline;
+    }
+
+    /// Average CPU frame time over the last [`AVERAGE_FRAMES`] frames
+    pub fn average_cpu_time(&self) -> Option<Duration> {
+        (!self.cpu_times.is_empty())
+            .then(|| self.cpu_times.iter().sum::<Duration>() / self.cpu_times.len() as u32)
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        if let Some(average) = self.average_cpu_time() {
+            ui.label(format!(
+                "CPU: {:.3} ms (avg over {} frames)",
+                average.as_secs_f64() * 1000.0,
+                self.cpu_times.len()
+            ));
+        }
+        if let Some(interval) = self.interval {
+            ui.small(format!("Capped at {:.1} fps", 1.0 / interval.as_secs_f64()));
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::{marker::PhantomData, time::Duration};
+
+use anyhow::Result;
+use breda::renderer::{create_buffer_with_data, Buffer, BufferCreateDesc, Device, Fence};
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+};
+
+/// Frames that may be in flight at once, matching the swapchain's
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Host-visible buffers of `len` `T`s for data that is rewritten every frame, like constants.
+///
+/// There is one buffer per frame in flight and the ring moves on to the next one every frame,
+/// without allocating anything per frame. Every buffer keeps the fence of the last submission that
+/// read it, which [`Self::write()`] waits on, so the CPU never overwrites a buffer the GPU is still
+/// reading for an earlier frame. That wait is free unless rendering gets more than
+/// [`FRAMES_IN_FLIGHT`] frames ahead of the GPU.
+pub struct FrameRing<T> {
+    slots: Vec<Slot>,
+    current: usize,
+    fence_timeout: FenceTimeout,
+    _marker: PhantomData<T>,
+}
+
+struct Slot {
+    buffer: Buffer,
+    /// Of the last submission that read [`Self::buffer`]
+    fence: Option<Fence>,
+}
+
+impl<T: Copy + Default> FrameRing<T> {
+    pub fn new(device: &dyn Device, name: &str, len: usize, fence_timeout: FenceTimeout) -> Self {
+        let slots = (0..FRAMES_IN_FLIGHT)
+            .map(|i| {
+                let name = format!("{name} {i}");
+                log_allocation(
+                    ResourceKind::Buffer,
+                    &name,
+                    len * std::mem::size_of::<T>(),
+                    MemoryKind::HostVisible,
+                );
+                Slot {
+                    buffer: create_buffer_with_data(
+                        device,
+                        &name,
+                        &BufferCreateDesc::cpu_to_gpu(),
+                        &vec![T::default(); len],
+                    ),
+                    fence: None,
+                }
+            })
+            .collect();
+
+        Self {
+            slots,
+            current: 0,
+            fence_timeout,
+            _marker: PhantomData,
+        }
+    }
+
+    /// The buffer of the frame being recorded, holding what [`Self::write()`] wrote this frame
+    pub fn current(&self) -> &Buffer {
+        &self.slots[self.current].buffer
+    }
+
+    /// Writes `data` into the buffer of the frame being recorded and returns it, once the GPU is
+    /// done with what was written into it [`FRAMES_IN_FLIGHT`] frames ago. Call at most once per
+    /// frame.
+    pub fn write(&mut self, data: &[T]) -> Result<&Buffer> {
+        let slot = &mut self.slots[self.current];
+        if let Some(fence) = slot.fence.take() {
+            self.fence_timeout
+                .wait(&fence, "reading a per-frame buffer")?;
+        }
+        slot.buffer.write_data(data);
+        Ok(&slot.buffer)
+    }
+
+    /// Hands over the fence of the frame's submission and moves on to the next buffer, call once
+    /// per frame whether or not the current buffer was written
+    pub fn advance(&mut self, fence: Fence) {
+        self.slots[self.current].fence = Some(fence);
+        self.current = (self.current + 1) % self.slots.len();
+    }
+}
+
+/// Resources that were replaced while frames that use them may still be in flight, like a rebuilt
+/// TLAS. Each is kept alive until the fence of the frame it was replaced in has signaled, frames
+/// finish in order so that covers every earlier one too.
+pub struct Retired<T> {
+    /// With the fence of the frame they were retired in, once [`Self::end_frame()`] handed it over
+    resources: Vec<(T, Option<Fence>)>,
+}
+
+impl<T> Default for Retired<T> {
+    fn default() -> Self {
+        Self { resources: vec![] }
+    }
+}
+
+impl<T> Retired<T> {
+    /// Keeps `resource` alive until the GPU is done with the frame being recorded
+    pub fn retire(&mut self, resource: T) {
+        self.resources.push((resource, None));
+    }
+
+    /// Everything that is still kept alive, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.resources.iter().map(|(resource, _)| resource)
+    }
+
+    /// Hands over the fence of the frame's submission and drops what the finished frames were the
+    /// last to use, call once per frame
+    pub fn end_frame(&mut self, fence: &Fence) {
+        self.end_frame_with(fence, drop);
+    }
+
+    /// Like [`Self::end_frame()`], but hands what the finished frames were the last to use to
+    /// `release` instead of dropping it, for resources that also have to be unregistered somewhere
+    pub fn end_frame_with(&mut self, fence: &Fence, release: impl FnMut(T)) {
+        for (_, retired_in) in &mut self.resources {
+            retired_in.get_or_insert_with(|| fence.clone());
+        }
+        let (finished, in_flight) = std::mem::take(&mut self.resources)
+            .into_iter()
+            .partition::<Vec<_>, _>(|(_, retired_in)| {
+                retired_in
+                    .as_ref()
+                    .is_some_and(|fence| fence.wait_timeout(Duration::ZERO))
+            });
+        self.resources = in_flight;
+        finished
+            .into_iter()
+            .map(|(resource, _)| resource)
+            .for_each(release);
+    }
+}
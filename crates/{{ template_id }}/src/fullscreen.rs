@@ -0,0 +1,18 @@
+use breda::{render_graph::RasterPass, renderer::RasterPipeline};
+
+/// Draws a pipeline over its whole render target, for passes that shade every pixel like the
+/// background, the upscale and the raytracer's main pass.
+///
+/// The pipeline's vertex shader must be `fullscreen_triangle.vs.hlsl`, which derives the
+/// positions of a single oversized triangle from `SV_VertexID`, see that file for the layout.
+/// No vertex or index buffers are bound.
+pub struct FullscreenPass;
+
+impl FullscreenPass {
+    /// Vertices of the triangle in `fullscreen_triangle.vs.hlsl`
+    pub const VERTEX_COUNT: u32 = 3;
+
+    pub fn draw(pass: RasterPass<'_>, pipeline: &RasterPipeline) {
+        pass.draw(pipeline, Self::VERTEX_COUNT, 1);
+    }
+}
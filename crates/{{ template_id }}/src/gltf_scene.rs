@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use gltf::{buffer, mesh::Mode, Gltf, Node};
+
+use crate::{
+    error::AppError,
+    math::{self, Transform},
+    mesh::Mesh,
+};
+
+/// The upper 3x4 rows of a column-major glTF node matrix, in the layout of [`Transform`]. The
+/// bottom row of node matrices is always `[0, 0, 0, 1]`.
+fn to_transform(m: &[[f32; 4]; 4]) -> Transform {
+    std::array::from_fn(|i| m[i % 4][i / 4])
+}
+
+/// Appends the triangles of `node` and its children to `mesh`, baking in their world transforms.
+/// Fails with [`AppError::MalformedPrimitive`] when a primitive's indices or normals don't fit its
+/// vertices, which would otherwise index out of bounds further down.
+fn append_node(
+    node: &Node<'_>,
+    parent: &Transform,
+    buffers: &[buffer::Data],
+    mesh: &mut Mesh,
+    primitives: &mut usize,
+) -> Result<()> {
+    let world = math::mul(parent, &to_transform(&node.transform().matrix()));
+
+    if let Some(node_mesh) = node.mesh() {
+        for primitive in node_mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                log::warn!(
+                    "Skipping {:?} primitive of mesh `{}`, only triangle lists are supported",
+                    primitive.mode(),
+                    node_mesh.name().unwrap_or_default()
+                );
+                continue;
+            }
+
+            let malformed = |reason: String| AppError::MalformedPrimitive {
+                mesh: node_mesh
+                    .name()
+                    .map_or_else(|| format!("#{}", node_mesh.index()), str::to_owned),
+                primitive: primitive.index(),
+                reason,
+            };
+
+            let reader = primitive
+                .reader(|buffer| buffers.get(buffer.index()).map(|data| data.0.as_slice()));
+            let Some(positions) = reader.read_positions() else {
+                continue;
+            };
+            let positions: Vec<_> = positions
+                .map(|p| math::transform_point(&world, p))
+                .collect();
+            let vertex_count = positions.len() as u32;
+            // Non-indexed primitives list every triangle's corners in order
+            let indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertex_count).collect(),
+            };
+            if indices.len() % 3 != 0 {
+                return Err(malformed(format!(
+                    "{} indices don't form whole triangles",
+                    indices.len()
+                ))
+                .into());
+            }
+            if let Some(index) = indices.iter().find(|&&index| index >= vertex_count) {
+                return Err(malformed(format!(
+                    "index {index} is out of range for {vertex_count} vertices"
+                ))
+                .into());
+            }
+            let normals: Option<Vec<_>> = reader
+                .read_normals()
+                .map(|normals| normals.map(|n| math::transform_normal(&world, n)).collect());
+            if let Some(normals) = &normals {
+                if normals.len() != positions.len() {
+                    return Err(malformed(format!(
+                        "{} normals for {vertex_count} vertices",
+                        normals.len()
+                    ))
+                    .into());
+                }
+            }
+
+            let base = mesh.positions.len() as u32;
+            mesh.positions.extend(positions);
+            // Only kept while every primitive so far had them, the rest is cleared by the caller
+            if let Some(normals) = normals {
+                if mesh.normals.len() == base as usize {
+                    mesh.normals.extend(normals);
+                }
+            }
+            mesh.indices
+                .extend(indices.iter().map(|index| base + index));
+            *primitives += 1;
+        }
+    }
+
+    for child in node.children() {
+        append_node(&child, &world, buffers, mesh, primitives)?;
+    }
+    Ok(())
+}
+
+/// Loads the triangle primitives of a `.gltf` or `.glb` file into a single mesh, see
+/// [`load_gltf_bytes()`]
+pub fn load_gltf_scene(path: &Path) -> Result<Mesh> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    load_gltf_bytes(&path.display().to_string(), &bytes, path.parent())
+}
+
+/// Loads the triangle primitives of the contents of a `.gltf` or `.glb` file into a single mesh.
+/// `name` only identifies the file in errors and logs. External buffers are resolved relative to
+/// `base_dir`, without one only buffers embedded in a `.glb` or as data URI can be loaded.
+///
+/// Every primitive reachable from the default scene (or the first scene if there is no default) is
+/// flattened with its node transform applied, with or without an index buffer. Only positions and
+/// normals are read, materials and other vertex attributes are ignored. Unless every primitive has
+/// normals none are kept, see [`Mesh::ensure_normals()`]. Primitives whose indices or normals don't
+/// match their vertices fail the load with [`AppError::MalformedPrimitive`].
+pub fn load_gltf_bytes(name: &str, bytes: &[u8], base_dir: Option<&Path>) -> Result<Mesh> {
+    let gltf = Gltf::from_slice(bytes).with_context(|| format!("Failed to parse `{name}`"))?;
+    let buffers = gltf::import_buffers(&gltf.document, base_dir, gltf.blob.clone())
+        .with_context(|| format!("Failed to load the buffers of `{name}`"))?;
+
+    let scene = gltf
+        .document
+        .default_scene()
+        .or_else(|| gltf.document.scenes().next())
+        .with_context(|| format!("`{name}` contains no scenes"))?;
+
+    let mut mesh = Mesh::default();
+    let mut primitives = 0;
+    for node in scene.nodes() {
+        append_node(&node, &math::IDENTITY, &buffers, &mut mesh, &mut primitives)
+            .with_context(|| format!("Failed to load `{name}`"))?;
+    }
+
+    ensure!(mesh.triangle_count() > 0, "`{name}` contains no triangles");
+    if mesh.normals.len() != mesh.positions.len() {
+        mesh.normals.clear();
+    }
+    log::info!(
+        "Loaded `{name}`: {} vertices, {} triangles from {} primitive(s)",
+        mesh.positions.len(),
+        mesh.triangle_count(),
+        primitives
+    );
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `.glb` with a single triangle primitive of three vertices and the given `u16` indices
+    fn triangle_glb(indices: [u16; 3]) -> Vec<u8> {
+        let mut bin: Vec<u8> = [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+            .iter()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        bin.extend(indices.iter().flat_map(|i| i.to_le_bytes()));
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let mut json = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{ "mesh": 0 }}],
+                "meshes": [{{
+                    "name": "triangle",
+                    "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}]
+                }}],
+                "buffers": [{{ "byteLength": {} }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "accessors": [
+                    {{
+                        "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0]
+                    }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ]
+            }}"#,
+            bin.len()
+        )
+        .into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut glb = Vec::new();
+        glb.extend(b"glTF");
+        glb.extend(2u32.to_le_bytes());
+        glb.extend((12 + 8 + json.len() as u32 + 8 + bin.len() as u32).to_le_bytes());
+        for (kind, chunk) in [(b"JSON", &json), (b"BIN\0", &bin)] {
+            glb.extend((chunk.len() as u32).to_le_bytes());
+            glb.extend(kind);
+            glb.extend(chunk);
+        }
+        glb
+    }
+
+    #[test]
+    fn in_range_indices_load() {
+        let mesh = load_gltf_bytes("triangle.glb", &triangle_glb([0, 1, 2]), None).unwrap();
+        assert_eq!(mesh.indices, [0, 1, 2]);
+        assert_eq!(mesh.positions.len(), 3);
+    }
+
+    #[test]
+    fn out_of_range_index_names_the_primitive() {
+        let error = load_gltf_bytes("triangle.glb", &triangle_glb([0, 1, 3]), None).unwrap_err();
+        let Some(AppError::MalformedPrimitive {
+            mesh, primitive, ..
+        }) = error.downcast_ref::<AppError>()
+        else {
+            panic!("expected a malformed primitive, got {error:?}");
+        };
+        assert_eq!(mesh, "triangle");
+        assert_eq!(*primitive, 0);
+    }
+}
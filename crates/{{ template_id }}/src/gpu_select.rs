@@ -0,0 +1,78 @@
+use std::{fmt::Write as _, sync::Arc};
+
+use anyhow::{bail, Result};
+use breda::renderer::Device;
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct GpuSelectOpts {
+    /// Render on the device at this index in the list printed by --rt-features, instead of the
+    /// first one that supports inline raytracing
+    #[clap(long, value_name = "INDEX", conflicts_with = "gpu_name")]
+    pub gpu: Option<usize>,
+    /// Render on the first device supporting inline raytracing whose name contains this,
+    /// ignoring case
+    #[clap(long, value_name = "SUBSTRING")]
+    pub gpu_name: Option<String>,
+}
+
+impl GpuSelectOpts {
+    /// The device to render on out of all of `devices`. Without --gpu or --gpu-name that is the
+    /// first one with inline raytracing support, or [`None`] if there is no such device. The
+    /// requested device not existing or not supporting inline raytracing is an error instead.
+    pub fn select(&self, devices: &[Arc<dyn Device>]) -> Result<Option<Arc<dyn Device>>> {
+        let supports_rt =
+            |device: &&Arc<dyn Device>| device.capabilities().supports_inline_ray_tracing;
+
+        let (flag, device) = if let Some(index) = self.gpu {
+            (format!("--gpu {index}"), devices.get(index))
+        } else if let Some(name) = &self.gpu_name {
+            let flag = format!("--gpu-name {name}");
+            let name = name.to_lowercase();
+            (
+                flag,
+                devices.iter().filter(supports_rt).find(|device| {
+                    device
+                        .driver_info()
+                        .device_name
+                        .to_lowercase()
+                        .contains(&name)
+                }),
+            )
+        } else {
+            return Ok(devices.iter().find(supports_rt).cloned());
+        };
+
+        match device {
+            Some(device) if supports_rt(&device) => Ok(Some(device.clone())),
+            Some(device) => bail!(
+                "{flag}: `{}` does not support inline raytracing, available devices are:\n{}",
+                device.driver_info().device_name,
+                list_devices(devices)
+            ),
+            None => bail!(
+                "{flag}: no such device, available devices are:\n{}",
+                list_devices(devices)
+            ),
+        }
+    }
+}
+
+fn list_devices(devices: &[Arc<dyn Device>]) -> String {
+    let mut list = String::new();
+    for (index, device) in devices.iter().enumerate() {
+        let driver_info = device.driver_info();
+        let _ = writeln!(
+            list,
+            "    {index}: {} {}{}",
+            driver_info.vendor,
+            driver_info.device_name,
+            if device.capabilities().supports_inline_ray_tracing {
+                ""
+            } else {
+                " (no inline raytracing)"
+            }
+        );
+    }
+    list
+}
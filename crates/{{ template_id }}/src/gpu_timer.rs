@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use breda::{
+    egui,
+    renderer::{Buffer, BufferCreateDesc, CommandBuffer, Device, Fence, TimestampQueryPool},
+};
+use clap::Args;
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::FRAMES_IN_FLIGHT,
+};
+
+/// Number of frame times kept for the graph
+const HISTORY_LEN: usize = 240;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct GpuTimerOpts {
+    /// Measure how long the GPU spends executing the render graph with timestamp queries, and
+    /// graph it in a window
+    #[clap(long)]
+    pub gpu_timer: bool,
+}
+
+struct Slot {
+    queries: TimestampQueryPool,
+    /// Receives the begin and end timestamps
+    results: Buffer,
+    fence: Option<Fence>,
+}
+
+/// GPU time between [`GpuTimer::begin()`] and [`GpuTimer::end()`], read back a couple of frames
+/// later once the fence of the frame has signaled
+pub struct GpuTimer {
+    slots: Vec<Slot>,
+    current: usize,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f64,
+    history: VecDeque<f32>,
+    fence_timeout: FenceTimeout,
+}
+
+impl GpuTimer {
+    /// Returns [`None`] when the device lacks timestamp query support, after logging a warning
+    pub fn new(device: &dyn Device, fence_timeout: FenceTimeout) -> Option<Self> {
+        let capabilities = device.capabilities();
+        if !capabilities.supports_timestamp_queries {
+            log::warn!(
+                "--gpu-timer requested but `{}` does not support timestamp queries",
+                device.driver_info().device_name
+            );
+            return None;
+        }
+
+        let slots = (0..FRAMES_IN_FLIGHT)
+            .map(|i| {
+                let name = format!("gpu timer results {i}");
+                let size = 2 * std::mem::size_of::<u64>();
+                log_allocation(ResourceKind::Buffer, &name, size, MemoryKind::Readback);
+                Slot {
+                    queries: device.create_timestamp_query_pool(&format!("gpu timer {i}"), 2),
+                    results: device.create_buffer(&name, size, &BufferCreateDesc::gpu_to_cpu()),
+                    fence: None,
+                }
+            })
+            .collect();
+
+        Some(Self {
+            slots,
+            current: 0,
+            timestamp_period: f64::from(capabilities.timestamp_period_ns),
+            history: VecDeque::with_capacity(HISTORY_LEN),
+            fence_timeout,
+        })
+    }
+
+    /// Collects the results of the oldest frame and records the begin timestamp
+    pub fn begin(&mut self, cmd: &mut CommandBuffer) -> Result<()> {
+        let slot = &mut self.slots[self.current];
+
+        if let Some(fence) = slot.fence.take() {
+            self.fence_timeout.wait(&fence, "writing gpu timestamps")?;
+
+            let [begin, end] = slot.results.read_data::<[u64; 2]>()[0];
+            let ms = end.saturating_sub(begin) as f64 * self.timestamp_period / 1_000_000.0;
+            if self.history.len() == HISTORY_LEN {
+                self.history.pop_front();
+            }
+            self.history.push_back(ms as f32);
+        }
+
+        cmd.write_timestamp(&slot.queries, 0);
+        Ok(())
+    }
+
+    /// Records the end timestamp and copies both into the readback buffer
+    pub fn end(&mut self, cmd: &mut CommandBuffer) {
+        let slot = &self.slots[self.current];
+        cmd.write_timestamp(&slot.queries, 1);
+        cmd.resolve_timestamps(&slot.queries, &slot.results);
+    }
+
+    /// Hands over the fence of the submission containing [`Self::begin()`] and [`Self::end()`]
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.slots[self.current].fence = Some(fence);
+        self.current = (self.current + 1) % self.slots.len();
+    }
+
+    pub fn last_ms(&self) -> Option<f32> {
+        self.history.back().copied()
+    }
+
+    /// Last frame time, the average over the history and a graph of the history
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        let Some(last) = self.last_ms() else {
+            ui.label("Waiting for results...");
+            return;
+        };
+        let average = self.history.iter().sum::<f32>() / self.history.len() as f32;
+        let max = self.history.iter().copied().fold(f32::EPSILON, f32::max);
+        ui.label(format!(
+            "{last:.3} ms (avg {average:.3} ms, max {max:.3} ms)"
+        ));
+
+        let (response, painter) =
+            ui.allocate_painter(egui::vec2(ui.available_width(), 60.0), egui::Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(96));
+        let points = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, &ms)| {
+                egui::pos2(
+                    rect.left() + rect.width() * i as f32 / (HISTORY_LEN - 1) as f32,
+                    rect.bottom() - rect.height() * ms / max,
+                )
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, egui::Color32::LIGHT_GREEN),
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use breda::renderer::Fence;
+
+use crate::{fence_timeout::FenceTimeout, frame_ring::FRAMES_IN_FLIGHT};
+
+/// A frame's submission that the GPU may still be working on
+struct InFlight {
+    fence: Fence,
+    /// Of the render graph that was executed, for the report when the GPU hangs on it
+    pass_names: Vec<String>,
+}
+
+/// Turns a GPU hang in the render loop into an error instead of a frozen window.
+///
+/// Before recording a frame, [`Self::begin_frame()`] waits for the submission [`FRAMES_IN_FLIGHT`]
+/// frames ago with --gpu-timeout-ms. That one has usually finished by the time the swapchain hands
+/// out its image, so this costs nothing until the GPU hangs.
+///
+/// The [`crate::frame_ring::FrameRing`]s and the readback rings of the replay, pixel probe, GPU
+/// timer and shader clock also wait on frame fences before reusing a slot. Their slots are
+/// [`FRAMES_IN_FLIGHT`] frames apart, so once this has waited those fences have signaled and
+/// their waits return immediately. Screenshots and the latency test instead wait for the frame
+/// they were just submitted in. All of them go through the same [`FenceTimeout`], so a hang fails
+/// the frame whichever wait hits it first, but only this one reports the hung frame's passes.
+pub struct GpuWatchdog {
+    fence_timeout: FenceTimeout,
+    in_flight: VecDeque<InFlight>,
+}
+
+impl GpuWatchdog {
+    pub fn new(fence_timeout: FenceTimeout) -> Self {
+        Self {
+            fence_timeout,
+            in_flight: VecDeque::with_capacity(FRAMES_IN_FLIGHT),
+        }
+    }
+
+    /// Waits until fewer than [`FRAMES_IN_FLIGHT`] frames are in flight, and fails with the pass
+    /// names of the frame that didn't finish in time
+    pub fn begin_frame(&mut self) -> Result<()> {
+        while self.in_flight.len() >= FRAMES_IN_FLIGHT {
+            let oldest = self.in_flight.pop_front().expect("Checked the length");
+            if let Err(e) = self.fence_timeout.wait(&oldest.fence, "rendering a frame") {
+                log::error!(
+                    "The GPU hung on a frame with the passes: {}",
+                    oldest.pass_names.join(", ")
+                );
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Call with the fence of every frame's submission
+    pub fn submitted(&mut self, fence: Fence, pass_names: Vec<String>) {
+        self.in_flight.push_back(InFlight { fence, pass_names });
+    }
+
+    /// Forgets all submissions, like those of a device that was switched away from or lost
+    pub fn reset(&mut self) {
+        self.in_flight.clear();
+    }
+}
@@ -0,0 +1,251 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::{Context, Result};
+use breda::{
+    render_graph::{ExecutedRenderGraphSignalFenceWith, RenderGraph, RenderGraphPersistentStore},
+    renderer::{Device, QueueSubmitInfo, Texture, TextureCreateDesc},
+    shader_database::AssetsShaderDatabase,
+    shader_database_api::ShaderDatabaseAsset,
+    streaming_system::StreamingSystem,
+};
+use clap::Args;
+use image::DynamicImage;
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    camera::Camera,
+    fence_timeout::FenceTimeout,
+    init_streaming_system,
+    pixel_perfect::parse_resolution,
+    raytracer::{record_main_pass, MainPassBuffers, RaytracerSettings},
+    readback::{ReadbackFormat, TextureReadback},
+    render_graph_debug::{GraphDump, RenderGraphDebugOpts},
+    scene::{SceneDescription, TriangleScene},
+    CommandlineOpts,
+};
+
+pub const DEFAULT_HEADLESS_SIZE: [u32; 2] = [1280, 720];
+pub const DEFAULT_HEADLESS_FRAMES: u32 = 1;
+
+/// Output settings shared by the modes that render a sequence of frames without a window
+#[derive(Clone, Debug, Default, Args)]
+pub struct HeadlessOpts {
+    /// Render --frames frames of the regular view without a window, writing every frame to --out,
+    /// and exit
+    #[clap(long)]
+    pub headless: bool,
+    /// Number of frames to render
+    #[clap(long, value_name = "N")]
+    pub frames: Option<u32>,
+    /// Directory that rendered frames are written to as numbered PNGs
+    #[clap(
+        long,
+        alias = "output-dir",
+        value_name = "DIR",
+        default_value = "frames"
+    )]
+    pub out: PathBuf,
+    /// Resolution of the rendered frames
+    #[clap(long, value_name = "WIDTHxHEIGHT", value_parser = parse_resolution)]
+    pub headless_size: Option<[u32; 2]>,
+}
+
+impl HeadlessOpts {
+    pub fn size(&self) -> [u32; 2] {
+        self.headless_size.unwrap_or(DEFAULT_HEADLESS_SIZE)
+    }
+
+    /// Path of the `index`th frame in [`Self::out`]
+    pub fn frame_path(&self, index: u32) -> PathBuf {
+        self.out.join(format!("frame_{index:04}.png"))
+    }
+}
+
+/// Renders frames into an offscreen target instead of a swapchain and reads them back to the CPU
+pub struct HeadlessRenderer {
+    device: Arc<dyn Device>,
+    target: Texture,
+    readback: TextureReadback,
+    render_graph_persistent_store: Option<RenderGraphPersistentStore>,
+    main_pass_buffers: MainPassBuffers,
+    render_graph_debug: RenderGraphDebugOpts,
+    /// Writes the first frame's graph with --dump-graph
+    graph_dump: GraphDump,
+    fence_timeout: FenceTimeout,
+}
+
+impl HeadlessRenderer {
+    pub fn new(
+        device: &Arc<dyn Device>,
+        size: [u32; 2],
+        format: ReadbackFormat,
+        render_graph_debug: &RenderGraphDebugOpts,
+        fence_timeout: FenceTimeout,
+    ) -> Self {
+        let target = device.create_texture(
+            "headless render target",
+            &TextureCreateDesc::render_target_2d(size, format.texture_format()),
+        );
+        log_allocation(
+            ResourceKind::Texture,
+            "headless render target",
+            size[0] as usize * size[1] as usize * format.bytes_per_pixel(),
+            MemoryKind::DeviceLocal,
+        );
+        let readback = TextureReadback::new(device.as_ref(), "headless readback", size, format);
+
+        Self {
+            device: device.clone(),
+            target,
+            readback,
+            render_graph_persistent_store: Some(RenderGraphPersistentStore::new(device.as_ref())),
+            main_pass_buffers: MainPassBuffers::new(device.as_ref(), fence_timeout),
+            render_graph_debug: render_graph_debug.clone(),
+            graph_dump: GraphDump::new(render_graph_debug),
+            fence_timeout,
+        }
+    }
+
+    /// Renders a single frame through the same passes as the windowed path and waits for the
+    /// result
+    pub fn render_frame(
+        &mut self,
+        streaming_system: &StreamingSystem,
+        scene: &TriangleScene,
+        settings: &RaytracerSettings,
+        camera: Option<&Camera>,
+    ) -> Result<DynamicImage> {
+        self.render_frame_impl(streaming_system, scene, settings, camera, None)
+    }
+
+    /// Like [`Self::render_frame()`], but clears the target to `fill` first instead of drawing over
+    /// whatever the previous frame left in it, so that pixels the passes never write show up
+    pub fn render_frame_over_fill(
+        &mut self,
+        streaming_system: &StreamingSystem,
+        scene: &TriangleScene,
+        settings: &RaytracerSettings,
+        camera: Option<&Camera>,
+        fill: [f32; 4],
+    ) -> Result<DynamicImage> {
+        self.render_frame_impl(streaming_system, scene, settings, camera, Some(fill))
+    }
+
+    fn render_frame_impl(
+        &mut self,
+        streaming_system: &StreamingSystem,
+        scene: &TriangleScene,
+        settings: &RaytracerSettings,
+        camera: Option<&Camera>,
+        fill: Option<[f32; 4]>,
+    ) -> Result<DynamicImage> {
+        let device = self.device.as_ref();
+        let queue = device.get_gfx_queue();
+
+        streaming_system.update();
+        let mut streaming_context = streaming_system.create_context(&self.device);
+        let shader_db = streaming_context
+            .load_versioned::<_, ShaderDatabaseAsset>(&streaming_system.get_shader_db_cid()?);
+
+        let shader_handle = shader_db.downgrade().upgrade().unwrap();
+        let shader_db = streaming_system
+            .assets
+            .borrow::<AssetsShaderDatabase>(&shader_handle)
+            .unwrap();
+
+        let mut render_graph = RenderGraph::new(
+            self.render_graph_persistent_store
+                .take()
+                .expect("Persistent store is returned after every frame"),
+        );
+        self.render_graph_debug.configure(&mut render_graph);
+
+        let target_rg = render_graph.import_texture(&self.target);
+        if let Some(fill) = fill {
+            render_graph.clear_texture(&target_rg, fill);
+        }
+        record_main_pass(
+            &mut render_graph,
+            &target_rg,
+            self.readback.size(),
+            scene,
+            &shader_db,
+            settings,
+            camera,
+            None,
+            None,
+            &mut self.main_pass_buffers,
+        )?;
+
+        let mut cmd = queue.lock().create_command_buffer();
+
+        let compiled_rg = render_graph.compile(&[&target_rg], None);
+        self.graph_dump.write_if_requested(&compiled_rg);
+        let (executed_rg, signal_fence) = compiled_rg.execute(device, &mut cmd);
+        self.render_graph_persistent_store = Some(executed_rg.release_store());
+
+        self.readback.record_copy(&mut cmd, &self.target)?;
+
+        let fence = queue.lock().submit(
+            vec![cmd],
+            QueueSubmitInfo::no_sync().with_render_graph_signal_fence(signal_fence),
+        );
+        self.main_pass_buffers.end_frame(fence.clone());
+        self.fence_timeout
+            .wait(&fence, "rendering a headless frame")?;
+
+        self.readback.read_image()
+    }
+}
+
+/// Renders frames with the camera the windowed path starts out with, so the images match what a
+/// window of the same size would show
+pub fn run_headless(
+    device: &Arc<dyn Device>,
+    streaming_system: &StreamingSystem,
+    opts: &CommandlineOpts,
+) -> Result<()> {
+    let fence_timeout = opts.fence_timeout.timeout();
+    let frames = opts
+        .headless
+        .frames
+        .unwrap_or(DEFAULT_HEADLESS_FRAMES)
+        .max(1);
+
+    std::fs::create_dir_all(&opts.headless.out).with_context(|| {
+        format!(
+            "Failed to create output directory `{}`",
+            opts.headless.out.display()
+        )
+    })?;
+
+    init_streaming_system(streaming_system, device, opts.startup.shader_db_timeout())?;
+    let scene = TriangleScene::new(
+        device.as_ref(),
+        &opts.scene,
+        fence_timeout,
+        &SceneDescription::from_opts(&opts.scene)?,
+    )?;
+
+    let mut renderer = HeadlessRenderer::new(
+        device,
+        opts.headless.size(),
+        opts.readback.readback_format,
+        &opts.render_graph_debug,
+        fence_timeout,
+    );
+    let settings = RaytracerSettings::new(&opts.raytracer, &mut opts.seed.rng());
+    let mut camera = Camera::framing(&scene.bounds);
+    camera.set_viewport_size(opts.headless.size());
+
+    for frame in 0..frames {
+        let path = opts.headless.frame_path(frame);
+        renderer
+            .render_frame(streaming_system, &scene, &settings, Some(&camera))?
+            .save(&path)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    }
+
+    log::info!("Wrote {frames} frames to `{}`", opts.headless.out.display());
+    Ok(())
+}
@@ -0,0 +1,228 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use breda::{input::InputEvent, render_loop::v2::event::RenderLoopState};
+use clap::Args;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct InputRecordingOpts {
+    /// Write every input event of the window to this file, one JSON object per line, to be
+    /// played back with --replay. Unrelated to the GIFs of --replay-seconds.
+    #[clap(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Feed the input events recorded with --record to the camera and UI instead of the live
+    /// input, which is ignored until the recording ends
+    #[clap(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+}
+
+/// One line of a recording. Generic over the event only for the tests, which can't construct
+/// breda's [`InputEvent`]s.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct RecordedEvent<E = InputEvent> {
+    /// Index of the frame the event was handled in, counted from the first frame
+    frame: u64,
+    /// Seconds since the first frame, for reference only, playback goes by [`Self::frame`]
+    time: f32,
+    event: E,
+}
+
+/// Appends the events of every frame to a --record file
+pub struct InputRecorder {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    frame: u64,
+    start: Instant,
+    events: usize,
+}
+
+impl InputRecorder {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create input recording `{}`", path.display()))?;
+        log::info!("Recording input to `{}`", path.display());
+        Ok(Self {
+            path: path.to_owned(),
+            writer: BufWriter::new(file),
+            frame: 0,
+            start: Instant::now(),
+            events: 0,
+        })
+    }
+
+    fn frame(&mut self, events: &[InputEvent]) -> Result<()> {
+        let time = self.start.elapsed().as_secs_f32();
+        for event in events {
+            serde_json::to_writer(
+                &mut self.writer,
+                &RecordedEvent {
+                    frame: self.frame,
+                    time,
+                    event: event.clone(),
+                },
+            )?;
+            self.writer.write_all(b"\n")?;
+        }
+        self.events += events.len();
+        self.frame += 1;
+        Ok(())
+    }
+}
+
+impl Drop for InputRecorder {
+    fn drop(&mut self) {
+        match self.writer.flush() {
+            Ok(()) => log::info!(
+                "Recorded {} input events over {} frames to `{}`",
+                self.events,
+                self.frame,
+                self.path.display()
+            ),
+            Err(e) => log::error!(
+                "Failed to write input recording `{}`: {e}",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+/// Plays back a --record file frame by frame, so that the same frames see the same events
+/// regardless of how long they took. Movement that scales with frame time, like the fly camera's,
+/// still only repeats exactly with the same frame times.
+pub struct InputPlayer<E = InputEvent> {
+    /// In reverse, so that the next event is popped off the end
+    events: Vec<RecordedEvent<E>>,
+    frame: u64,
+}
+
+impl<E: DeserializeOwned> InputPlayer<E> {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open input recording `{}`", path.display()))?;
+        let events = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+            .map(|(i, line)| {
+                let line = line?;
+                serde_json::from_str::<RecordedEvent<E>>(&line)
+                    .with_context(|| format!("Malformed line {} of `{}`", i + 1, path.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        log::info!(
+            "Replaying {} input events over {:.1} s from `{}`",
+            events.len(),
+            events.last().map_or(0.0, |event| event.time),
+            path.display()
+        );
+        Ok(Self::from_events(events))
+    }
+}
+
+impl<E> InputPlayer<E> {
+    /// `events` in the order they were recorded
+    fn from_events(mut events: Vec<RecordedEvent<E>>) -> Self {
+        events.reverse();
+        Self { events, frame: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// The recorded events of the current frame
+    fn frame(&mut self) -> Vec<E> {
+        let mut events = Vec::new();
+        while self
+            .events
+            .last()
+            .is_some_and(|event| event.frame <= self.frame)
+        {
+            events.push(self.events.pop().expect("Checked by `last()`").event);
+        }
+        self.frame += 1;
+        if self.is_finished() {
+            log::info!("Input replay finished after {} frames", self.frame);
+        }
+        events
+    }
+}
+
+/// Recording or playing back the window's input, selected by --record and --replay
+pub enum InputTape {
+    Record(InputRecorder),
+    Play(InputPlayer),
+}
+
+impl InputTape {
+    pub fn new(opts: &InputRecordingOpts) -> Result<Option<Self>> {
+        Ok(if let Some(path) = &opts.record {
+            Some(Self::Record(InputRecorder::new(path)?))
+        } else if let Some(path) = &opts.replay {
+            Some(Self::Play(InputPlayer::new(path)?))
+        } else {
+            None
+        })
+    }
+
+    /// Call every frame before `state`'s events are applied. Records them, or replaces them with
+    /// the recorded ones for this frame. Returns whether the tape is done with, after which live
+    /// input is used again.
+    pub fn frame(&mut self, state: &mut RenderLoopState) -> Result<bool> {
+        match self {
+            Self::Record(recorder) => {
+                recorder.frame(state.events())?;
+                Ok(false)
+            }
+            Self::Play(player) => {
+                state.set_events(player.frame());
+                Ok(player.is_finished())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorded(frame: u64, event: u32) -> RecordedEvent<u32> {
+        RecordedEvent {
+            frame,
+            time: frame as f32 / 60.0,
+            event,
+        }
+    }
+
+    #[test]
+    fn recorded_events_round_trip() {
+        let event = recorded(3, 7);
+        let line = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RecordedEvent<u32>>(&line).unwrap(),
+            event
+        );
+    }
+
+    #[test]
+    fn playback_groups_events_by_frame() {
+        let mut player = InputPlayer::from_events(vec![
+            recorded(0, 1),
+            recorded(0, 2),
+            recorded(2, 3),
+            recorded(3, 4),
+        ]);
+        assert_eq!(player.frame(), [1, 2]);
+        assert_eq!(player.frame(), [] as [u32; 0]);
+        assert_eq!(player.frame(), [3]);
+        assert!(!player.is_finished());
+        assert_eq!(player.frame(), [4]);
+        assert!(player.is_finished());
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use breda::{
+    render_graph::{ComputePass, RenderGraph, RenderGraphBuffer},
+    renderer::BufferCreateDesc,
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+
+use crate::frame_ring::FrameRing;
+
+/// Mirrors `numthreads` in `instance_colors.cs.hlsl`
+const THREAD_GROUP_SIZE: u32 = 64;
+
+/// Every color is a `float4`, keeping the loads in the main pass aligned
+const COLOR_STRIDE: u64 = 16;
+
+/// Mirrors `InstanceColorConstants` in `instance_colors.cs.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+pub struct InstanceColorConstants {
+    instance_count: u32,
+    seed: u32,
+    _pad: [u32; 2],
+}
+
+/// Computes the color of every TLAS instance into a buffer that only lives in the render graph,
+/// for the instances shaded through [`crate::scene::HIT_GROUP_INSTANCE_COLOR`].
+///
+/// Nothing orders the compute pass before the main pass explicitly: the main pass reads the
+/// returned buffer, and the render graph schedules it after the pass that writes it.
+pub fn record_instance_colors(
+    render_graph: &mut RenderGraph,
+    instance_count: usize,
+    seed: u32,
+    constants: &mut FrameRing<InstanceColorConstants>,
+    shader_db: &AssetsShaderDatabase,
+) -> Result<RenderGraphBuffer> {
+    let constants_rg = render_graph.import_buffer(constants.write(&[InstanceColorConstants {
+        instance_count: instance_count as u32,
+        seed,
+        _pad: [0; 2],
+    }])?);
+
+    // An empty scene still gets a buffer to bind
+    let colors_rg = render_graph.create_buffer(
+        "instance colors",
+        &BufferCreateDesc::gpu_only_storage(),
+        instance_count.max(1) as u64 * COLOR_STRIDE,
+    );
+
+    ComputePass::new("Instance colors", render_graph)
+        .read_buffer(&constants_rg)
+        .rw_buffer(&colors_rg)
+        .dispatch(
+            &shader_db.get_pipeline("{{ template_id }}-compute"),
+            [(instance_count as u32).div_ceil(THREAD_GROUP_SIZE), 1, 1],
+        );
+
+    Ok(colors_rg)
+}
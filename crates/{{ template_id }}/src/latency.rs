@@ -0,0 +1,136 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use breda::{egui, renderer::Fence};
+use clap::Args;
+
+use crate::fence_timeout::FenceTimeout;
+
+/// Number of measurements averaged in the UI
+const SAMPLES: usize = 16;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct LatencyOpts {
+    /// Flash the screen when Space is pressed and measure how long it takes from processing the
+    /// input to the flash being rendered and handed to the presentation engine
+    #[clap(long)]
+    pub latency_test: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatencySample {
+    /// Input processed until the frame is submitted
+    pub cpu: Duration,
+    /// Submission until the GPU signals completion
+    pub gpu: Duration,
+    /// GPU completion until present returns
+    pub present: Duration,
+}
+
+impl LatencySample {
+    pub fn total(&self) -> Duration {
+        self.cpu + self.gpu + self.present
+    }
+}
+
+/// Measures input-to-present latency of a calibration flash, using CPU timestamps around the
+/// submission and a wait on its fence.
+///
+/// Input is only seen once the frame picks it up and scanout isn't observable from here, so the
+/// result is a lower bound on what a photodiode on the display would measure.
+pub struct LatencyTest {
+    fence_timeout: FenceTimeout,
+    input_at: Option<Instant>,
+    submitted_at: Option<Instant>,
+    gpu_done_at: Option<Instant>,
+    samples: Vec<LatencySample>,
+}
+
+impl LatencyTest {
+    pub fn new(opts: &LatencyOpts, fence_timeout: FenceTimeout) -> Option<Self> {
+        opts.latency_test.then(|| Self {
+            fence_timeout,
+            input_at: None,
+            submitted_at: None,
+            gpu_done_at: None,
+            samples: Vec::new(),
+        })
+    }
+
+    /// Starts a measurement, returns whether the current frame should show the flash
+    pub fn begin_frame(&mut self, triggered: bool) -> bool {
+        if triggered && self.input_at.is_none() {
+            self.input_at = Some(Instant::now());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Waits on the flash frame's submission, call right after submitting
+    pub fn submitted(&mut self, fence: &Fence) -> Result<()> {
+        if self.input_at.is_none() || self.submitted_at.is_some() {
+            return Ok(());
+        }
+        self.submitted_at = Some(Instant::now());
+        self.fence_timeout
+            .wait(fence, "rendering the latency test flash")?;
+        self.gpu_done_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Completes the measurement, call right after presenting
+    pub fn presented(&mut self) {
+        let (Some(input_at), Some(submitted_at), Some(gpu_done_at)) = (
+            self.input_at.take(),
+            self.submitted_at.take(),
+            self.gpu_done_at.take(),
+        ) else {
+            return;
+        };
+
+        let sample = LatencySample {
+            cpu: submitted_at - input_at,
+            gpu: gpu_done_at - submitted_at,
+            present: gpu_done_at.elapsed(),
+        };
+        log::info!(
+            "Latency: {:.2} ms (CPU {:.2} ms, GPU {:.2} ms, present {:.2} ms)",
+            sample.total().as_secs_f64() * 1000.0,
+            sample.cpu.as_secs_f64() * 1000.0,
+            sample.gpu.as_secs_f64() * 1000.0,
+            sample.present.as_secs_f64() * 1000.0
+        );
+
+        if self.samples.len() == SAMPLES {
+            self.samples.remove(0);
+        }
+        self.samples.push(sample);
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.label("Press Space to flash the screen");
+        let Some(last) = self.samples.last() else {
+            return;
+        };
+
+        let average = self
+            .samples
+            .iter()
+            .map(LatencySample::total)
+            .sum::<Duration>()
+            / self.samples.len() as u32;
+        ui.label(format!(
+            "Last: {:.2} ms (CPU {:.2}, GPU {:.2}, present {:.2})",
+            last.total().as_secs_f64() * 1000.0,
+            last.cpu.as_secs_f64() * 1000.0,
+            last.gpu.as_secs_f64() * 1000.0,
+            last.present.as_secs_f64() * 1000.0
+        ));
+        ui.label(format!(
+            "Average of {}: {:.2} ms",
+            self.samples.len(),
+            average.as_secs_f64() * 1000.0
+        ));
+    }
+}
@@ -1,296 +1,424 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 // Re-export or Android
 #[cfg(target_os = "android")]
 pub use android_activity::AndroidApp;
-use anyhow::{Context, Result};
+use anyhow::Result;
 use breda::{
-    egui::WindowSettings,
-    render_graph::{
-        ExecutedRenderGraphSignalFenceWith, RasterPass, RenderGraph, RenderGraphPersistentStore,
-    },
-    render_loop::v2::{
-        event::RenderLoopEvent,
-        opts::{BredaOpts, WindowOpts},
-    },
-    renderer::{
-        create_buffer_with_data, AccelerationStructureBuildLocation, BufferCreateDesc, BuildFlags,
-        Device, GeometryFlags, IndexBufferFormat, InstanceFlags, LoadOp, QueueSubmitInfo,
-        RaytracingInstanceDesc, StoreOp, TriangleGeometryCreateDesc, VertexFormat,
-    },
-    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+    render_loop::v2::opts::{BredaOpts, WindowOpts},
+    renderer::Device,
     shader_database_api::ShaderDatabaseAsset,
-    streaming_system::StreamingSystem,
+    streaming_system::{StreamingContext, StreamingSystem},
     workspace_recipe::WorkspaceBuildRecipe,
 };
 use clap::Parser;
 
+pub mod accumulation;
+pub mod affinity;
+pub mod alignment_grid;
+pub mod allocations;
+pub mod app_resources;
+pub mod app_state;
+pub mod background;
+pub mod benchmark_as;
+pub mod build_recipe;
+pub mod camera;
+pub mod camera_bookmarks;
+pub mod color_grading;
+pub mod color_mode;
+pub mod compare;
+pub mod crash_report;
+pub mod deferred;
+pub mod determinism;
+pub mod device_loss;
+pub mod diagnostics;
+pub mod dropped_frames;
 #[cfg(target_os = "android")]
 pub mod entry;
+pub mod error;
+pub mod fence_timeout;
+pub mod fog;
+pub mod frame_pacer;
+pub mod frame_ring;
+pub mod fullscreen;
+pub mod gltf_scene;
+pub mod gpu_select;
+pub mod gpu_timer;
+pub mod gpu_watchdog;
+pub mod headless;
+pub mod input_recording;
+pub mod instance_colors;
+pub mod latency;
+pub mod logging;
+pub mod math;
+pub mod memory_stats;
+pub mod mesh;
+pub mod msaa;
+pub mod obj;
+pub mod pixel_perfect;
+pub mod pixel_probe;
+pub mod present_mode;
+pub mod raytracer;
+pub mod raytracing_scene;
+pub mod readback;
+pub mod render_graph_debug;
+pub mod replay;
+pub mod repro;
+pub mod resize_stress;
+pub mod rng;
+pub mod scene;
+pub mod scene_json;
+pub mod screenshot;
+pub mod settings;
+pub mod shader_clock;
+pub mod shader_reload;
+pub mod startup;
+pub mod turntable;
+pub mod ui_scale;
+pub mod upload;
+pub mod user_uniforms;
+pub mod validation;
+pub mod viewport_preview;
+pub mod window_icon;
+
+use accumulation::AccumulationOpts;
+use affinity::AffinityOpts;
+use alignment_grid::AlignmentGridOpts;
+use allocations::AllocationLogOpts;
+use app_state::{AppState, AppStateOpts, FrameOutcome};
+use benchmark_as::BenchmarkAsOpts;
+use build_recipe::BuildRecipeOpts;
+use camera::CameraInputOpts;
+use color_mode::ColorModeOpts;
+use compare::CompareOpts;
+use determinism::DeterminismOpts;
+use diagnostics::DiagnosticsOpts;
+use dropped_frames::DroppedFrameOpts;
+use error::AppError;
+use fence_timeout::FenceTimeoutOpts;
+use frame_pacer::FramePacerOpts;
+use gpu_select::GpuSelectOpts;
+use gpu_timer::GpuTimerOpts;
+use headless::HeadlessOpts;
+use input_recording::InputRecordingOpts;
+use latency::LatencyOpts;
+use logging::LogOpts;
+use msaa::MsaaOpts;
+use pixel_perfect::PixelPerfectOpts;
+use pixel_probe::PixelProbeOpts;
+use present_mode::PresentModeOpts;
+use raytracer::{RaytracerOpts, RaytracerSettings};
+use readback::ReadbackOpts;
+use render_graph_debug::RenderGraphDebugOpts;
+use replay::ReplayOpts;
+use repro::ReproOpts;
+use resize_stress::ResizeStressOpts;
+use rng::SeedOpts;
+use scene::{SceneDescription, SceneOpts};
+use shader_clock::ShaderClockOpts;
+use startup::StartupOpts;
+use turntable::TurntableOpts;
+use ui_scale::UiScaleOpts;
+use user_uniforms::UserUniformOpts;
+use validation::ValidationOpts;
+use viewport_preview::ViewportPreviewOpts;
+use window_icon::WindowIconOpts;
+
+pub const DEFAULT_TITLE: &str = "{{ template_name }}";
 
 /// This app serves as an application template for other apps
 #[derive(Default, Parser)]
 pub struct CommandlineOpts {
+    /// Application name, used for the window title and screenshot file names
+    #[clap(long)]
+    pub title: Option<String>,
     #[clap(flatten)]
     pub breda: BredaOpts,
     #[clap(flatten)]
     pub window: WindowOpts,
+    #[clap(flatten)]
+    pub scene: SceneOpts,
+    #[clap(flatten)]
+    pub raytracer: RaytracerOpts,
+    #[clap(flatten)]
+    pub shader_clock: ShaderClockOpts,
+    #[clap(flatten)]
+    pub compare: CompareOpts,
+    #[clap(flatten)]
+    pub readback: ReadbackOpts,
+    #[clap(flatten)]
+    pub startup: StartupOpts,
+    #[clap(flatten)]
+    pub affinity: AffinityOpts,
+    #[clap(flatten)]
+    pub resize_stress: ResizeStressOpts,
+    #[clap(flatten)]
+    pub fence_timeout: FenceTimeoutOpts,
+    #[clap(flatten)]
+    pub pixel_perfect: PixelPerfectOpts,
+    #[clap(flatten)]
+    pub replay: ReplayOpts,
+    #[clap(flatten)]
+    pub diagnostics: DiagnosticsOpts,
+    #[clap(flatten)]
+    pub headless: HeadlessOpts,
+    #[clap(flatten)]
+    pub turntable: TurntableOpts,
+    #[clap(flatten)]
+    pub dropped_frames: DroppedFrameOpts,
+    #[clap(flatten)]
+    pub user_uniforms: UserUniformOpts,
+    #[clap(flatten)]
+    pub allocations: AllocationLogOpts,
+    #[clap(flatten)]
+    pub determinism: DeterminismOpts,
+    #[clap(flatten)]
+    pub latency: LatencyOpts,
+    #[clap(flatten)]
+    pub alignment_grid: AlignmentGridOpts,
+    #[clap(flatten)]
+    pub repro: ReproOpts,
+    #[clap(flatten)]
+    pub ui_scale: UiScaleOpts,
+    #[clap(flatten)]
+    pub render_graph_debug: RenderGraphDebugOpts,
+    #[clap(flatten)]
+    pub pixel_probe: PixelProbeOpts,
+    #[clap(flatten)]
+    pub gpu_timer: GpuTimerOpts,
+    #[clap(flatten)]
+    pub validation: ValidationOpts,
+    #[clap(flatten)]
+    pub frame_pacer: FramePacerOpts,
+    #[clap(flatten)]
+    pub color_mode: ColorModeOpts,
+    #[clap(flatten)]
+    pub log: LogOpts,
+    #[clap(flatten)]
+    pub gpu_select: GpuSelectOpts,
+    #[clap(flatten)]
+    pub viewport_preview: ViewportPreviewOpts,
+    #[clap(flatten)]
+    pub benchmark_as: BenchmarkAsOpts,
+    #[clap(flatten)]
+    pub build_recipe: BuildRecipeOpts,
+    #[clap(flatten)]
+    pub accumulation: AccumulationOpts,
+    #[clap(flatten)]
+    pub present_mode: PresentModeOpts,
+    #[clap(flatten)]
+    pub seed: SeedOpts,
+    #[clap(flatten)]
+    pub msaa: MsaaOpts,
+    #[clap(flatten)]
+    pub input_recording: InputRecordingOpts,
+    #[clap(flatten)]
+    pub window_icon: WindowIconOpts,
+    #[clap(flatten)]
+    pub camera_input: CameraInputOpts,
 }
 
-fn init_streaming_system(
-    streaming_system: &StreamingSystem,
-    device: &Arc<dyn Device>,
-) -> Result<()> {
-    let mut ctx = streaming_system.create_context(device);
-    let recipe = WorkspaceBuildRecipe {
+impl CommandlineOpts {
+    pub fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or(DEFAULT_TITLE)
+    }
+}
+
+/// Builds this crate's shaders for `device`'s preferred target, unless overridden by
+/// [`BuildRecipeOpts`]
+pub fn workspace_build_recipe(device: &dyn Device) -> WorkspaceBuildRecipe {
+    let mut recipe = WorkspaceBuildRecipe {
         root_crate_names: vec![env!("CARGO_PKG_NAME").to_string()],
 
         shader_compile_targets: vec![device.preferred_compile_target()],
         ..WorkspaceBuildRecipe::default_for_current_target_platform()
     };
-    streaming_system.build_workspace(recipe)?;
+    build_recipe::apply_overrides(&mut recipe, device);
+    recipe
+}
+
+/// How long [`wait_for_shader_db()`] sleeps between updates of the streaming system
+const SHADER_DB_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Builds the workspace for `device` and blocks until its shader database has been loaded, see
+/// [`wait_for_shader_db()`]
+pub fn init_streaming_system(
+    streaming_system: &StreamingSystem,
+    device: &Arc<dyn Device>,
+    shader_db_timeout: Duration,
+) -> Result<(), AppError> {
+    let _span = tracing::info_span!("init_streaming_system").entered();
+
+    let mut ctx = streaming_system.create_context(device);
+    tracing::info_span!("build_workspace")
+        .in_scope(|| streaming_system.build_workspace(workspace_build_recipe(device.as_ref())))
+        .map_err(|e| AppError::ShaderBuildFailed(e.into()))?;
+
+    let _shader_db = ctx.load_versioned::<_, ShaderDatabaseAsset>(
+        &streaming_system
+            .get_shader_db_cid()
+            .map_err(anyhow::Error::from)?,
+    );
 
-    let _shader_db =
-        ctx.load_versioned::<_, ShaderDatabaseAsset>(&streaming_system.get_shader_db_cid()?);
+    wait_for_shader_db(streaming_system, &ctx, shader_db_timeout)
+}
 
+/// Updates the streaming system until everything loaded through `ctx`, like the shader database,
+/// is ready. Sleeps between updates instead of spinning, and fails once `timeout` has elapsed so
+/// that a broken build doesn't hang the app.
+pub fn wait_for_shader_db(
+    streaming_system: &StreamingSystem,
+    ctx: &StreamingContext,
+    timeout: Duration,
+) -> Result<(), AppError> {
     let shader_db_poller = ctx.poller();
+    let start = Instant::now();
     loop {
         streaming_system.update();
 
         if shader_db_poller.is_ready() {
-            break;
+            return Ok(());
+        }
+        if start.elapsed() > timeout {
+            return Err(AppError::StreamingTimeout { timeout });
         }
+        std::thread::sleep(SHADER_DB_POLL_INTERVAL);
     }
-    Ok(())
 }
 
+/// Runs the mode picked by `opts`, until the window is closed for the interactive one
 pub fn internal_main(
     opts: &CommandlineOpts,
     #[cfg(target_os = "android")] android_app: AndroidApp,
+) -> Result<(), AppError> {
+    run(
+        opts,
+        #[cfg(target_os = "android")]
+        android_app,
+    )
+    .map_err(AppError::from)
+}
+
+fn run(
+    opts: &CommandlineOpts,
+    #[cfg(target_os = "android")] android_app: AndroidApp,
 ) -> Result<()> {
+    crash_report::install();
+    opts.log.install();
+
+    let mut breda_desc = opts.breda.into();
+    let mut validation = opts.validation.clone();
+    // --resize-stress fails on validation errors, which are only reported with the layers enabled
+    validation.validation |= opts.resize_stress.resize_stress;
+    validation.apply(&mut breda_desc);
+
     let mut breda = breda::Breda::new(
-        "{{ template_name }}",
-        opts.breda.into(),
+        opts.title(),
+        breda_desc,
         #[cfg(target_os = "android")]
         android_app,
     )?;
 
-    let device_arc = breda
+    let rt_devices = breda
         .devices()
-        .find(|d| d.capabilities().supports_inline_ray_tracing)
-        .context("No device found that supports inline raytracing")?
-        .clone();
+        .filter(|d| d.capabilities().supports_inline_ray_tracing)
+        .cloned()
+        .collect::<Vec<_>>();
+    let selected_device = opts
+        .gpu_select
+        .select(&breda.devices().cloned().collect::<Vec<_>>())?;
+
+    if opts.diagnostics.rt_features {
+        diagnostics::print_rt_features(breda.devices(), selected_device.as_ref());
+        return Ok(());
+    }
+
+    opts.allocations.install();
+
+    if let Some(dir) = &opts.repro.export_repro {
+        return repro::export_repro(
+            dir,
+            &SceneDescription::from_opts(&opts.scene)?,
+            &RaytracerSettings::new(&opts.raytracer, &mut opts.seed.rng()),
+        );
+    }
+
+    let device_arc = selected_device.ok_or(AppError::NoSuitableDevice)?;
+    crash_report::set_device(device_arc.as_ref());
+    let driver_info = device_arc.driver_info();
+    log::info!(
+        "Rendering on {} {}",
+        driver_info.vendor,
+        driver_info.device_name
+    );
+    opts.build_recipe.install(device_arc.as_ref())?;
     let streaming_system = breda.streaming_system();
 
-    breda.render_loop().run_closure(
-        opts.window.into_desc(
-            "{{ template_name }}",
-            breda::renderer::SwapchainColorMode::ForceSrgb8Bit,
-            true,
-        ),
-        &mut breda_app_support::EguiInputStateHandler::new_auto_size(),
-        move |mut event_receiver, event_sender| -> Result<()> {
-            let device = device_arc.as_ref();
-            let queue = device.get_gfx_queue();
-
-            init_streaming_system(&streaming_system, &device_arc)?;
-
-            let mut render_graph_persistent_store = RenderGraphPersistentStore::new(device);
-
-            let positions = vec![
-                [100.0f32, 100.1f32, 100.0f32],
-                [200.0f32, 100.2f32, 3.1f32],
-                [302.0f32, 403.0f32, 3.2f32],
-            ];
-            let position_buffer = create_buffer_with_data(
-                device,
-                "inline position buffer",
-                &BufferCreateDesc::gpu_only_storage(),
-                &positions,
-            );
+    if let Some(reference) = &opts.compare.compare {
+        return compare::run_headless_compare(&device_arc, &streaming_system, opts, reference);
+    }
 
-            let indices = vec![0, 1, 2];
-            let index_buffer = create_buffer_with_data(
-                device,
-                "inline index buffer",
-                &BufferCreateDesc::gpu_only_storage(),
-                &indices,
-            );
+    if let Some(triangles) = opts.benchmark_as.benchmark_as {
+        return benchmark_as::run_benchmark_as(&device_arc, opts, triangles);
+    }
 
-            let vertex_format = VertexFormat::R32g32b32Sfloat;
-            let geometry = device.create_tri_geometry(
-                "inline tri geom",
-                &position_buffer,
-                Some(&index_buffer),
-                None,
-                &TriangleGeometryCreateDesc {
-                    vertex_format,
-                    vertex_offset_in_bytes: 0,
-                    vertex_count: positions.len(),
-                    vertex_stride_in_bytes: vertex_format.size_in_bytes(),
-                    index_format: Some(IndexBufferFormat::Uint32),
-                    index_offset_in_bytes: 0,
-                    index_count: indices.len(),
-                    transform_offset_in_bytes: 0,
-                    geometry_flags: GeometryFlags::empty(),
-                    build_location: AccelerationStructureBuildLocation::Device,
-                },
-                None,
-            );
+    if opts.turntable.turntable {
+        return turntable::run_turntable(&device_arc, &streaming_system, opts);
+    }
 
-            let mat4x3 = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
-
-            let mut cmd = queue.lock().create_command_buffer();
-
-            // Note: keep the blas alive, otherwise the buffer and handle will be freed when it goes out of scope
-            let (acceleration_structure, _blas) = {
-                let blas_request = device.create_blas_build_request(
-                    AccelerationStructureBuildLocation::Device,
-                    BuildFlags::FAST_TRACE,
-                    &[geometry],
-                    "blas",
-                );
-                let blas_handle = unsafe {
-                    blas_request
-                        .blas
-                        .blas_handle(AccelerationStructureBuildLocation::Device)
-                };
-                let instance = RaytracingInstanceDesc::new(
-                    mat4x3,
-                    0u32,
-                    0xff,
-                    0,
-                    InstanceFlags::TRIANGLE_CULL_DISABLE,
-                    blas_handle,
-                );
-                let tlas_request = device.create_tlas_build_request_from_instances(
-                    AccelerationStructureBuildLocation::Device,
-                    BuildFlags::FAST_BUILD,
-                    &[instance],
-                    "tlas",
-                );
-
-                let scratch = device.create_buffer(
-                    "acceleration_structure_scratch",
-                    u64::max(
-                        blas_request
-                            .build_info
-                            .size_requirements()
-                            .scratch_size_in_bytes,
-                        tlas_request
-                            .build_info
-                            .size_requirements()
-                            .scratch_size_in_bytes,
-                    ) as usize,
-                    &BufferCreateDesc::gpu_only_scratch_build(),
-                );
-
-                let mut as_enc = cmd.acceleration_structure_encoder();
-                let blas = blas_request.blas.clone();
-                as_enc.batch_build_bottom_level(&[blas_request], &scratch);
-                as_enc.build_top_level(&tlas_request, &scratch);
-                cmd.end_acceleration_structure(as_enc);
-
-                (tlas_request.tlas, blas)
-            };
-
-            let mut egui_renderer = breda::egui::Renderer::new(device);
-
-            let fence = queue.lock().submit(vec![cmd], QueueSubmitInfo::no_sync());
-            fence.wait_for_idle();
-
-            let mut input_processor = breda::input::InputProvider::default();
-
-            while let Ok(RenderLoopEvent {
-                swapchain,
-                swapchain_sync,
-                present_index,
-                state,
-            }) = event_receiver.receive(&device_arc, &queue)
-            {
-                let egui = state.apply(&mut input_processor);
-
-                streaming_system.update();
-                let mut streaming_context = streaming_system.create_context(&device_arc);
-                let shader_db = streaming_context.load_versioned::<_, ShaderDatabaseAsset>(
-                    &streaming_system.get_shader_db_cid()?,
-                );
-
-                let shader_handle = shader_db.downgrade().upgrade().unwrap();
-                let shader_db = streaming_system
-                    .assets
-                    .borrow::<AssetsShaderDatabase>(&shader_handle)
-                    .unwrap();
-
-                let mut render_graph = RenderGraph::new(render_graph_persistent_store);
-
-                let present_image = swapchain.present_image(present_index);
-                let present_image_rg = render_graph.import_texture(&present_image);
-
-                let tlas = render_graph.import_tlas(&acceleration_structure);
-                let pipeline = shader_db.get_pipeline("{{ template_id }}-raytracer");
-                RasterPass::new("Main pass", &mut render_graph)
-                    .render_target(&present_image_rg, LoadOp::Discard, StoreOp::Store)
-                    .tlas(&tlas)
-                    .draw(&pipeline, 6, 1);
-
-                if let Some(ctx) = &egui {
-                    ctx.window(
-                        "Current GPU",
-                        &mut true,
-                        &WindowSettings::from_window_size([500.0, 140.0]),
-                        |ui| {
-                            let driver_info = device.driver_info();
-                            ui.label(format!(
-                                "{} {}",
-                                driver_info.vendor, driver_info.device_name
-                            ));
-                            ui.label(format!(
-                                "{} `{}` @ {}",
-                                driver_info.driver_id,
-                                driver_info.driver_name,
-                                driver_info.version.map_or_else(
-                                    || "Invalid driver version".to_string(),
-                                    |v| v.to_string()
-                                )
-                            ));
-                            if !driver_info.driver_extra_info.is_empty() {
-                                ui.label(driver_info.driver_extra_info);
-                            }
-                            if let Some(shader_compiler_version) =
-                                driver_info.shader_compiler_version
-                            {
-                                ui.small(format!(
-                                    "Driver shader compiler: {shader_compiler_version}"
-                                ));
-                            }
-                        },
-                    );
-                }
+    if opts.determinism.determinism_check {
+        return determinism::run_determinism_check(&device_arc, &streaming_system, opts);
+    }
 
-                let mut cmd = queue.lock().create_command_buffer();
+    if opts.headless.headless {
+        return headless::run_headless(&device_arc, &streaming_system, opts);
+    }
 
-                // compile and execute render graph
-                let compiled_rg = render_graph.compile(&[&present_image_rg], None);
-                let (executed_rg, signal_fence) = compiled_rg.execute(device, &mut cmd);
+    let app_state_opts = AppStateOpts::new(opts);
+    let affinity_opts = opts.affinity.clone();
 
-                render_graph_persistent_store = executed_rg.release_store();
+    let mut window_desc = opts.window.into_desc(
+        opts.title(),
+        opts.color_mode.color_mode.swapchain_color_mode(),
+        true,
+    );
+    // Unsupported modes are replaced by FIFO once the first frame sees the swapchain
+    window_desc.present_mode = opts.present_mode.present_mode.swapchain_present_mode();
+    opts.window_icon.apply(&mut window_desc)?;
 
-                if let Some(mut ctx) = egui {
-                    let (platform_output, render_input) = ctx.end_frame();
-                    event_sender.send(platform_output);
-                    egui_renderer.render(device, &mut cmd, None, &present_image, render_input);
-                }
+    breda.render_loop().run_closure(
+        window_desc,
+        &mut breda_app_support::EguiInputStateHandler::new_auto_size(),
+        move |mut event_receiver, event_sender| -> Result<()> {
+            affinity_opts.apply_to_current_thread();
+
+            let mut app_state =
+                AppState::new(&device_arc, &streaming_system, rt_devices, &app_state_opts)?;
 
-                let _fence = queue.lock().submit(
-                    vec![cmd],
-                    QueueSubmitInfo::swapchain_only_sync(swapchain_sync)
-                        .with_render_graph_signal_fence(signal_fence),
-                );
-                let present_status = swapchain.present(&queue, present_index, swapchain_sync);
-                event_receiver.with_status(present_status);
+            loop {
+                let device = app_state.device().clone();
+                let queue = device.get_gfx_queue();
+                let Ok(event) = event_receiver.receive(&device, &queue) else {
+                    break;
+                };
+
+                match app_state.render_frame(event, &mut event_receiver, &event_sender)? {
+                    FrameOutcome::Continue => {}
+                    FrameOutcome::Exit => break,
+                }
             }
 
+            log::info!(
+                "{} dropped frames in total",
+                app_state.dropped_frames().total()
+            );
+            // Only reached on a clean exit, errors return before this
+            if let Err(e) = app_state.settings().save() {
+                log::warn!("Failed to save settings: {e:?}");
+            }
             Ok(())
         },
     )?
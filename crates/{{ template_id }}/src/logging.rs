@@ -0,0 +1,80 @@
+use clap::{Args, ValueEnum};
+
+/// Most verbose level that is logged
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    /// Everything, including spans around every frame's render graph compile and execute
+    Trace,
+    Debug,
+    #[default]
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    fn tracing_level(self) -> tracing::Level {
+        match self {
+            Self::Trace => tracing::Level::TRACE,
+            Self::Debug => tracing::Level::DEBUG,
+            Self::Info => tracing::Level::INFO,
+            Self::Warn => tracing::Level::WARN,
+            Self::Error => tracing::Level::ERROR,
+        }
+    }
+
+    fn log_level(self) -> log::LevelFilter {
+        match self {
+            Self::Trace => log::LevelFilter::Trace,
+            Self::Debug => log::LevelFilter::Debug,
+            Self::Info => log::LevelFilter::Info,
+            Self::Warn => log::LevelFilter::Warn,
+            Self::Error => log::LevelFilter::Error,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct LogOpts {
+    /// Most verbose level to log, of both `log` records and `tracing` spans
+    #[clap(long, value_enum, default_value_t)]
+    pub log_level: LogLevel,
+}
+
+impl LogOpts {
+    /// Installs a `tracing` subscriber that also receives everything logged through `log`. Spans
+    /// log how long they took when they close, so slow startup steps show up in the log.
+    ///
+    /// On Android everything goes to logcat instead of stdout. Call before creating
+    /// [`breda::Breda`], if a logger was installed already only its level is changed.
+    pub fn install(&self) {
+        let level = self.log_level.tracing_level();
+
+        #[cfg(not(target_os = "android"))]
+        let result = tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .try_init();
+
+        #[cfg(target_os = "android")]
+        let result = install_logcat(level);
+
+        if let Err(e) = result {
+            log::set_max_level(self.log_level.log_level());
+            log::warn!("Failed to install the tracing subscriber, spans are not logged: {e}");
+        }
+    }
+}
+
+#[cfg(target_os = "android")]
+fn install_logcat(level: tracing::Level) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use tracing_subscriber::{
+        filter::LevelFilter, layer::SubscriberExt as _, util::SubscriberInitExt as _,
+    };
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(level))
+        .with(tracing_android::layer(env!("CARGO_PKG_NAME"))?)
+        .try_init()?;
+    Ok(())
+}
@@ -0,0 +1,218 @@
+/// Row-major 3x4 transform of a TLAS instance, as taken by `RaytracingInstanceDesc::new()`. The
+/// last column holds the translation and the implicit fourth row is `[0, 0, 0, 1]`.
+pub type Transform = [f32; 12];
+
+pub const IDENTITY: Transform = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+pub fn translation([x, y, z]: [f32; 3]) -> Transform {
+    [1.0, 0.0, 0.0, x, 0.0, 1.0, 0.0, y, 0.0, 0.0, 1.0, z]
+}
+
+/// Counter-clockwise around +X when looking down it, by `angle` radians
+pub fn rotation_x(angle: f32) -> Transform {
+    let (sin, cos) = angle.sin_cos();
+    [1.0, 0.0, 0.0, 0.0, 0.0, cos, -sin, 0.0, 0.0, sin, cos, 0.0]
+}
+
+/// Counter-clockwise around +Y when looking down it, by `angle` radians
+pub fn rotation_y(angle: f32) -> Transform {
+    let (sin, cos) = angle.sin_cos();
+    [cos, 0.0, sin, 0.0, 0.0, 1.0, 0.0, 0.0, -sin, 0.0, cos, 0.0]
+}
+
+/// Counter-clockwise around +Z when looking down it, by `angle` radians
+pub fn rotation_z(angle: f32) -> Transform {
+    let (sin, cos) = angle.sin_cos();
+    [cos, -sin, 0.0, 0.0, sin, cos, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+}
+
+pub fn scale([x, y, z]: [f32; 3]) -> Transform {
+    [x, 0.0, 0.0, 0.0, 0.0, y, 0.0, 0.0, 0.0, 0.0, z, 0.0]
+}
+
+pub fn transform_point(transform: &Transform, p: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|row| {
+        let m = &transform[row * 4..row * 4 + 4];
+        m[0] * p[0] + m[1] * p[1] + m[2] * p[2] + m[3]
+    })
+}
+
+/// The inverse transpose of the linear part of `transform` applied to `n`, normalized. Keeps
+/// normals perpendicular to their surface under non-uniform and mirroring scales too.
+pub fn transform_normal(transform: &Transform, n: [f32; 3]) -> [f32; 3] {
+    let row = |i: usize| [transform[i * 4], transform[i * 4 + 1], transform[i * 4 + 2]];
+    // The inverse transpose is the cofactor matrix divided by the determinant
+    let cofactors = [
+        cross(row(1), row(2)),
+        cross(row(2), row(0)),
+        cross(row(0), row(1)),
+    ];
+    let sign = dot(row(0), cofactors[0]).signum();
+    normalize(std::array::from_fn(|i| dot(cofactors[i], n) * sign))
+}
+
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    std::array::from_fn(|i| a[i] - b[i])
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (0..3).map(|i| a[i] * b[i]).sum()
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Zero vectors are returned as they are
+pub fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        v.map(|c| c / length)
+    } else {
+        v
+    }
+}
+
+/// Row-major 4x4 matrix, applied as `mul(m, v)` in HLSL. Used for projections, which don't fit
+/// a [`Transform`].
+pub type Mat4 = [[f32; 4]; 4];
+
+/// Applies `b` first, then `a`, like [`mul()`]
+pub fn mul_mat4(a: &Mat4, b: &Mat4) -> Mat4 {
+    std::array::from_fn(|row| {
+        std::array::from_fn(|col| (0..4).map(|i| a[row][i] * b[i][col]).sum())
+    })
+}
+
+/// Applies `b` first, then `a`
+pub fn mul(a: &Transform, b: &Transform) -> Transform {
+    std::array::from_fn(|i| {
+        let (row, col) = (i / 4, i % 4);
+        let linear = (0..3).map(|k| a[row * 4 + k] * b[k * 4 + col]).sum::<f32>();
+        if col == 3 {
+            linear + a[row * 4 + 3]
+        } else {
+            linear
+        }
+    })
+}
+
+/// Scales by `s`, then rotates by `r`, then translates by `t`
+pub fn compose(t: &Transform, r: &Transform, s: &Transform) -> Transform {
+    mul(t, &mul(r, s))
+}
+
+/// `transform` applied around `center` instead of the origin
+pub fn around(center: [f32; 3], transform: &Transform) -> Transform {
+    mul(
+        &translation(center),
+        &mul(transform, &translation(center.map(|c| -c))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (actual[i] - expected[i]).abs() < 1e-5),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    #[test]
+    fn translation_offsets_points() {
+        assert_close(
+            transform_point(&translation([1.0, 2.0, 3.0]), [4.0, 5.0, 6.0]),
+            [5.0, 7.0, 9.0],
+        );
+    }
+
+    #[test]
+    fn rotations_are_counter_clockwise() {
+        assert_close(
+            transform_point(&rotation_x(FRAC_PI_2), [0.0, 1.0, 0.0]),
+            [0.0, 0.0, 1.0],
+        );
+        assert_close(
+            transform_point(&rotation_y(FRAC_PI_2), [0.0, 0.0, 1.0]),
+            [1.0, 0.0, 0.0],
+        );
+        assert_close(
+            transform_point(&rotation_z(FRAC_PI_2), [1.0, 0.0, 0.0]),
+            [0.0, 1.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn scale_is_per_axis() {
+        assert_close(
+            transform_point(&scale([2.0, 3.0, 4.0]), [1.0, 1.0, 1.0]),
+            [2.0, 3.0, 4.0],
+        );
+    }
+
+    #[test]
+    fn mul_applies_right_first() {
+        let t = translation([1.0, 0.0, 0.0]);
+        let s = scale([2.0, 2.0, 2.0]);
+        // Scaled then translated
+        assert_close(
+            transform_point(&mul(&t, &s), [1.0, 0.0, 0.0]),
+            [3.0, 0.0, 0.0],
+        );
+        // Translated then scaled
+        assert_close(
+            transform_point(&mul(&s, &t), [1.0, 0.0, 0.0]),
+            [4.0, 0.0, 0.0],
+        );
+        assert_eq!(mul(&IDENTITY, &t), t);
+    }
+
+    #[test]
+    fn compose_scales_rotates_then_translates() {
+        let transform = compose(
+            &translation([10.0, 0.0, 0.0]),
+            &rotation_z(FRAC_PI_2),
+            &scale([2.0, 2.0, 2.0]),
+        );
+        assert_close(
+            transform_point(&transform, [1.0, 0.0, 0.0]),
+            [10.0, 2.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn around_keeps_the_center_in_place() {
+        let center = [5.0, 5.0, 0.0];
+        let transform = around(center, &rotation_z(FRAC_PI_2));
+        assert_close(transform_point(&transform, center), center);
+        assert_close(
+            transform_point(&transform, [6.0, 5.0, 0.0]),
+            [5.0, 6.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn mul_mat4_matches_mul() {
+        let to_mat4 = |t: &Transform| -> Mat4 {
+            std::array::from_fn(|row| {
+                if row < 3 {
+                    std::array::from_fn(|col| t[row * 4 + col])
+                } else {
+                    [0.0, 0.0, 0.0, 1.0]
+                }
+            })
+        };
+        let a = rotation_x(0.3);
+        let b = translation([1.0, 2.0, 3.0]);
+        assert_eq!(mul_mat4(&to_mat4(&a), &to_mat4(&b)), to_mat4(&mul(&a, &b)));
+    }
+}
@@ -0,0 +1,73 @@
+use breda::{egui, renderer::Device};
+
+/// Bytes of device memory held by a [`crate::scene::TriangleScene`], see
+/// [`crate::scene::TriangleScene::memory_stats()`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStats {
+    pub position_buffer: usize,
+    pub normal_buffer: usize,
+    pub index_buffer: usize,
+    pub instance_transforms: usize,
+    pub blas: usize,
+    pub tlas: usize,
+    /// Shared by all acceleration structure builds and kept for TLAS updates
+    pub scratch: usize,
+}
+
+impl MemoryStats {
+    pub fn total(&self) -> usize {
+        self.position_buffer
+            + self.normal_buffer
+            + self.index_buffer
+            + self.instance_transforms
+            + self.blas
+            + self.tlas
+            + self.scratch
+    }
+
+    /// Lists every allocation and the total, followed by all memory allocated on the device and
+    /// the budget, when the driver reports one
+    pub fn ui(&self, ui: &mut egui::Ui, device: &dyn Device) {
+        egui::Grid::new("memory stats").show(ui, |ui| {
+            for (name, bytes) in [
+                ("Position buffer", self.position_buffer),
+                ("Normal buffer", self.normal_buffer),
+                ("Index buffer", self.index_buffer),
+                ("Instance transforms", self.instance_transforms),
+                ("BLAS", self.blas),
+                ("TLAS", self.tlas),
+                ("Scratch", self.scratch),
+            ] {
+                ui.label(name);
+                ui.label(format_mib(bytes as u64));
+                ui.end_row();
+            }
+            ui.strong("Total");
+            ui.strong(format_mib(self.total() as u64));
+            ui.end_row();
+        });
+
+        // Everything on the device, including render targets, swapchain images and egui
+        let allocated = device.allocated_memory_bytes();
+        ui.separator();
+        match device.memory_budget_bytes() {
+            Some(budget) => {
+                ui.label(format!(
+                    "Device: {} / {} budget",
+                    format_mib(allocated),
+                    format_mib(budget)
+                ));
+                ui.add(egui::ProgressBar::new(
+                    allocated as f32 / budget.max(1) as f32,
+                ));
+            }
+            None => {
+                ui.label(format!("Device: {} allocated", format_mib(allocated)));
+            }
+        }
+    }
+}
+
+fn format_mib(bytes: u64) -> String {
+    format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::HashMap,
+    f32::consts::{PI, TAU},
+};
+
+use crate::math;
+
+/// Cosine of the largest angle between the normals of vertices that [`Mesh::weld()`] merges,
+/// about 8 degrees. Vertices on either side of a hard edge keep their own normals.
+const WELD_MIN_NORMAL_COS: f32 = 0.99;
+
+/// Axis-aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// An inverted box that any point or box can be merged into
+    pub const EMPTY: Self = Self {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a [f32; 3]>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| Self {
+            min: std::array::from_fn(|i| aabb.min[i].min(p[i])),
+            max: std::array::from_fn(|i| aabb.max[i].max(p[i])),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.min[i] > self.max[i])
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: std::array::from_fn(|i| self.min[i].min(other.min[i])),
+            max: std::array::from_fn(|i| self.max[i].max(other.max[i])),
+        }
+    }
+
+    pub fn center(&self) -> [f32; 3] {
+        std::array::from_fn(|i| (self.min[i] + self.max[i]) * 0.5)
+    }
+
+    /// Radius of the sphere around [`Self::center()`] enclosing the box
+    pub fn radius(&self) -> f32 {
+        (0..3)
+            .map(|i| ((self.max[i] - self.min[i]) * 0.5).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /// Bounds of this box after applying a row-major 3x4 transform to its corners
+    pub fn transformed(&self, transform: &[f32; 12]) -> Self {
+        let corners = (0..8).map(|corner| {
+            let p: [f32; 3] = std::array::from_fn(|i| {
+                if corner & (1 << i) == 0 {
+                    self.min[i]
+                } else {
+                    self.max[i]
+                }
+            });
+            std::array::from_fn(|row| {
+                let m = &transform[row * 4..row * 4 + 4];
+                m[0] * p[0] + m[1] * p[1] + m[2] * p[2] + m[3]
+            })
+        });
+        Self::from_points(&corners.collect::<Vec<[f32; 3]>>())
+    }
+}
+
+/// CPU-side indexed triangle mesh, before it is uploaded for acceleration structure builds
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Unit normal of every vertex, or empty when the source has none, see
+    /// [`Self::ensure_normals()`]
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Latitude/longitude sphere around the origin with `subdivisions` rings and twice as many
+    /// segments per ring
+    pub fn sphere(radius: f32, subdivisions: u32) -> Self {
+        let rings = subdivisions.max(2);
+        let segments = rings * 2;
+
+        Self::parametric(rings, segments, |v, u| {
+            let (theta, phi) = (v * PI, u * TAU);
+            [
+                radius * theta.sin() * phi.cos(),
+                radius * theta.cos(),
+                radius * theta.sin() * phi.sin(),
+            ]
+        })
+    }
+
+    /// Torus around the Y axis through the origin, with `subdivisions` segments around the tube
+    /// and twice as many around the ring
+    pub fn torus(major_radius: f32, minor_radius: f32, subdivisions: u32) -> Self {
+        let sides = subdivisions.max(3);
+        let segments = sides * 2;
+
+        Self::parametric(sides, segments, |v, u| {
+            let (theta, phi) = (v * TAU, u * TAU);
+            let ring = major_radius + minor_radius * theta.cos();
+            [
+                ring * phi.cos(),
+                minor_radius * theta.sin(),
+                ring * phi.sin(),
+            ]
+        })
+    }
+
+    /// Square of `size` facing the camera (in the XY plane) through the origin, split into a grid
+    /// of `subdivisions` by `subdivisions` quads
+    pub fn plane(size: f32, subdivisions: u32) -> Self {
+        let quads = subdivisions.max(1);
+
+        Self::parametric(quads, quads, |v, u| {
+            [(u - 0.5) * size, (v - 0.5) * size, 0.0]
+        })
+    }
+
+    /// Tessellates a `position(v, u)` surface over `[0, 1]^2` into a grid of `rows` by `columns`
+    /// quads. Seam vertices are duplicated, [`Self::weld()`] can merge them.
+    fn parametric(rows: u32, columns: u32, position: impl Fn(f32, f32) -> [f32; 3]) -> Self {
+        let positions = (0..=rows)
+            .flat_map(|row| (0..=columns).map(move |column| (row, column)))
+            .map(|(row, column)| position(row as f32 / rows as f32, column as f32 / columns as f32))
+            .collect();
+
+        let stride = columns + 1;
+        let indices = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| row * stride + column))
+            .flat_map(|i| [i, i + stride, i + 1, i + 1, i + stride, i + stride + 1])
+            .collect();
+
+        Self {
+            positions,
+            normals: vec![],
+            indices,
+        }
+    }
+
+    /// Moves every vertex by `offset`
+    pub fn translate(&mut self, offset: [f32; 3]) {
+        for p in &mut self.positions {
+            *p = std::array::from_fn(|i| p[i] + offset[i]);
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(&self.positions)
+    }
+
+    /// Gives meshes without normals flat ones: every triangle gets three vertices of its own that
+    /// share its face normal. Returns whether normals had to be computed.
+    ///
+    /// The raytracer flips normals to face the ray, so the winding of the triangles doesn't
+    /// matter. Degenerate triangles get a zero normal.
+    pub fn ensure_normals(&mut self) -> bool {
+        if !self.normals.is_empty() {
+            return false;
+        }
+
+        let mut positions = Vec::with_capacity(self.indices.len());
+        let mut normals = Vec::with_capacity(self.indices.len());
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| self.positions[triangle[i] as usize]);
+            let edge = |p: [f32; 3]| std::array::from_fn(|i| p[i] - a[i]);
+            positions.extend([a, b, c]);
+            normals.extend([math::normalize(math::cross(edge(b), edge(c))); 3]);
+        }
+        self.indices = (0..positions.len() as u32).collect();
+        self.positions = positions;
+        self.normals = normals;
+        true
+    }
+
+    /// Merges vertices that lie within `epsilon` of each other and remaps the indices to the
+    /// surviving vertices. With an `epsilon` of zero only bit-identical positions are merged.
+    /// Vertices with normals further apart than [`WELD_MIN_NORMAL_COS`] are never merged, so
+    /// welding doesn't smooth over hard edges.
+    ///
+    /// Vertices are bucketed in a grid with cells of `epsilon` size, so every candidate lies in
+    /// one of the 27 cells surrounding a vertex. Whichever vertex comes first in a cluster is kept,
+    /// along with its normal.
+    pub fn weld(&self, epsilon: f32) -> Self {
+        let mut positions = Vec::<[f32; 3]>::new();
+        let mut remap = Vec::with_capacity(self.positions.len());
+        // Index of the vertex each surviving one was copied from
+        let mut kept = Vec::<usize>::new();
+        let normals_match = |a: usize, b: usize| {
+            self.normals.is_empty()
+                || math::dot(self.normals[a], self.normals[b]) >= WELD_MIN_NORMAL_COS
+        };
+
+        if epsilon <= 0.0 {
+            let mut unique = HashMap::<[u32; 3], Vec<u32>>::new();
+            for (vertex, p) in self.positions.iter().enumerate() {
+                let candidates = unique.entry(p.map(f32::to_bits)).or_default();
+                let existing = candidates
+                    .iter()
+                    .copied()
+                    .find(|&candidate| normals_match(vertex, kept[candidate as usize]));
+                let index = existing.unwrap_or_else(|| {
+                    positions.push(*p);
+                    kept.push(vertex);
+                    let index = positions.len() as u32 - 1;
+                    candidates.push(index);
+                    index
+                });
+                remap.push(index);
+            }
+        } else {
+            let cell_of = |p: &[f32; 3]| p.map(|c| (c / epsilon).floor() as i64);
+            let mut grid = HashMap::<[i64; 3], Vec<u32>>::new();
+
+            for (vertex, p) in self.positions.iter().enumerate() {
+                let [cx, cy, cz] = cell_of(p);
+                let existing = (-1..=1)
+                    .flat_map(|dx| {
+                        (-1..=1).flat_map(move |dy| (-1..=1).map(move |dz| [dx, dy, dz]))
+                    })
+                    .filter_map(|[dx, dy, dz]| grid.get(&[cx + dx, cy + dy, cz + dz]))
+                    .flatten()
+                    .copied()
+                    .find(|&candidate| {
+                        let q = positions[candidate as usize];
+                        let distance_sq = (0..3).map(|i| (p[i] - q[i]).powi(2)).sum::<f32>();
+                        distance_sq <= epsilon * epsilon
+                            && normals_match(vertex, kept[candidate as usize])
+                    });
+
+                let index = existing.unwrap_or_else(|| {
+                    positions.push(*p);
+                    kept.push(vertex);
+                    let index = positions.len() as u32 - 1;
+                    grid.entry([cx, cy, cz]).or_default().push(index);
+                    index
+                });
+                remap.push(index);
+            }
+        }
+
+        Self {
+            positions,
+            normals: if self.normals.is_empty() {
+                vec![]
+            } else {
+                kept.iter().map(|&vertex| self.normals[vertex]).collect()
+            },
+            indices: self.indices.iter().map(|&i| remap[i as usize]).collect(),
+        }
+    }
+}
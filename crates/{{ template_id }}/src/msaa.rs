@@ -0,0 +1,207 @@
+use anyhow::{ensure, Context, Result};
+use breda::{
+    render_graph::{RasterPass, RenderGraph, RenderGraphTexture},
+    renderer::{Device, Format, LoadOp, StoreOp, TextureCreateDesc},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+use clap::{Args, ValueEnum};
+
+use crate::fullscreen::FullscreenPass;
+
+pub const DEFAULT_MSAA_SAMPLES: u32 = 1;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct MsaaOpts {
+    /// Samples per pixel of the raster passes that draw the scene, the background and the main
+    /// pass, resolved before anything else draws over them. Only pixels at the edges of the
+    /// scene's triangles with --draw-mode scene-geometry get smoother, the rays are still traced
+    /// once per pixel. Clamped to what the device supports. Only applies to the window, headless
+    /// output and the viewport preview stay single-sampled.
+    #[clap(long, value_name = "1|2|4|8", value_parser = parse_samples)]
+    pub msaa: Option<u32>,
+    /// Resolve --msaa with a pass that gives bright samples less weight instead of averaging
+    /// them in hardware, so that HDR highlights on the scene's edges don't flicker as fireflies
+    #[clap(long)]
+    pub custom_resolve: bool,
+    /// Also resolve the main pass' multisampled depth into a single-sampled texture, for passes
+    /// that read depth after it, combining the samples of each pixel as selected. Only has an
+    /// effect with --msaa.
+    #[clap(long, value_enum)]
+    pub depth_resolve: Option<DepthResolve>,
+    /// Show the depth resolved by --depth-resolve in place of the scene, white at the near plane
+    /// and black where rays miss
+    #[clap(long, requires = "depth_resolve")]
+    pub show_resolved_depth: bool,
+}
+
+/// How [`MsaaTarget::resolve_depth()`] combines the depth samples of a pixel. Averaging them, like
+/// color, would give depths that no surface has at the edges of triangles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DepthResolve {
+    /// The closest sample, with the 0-1 depth range of [`crate::camera::Camera::view_proj()`]
+    Min,
+    /// The farthest sample
+    Max,
+    /// Whichever surface covers the first sample, like a single-sampled pass would have drawn
+    #[value(name = "sample0")]
+    Sample0,
+}
+
+impl DepthResolve {
+    /// Of the `msaa_depth_resolve.ps.hlsl` entry point for the mode
+    fn pipeline_name(self) -> &'static str {
+        match self {
+            Self::Min => "{{ template_id }}-msaa-depth-resolve-min",
+            Self::Max => "{{ template_id }}-msaa-depth-resolve-max",
+            Self::Sample0 => "{{ template_id }}-msaa-depth-resolve-sample0",
+        }
+    }
+}
+
+impl MsaaOpts {
+    pub fn samples(&self) -> u32 {
+        self.msaa.unwrap_or(DEFAULT_MSAA_SAMPLES)
+    }
+}
+
+fn parse_samples(s: &str) -> Result<u32> {
+    let samples = s
+        .parse::<u32>()
+        .with_context(|| format!("Invalid sample count `{s}`"))?;
+    ensure!(
+        matches!(samples, 1 | 2 | 4 | 8),
+        "Sample count must be 1, 2, 4 or 8, got {samples}"
+    );
+    Ok(samples)
+}
+
+/// The highest sample count up to `requested` that `device` supports for color and depth targets,
+/// warning when that is lower than what was requested
+pub fn supported_samples(requested: u32, device: &dyn Device) -> u32 {
+    // The main pass draws with a multisampled depth target of the same sample count
+    let capabilities = device.capabilities();
+    let max = capabilities
+        .max_color_sample_count
+        .min(capabilities.max_depth_sample_count)
+        .max(1);
+    if requested <= max {
+        return requested;
+    }
+    // Sample counts are powers of two, so this is the largest one within the limit
+    let samples = 1 << max.ilog2();
+    log::warn!(
+        "{} supports at most {max}x MSAA, using {samples}x instead of the requested {requested}x",
+        device.driver_info().device_name
+    );
+    samples
+}
+
+/// Multisampled color target of the scene's raster passes, resolved into the single-sampled
+/// target they would draw to otherwise, see [`crate::raytracer::record_main_pass()`].
+///
+/// Created per frame in the render graph, like the main pass' depth target.
+pub struct MsaaTarget {
+    pub color: RenderGraphTexture,
+    pub samples: u32,
+    /// Resolve with `msaa_resolve.ps.hlsl` instead of in hardware, see --custom-resolve
+    pub custom_resolve: bool,
+    /// See --depth-resolve
+    pub depth_resolve: Option<DepthResolve>,
+}
+
+impl MsaaTarget {
+    /// [`None`] with a single sample, the passes then draw to their target directly. `format`
+    /// must be that of the target resolved into.
+    pub fn create(
+        render_graph: &mut RenderGraph,
+        size: [u32; 2],
+        format: Format,
+        samples: u32,
+        custom_resolve: bool,
+        depth_resolve: Option<DepthResolve>,
+    ) -> Option<Self> {
+        (samples > 1).then(|| Self {
+            color: render_graph.create_texture(
+                "msaa color target",
+                &TextureCreateDesc::render_target_2d(size, format).with_sample_count(samples),
+            ),
+            samples,
+            custom_resolve,
+            depth_resolve,
+        })
+    }
+
+    /// Format of the texture [`Self::resolve_depth()`] returns
+    pub const RESOLVED_DEPTH_FORMAT: Format = Format::R32Sfloat;
+
+    /// Records the resolve of the multisampled `depth` target into a single-sampled texture of
+    /// `size`, which is only done with [`Self::depth_resolve`]. A color texture, as depth formats
+    /// can't be written by a pixel shader's color output. `depth` must be stored by the pass that
+    /// draws into it, see [`Self::depth_store_op()`].
+    pub fn resolve_depth(
+        &self,
+        render_graph: &mut RenderGraph,
+        depth: &RenderGraphTexture,
+        size: [u32; 2],
+        shader_db: &AssetsShaderDatabase,
+    ) -> Option<RenderGraphTexture> {
+        let mode = self.depth_resolve?;
+        let resolved = render_graph.create_texture(
+            "resolved depth",
+            &TextureCreateDesc::render_target_2d(size, Self::RESOLVED_DEPTH_FORMAT),
+        );
+        FullscreenPass::draw(
+            RasterPass::new("MSAA depth resolve", render_graph)
+                .render_target(&resolved, LoadOp::Discard, StoreOp::Store)
+                .read_texture(depth),
+            &shader_db.get_pipeline(mode.pipeline_name()),
+        );
+        Some(resolved)
+    }
+
+    /// Whether the multisampled depth has to outlive the pass drawing it, for
+    /// [`Self::resolve_depth()`]
+    pub fn depth_store_op(&self) -> StoreOp {
+        if self.depth_resolve.is_some() {
+            StoreOp::Store
+        } else {
+            StoreOp::Discard
+        }
+    }
+
+    /// Averages the samples of [`Self::color`] into every pixel of `target`, weighted by their
+    /// brightness with [`Self::custom_resolve`]
+    pub fn resolve(
+        &self,
+        render_graph: &mut RenderGraph,
+        target: &RenderGraphTexture,
+        shader_db: &AssetsShaderDatabase,
+    ) {
+        if !self.custom_resolve {
+            render_graph.resolve_texture(&self.color, target);
+            return;
+        }
+        FullscreenPass::draw(
+            RasterPass::new("MSAA resolve", render_graph)
+                .render_target(target, LoadOp::Discard, StoreOp::Store)
+                .read_texture(&self.color),
+            &shader_db.get_pipeline("{{ template_id }}-msaa-resolve"),
+        );
+    }
+}
+
+/// Draws `depth`, resolved by [`MsaaTarget::resolve_depth()`], over all of `target` in grayscale,
+/// for --show-resolved-depth
+pub fn draw_depth_view(
+    render_graph: &mut RenderGraph,
+    depth: &RenderGraphTexture,
+    target: &RenderGraphTexture,
+    shader_db: &AssetsShaderDatabase,
+) {
+    FullscreenPass::draw(
+        RasterPass::new("Resolved depth view", render_graph)
+            .render_target(target, LoadOp::Discard, StoreOp::Store)
+            .read_texture(depth),
+        &shader_db.get_pipeline("{{ template_id }}-resolved-depth-view"),
+    );
+}
@@ -0,0 +1,112 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::{math, mesh::Mesh};
+
+/// Resolves a 1-based or negative (relative to the end) OBJ index into a 0-based one
+fn resolve_index(index: &str, count: usize) -> Result<u32> {
+    let index = index
+        .parse::<i64>()
+        .with_context(|| format!("Invalid index `{index}`"))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => count as i64 + index,
+        0 => bail!("OBJ indices start at 1"),
+    };
+    ensure!(
+        (0..count as i64).contains(&resolved),
+        "Index {index} is out of range for {count} vertices"
+    );
+    Ok(resolved as u32)
+}
+
+/// Parses the positions, normals and faces of a Wavefront OBJ file into a single mesh.
+///
+/// All objects and groups are merged and polygons are fan-triangulated. Every distinct pair of
+/// position and normal index becomes a vertex. When any corner has no normal, none are kept and
+/// the mesh gets flat normals later, see [`Mesh::ensure_normals()`]. Texture coordinates and
+/// materials are ignored.
+pub fn load_obj_scene(path: &Path) -> Result<Mesh> {
+    let source = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+
+    let (mut positions, mut normals) = (Vec::<[f32; 3]>::new(), Vec::<[f32; 3]>::new());
+    let mut vertices = HashMap::<(u32, Option<u32>), u32>::new();
+    let mut mesh = Mesh::default();
+    let mut objects = 0;
+    for (line_number, line) in source.lines().enumerate() {
+        let context = || format!("{}:{}", path.display(), line_number + 1);
+
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some(kind @ ("v" | "vn")) => {
+                let vector = tokens
+                    .by_ref()
+                    .take(3)
+                    .map(str::parse::<f32>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(context)?;
+                ensure!(vector.len() == 3, "{}: Expected 3 coordinates", context());
+                let vector = [vector[0], vector[1], vector[2]];
+                if kind == "v" {
+                    positions.push(vector);
+                } else {
+                    normals.push(math::normalize(vector));
+                }
+            }
+            Some("f") => {
+                // Corners are `v`, `v/vt`, `v//vn` or `v/vt/vn`
+                let corners = tokens
+                    .map(|corner| -> Result<u32> {
+                        let mut parts = corner.split('/');
+                        let position =
+                            resolve_index(parts.next().unwrap_or_default(), positions.len())?;
+                        let normal = match parts.nth(1) {
+                            Some(normal) if !normal.is_empty() => {
+                                Some(resolve_index(normal, normals.len())?)
+                            }
+                            _ => None,
+                        };
+                        Ok(*vertices.entry((position, normal)).or_insert_with(|| {
+                            mesh.positions.push(positions[position as usize]);
+                            if let Some(normal) = normal {
+                                mesh.normals.push(normals[normal as usize]);
+                            }
+                            mesh.positions.len() as u32 - 1
+                        }))
+                    })
+                    .collect::<Result<Vec<_>>>()
+                    .with_context(context)?;
+                ensure!(
+                    corners.len() >= 3,
+                    "{}: Face needs 3 or more corners",
+                    context()
+                );
+                for i in 1..corners.len() - 1 {
+                    mesh.indices
+                        .extend([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            Some("o" | "g") => objects += 1,
+            _ => {}
+        }
+    }
+
+    ensure!(
+        mesh.triangle_count() > 0,
+        "`{}` contains no faces",
+        path.display()
+    );
+    if mesh.normals.len() != mesh.positions.len() {
+        mesh.normals.clear();
+    }
+    log::info!(
+        "Loaded `{}`: {} vertices, {} triangles from {} object(s)",
+        path.display(),
+        mesh.positions.len(),
+        mesh.triangle_count(),
+        objects.max(1)
+    );
+    Ok(mesh)
+}
@@ -0,0 +1,132 @@
+use anyhow::{ensure, Context, Result};
+use breda::{
+    render_graph::{RasterPass, RenderGraph, RenderGraphTexture},
+    renderer::{Device, Fence, Format, LoadOp, StoreOp, Texture, TextureCreateDesc},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+use clap::Args;
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::FrameRing,
+    fullscreen::FullscreenPass,
+};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct PixelPerfectOpts {
+    /// Render at this fixed resolution and upscale to the window by the largest integer factor
+    /// that fits, with nearest-neighbor filtering and letterboxing
+    #[clap(long, value_name = "WIDTHxHEIGHT", value_parser = parse_resolution)]
+    pub pixel_perfect: Option<[u32; 2]>,
+}
+
+pub fn parse_resolution(s: &str) -> Result<[u32; 2]> {
+    let (width, height) = s
+        .split_once('x')
+        .context("Expected a resolution like `320x180`")?;
+    let size = [width.trim().parse()?, height.trim().parse()?];
+    ensure!(size[0] > 0 && size[1] > 0, "Resolution must not be empty");
+    Ok(size)
+}
+
+/// Placement of the upscaled base image inside the window
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntegerScale {
+    pub scale: u32,
+    /// Top-left corner of the upscaled image, negative when the window is smaller than the base
+    /// resolution and the image is cropped instead
+    pub offset: [i32; 2],
+}
+
+impl IntegerScale {
+    pub fn fit(base_size: [u32; 2], window_size: [u32; 2]) -> Self {
+        let scale = (window_size[0] / base_size[0])
+            .min(window_size[1] / base_size[1])
+            .max(1);
+        let offset =
+            std::array::from_fn(|i| (window_size[i] as i32 - (base_size[i] * scale) as i32) / 2);
+        Self { scale, offset }
+    }
+}
+
+/// Mirrors `UpscaleConstants` in `upscale.ps.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct UpscaleConstants {
+    offset: [i32; 2],
+    base_size: [u32; 2],
+    scale: u32,
+    _pad: [u32; 3],
+}
+
+/// Low resolution target the scene is rendered into before it is blown up to the window
+pub struct PixelPerfectTarget {
+    texture: Texture,
+    size: [u32; 2],
+    constants: FrameRing<UpscaleConstants>,
+}
+
+impl PixelPerfectTarget {
+    pub fn new(device: &dyn Device, size: [u32; 2], fence_timeout: FenceTimeout) -> Self {
+        let texture = device.create_texture(
+            "pixel perfect target",
+            &TextureCreateDesc::render_target_2d(size, Format::R8g8b8a8Unorm),
+        );
+        log_allocation(
+            ResourceKind::Texture,
+            "pixel perfect target",
+            size[0] as usize * size[1] as usize * 4,
+            MemoryKind::DeviceLocal,
+        );
+        Self {
+            texture,
+            size,
+            constants: FrameRing::new(device, "upscale constants", 1, fence_timeout),
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn format(&self) -> Format {
+        self.texture.format()
+    }
+
+    pub fn import(&self, render_graph: &mut RenderGraph) -> RenderGraphTexture {
+        render_graph.import_texture(&self.texture)
+    }
+
+    /// Records the nearest-neighbor upscale of `source`, as imported by [`Self::import()`], into
+    /// the whole of `target`, filling the letterbox area with black
+    pub fn record_upscale(
+        &mut self,
+        render_graph: &mut RenderGraph,
+        source: &RenderGraphTexture,
+        target: &RenderGraphTexture,
+        target_size: [u32; 2],
+        shader_db: &AssetsShaderDatabase,
+    ) -> Result<()> {
+        let IntegerScale { scale, offset } = IntegerScale::fit(self.size, target_size);
+        let constants_rg =
+            render_graph.import_buffer(self.constants.write(&[UpscaleConstants {
+                offset,
+                base_size: self.size,
+                scale,
+                _pad: [0; 3],
+            }])?);
+
+        let pass = RasterPass::new("Pixel perfect upscale", render_graph)
+            .render_target(target, LoadOp::Discard, StoreOp::Store)
+            .read_texture(source)
+            .read_buffer(&constants_rg);
+        FullscreenPass::draw(pass, &shader_db.get_pipeline("{{ template_id }}-upscale"));
+        Ok(())
+    }
+
+    /// Hands over the fence of the frame's submission, call once per frame
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.constants.advance(fence);
+    }
+}
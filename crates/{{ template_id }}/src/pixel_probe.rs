@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use breda::{
+    egui,
+    renderer::{CommandBuffer, Device, Fence, Texture},
+};
+use clap::Args;
+
+use crate::{
+    device_loss::wait_unless_lost,
+    fence_timeout::FenceTimeout,
+    frame_ring::{Retired, FRAMES_IN_FLIGHT},
+    raytracer::DecodedPixel,
+    readback::{ReadbackFormat, TextureReadback},
+};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct PixelProbeOpts {
+    /// Read back the pixel under the mouse cursor every frame and show its value in a window
+    #[clap(long)]
+    pub pixel_probe: bool,
+}
+
+/// A value read back by [`PixelProbe`], a few frames old
+#[derive(Clone, Copy, Debug)]
+pub struct ProbedPixel {
+    pub position: [u32; 2],
+    pub rgba: [f32; 4],
+}
+
+struct Slot {
+    readback: TextureReadback,
+    fence: Option<Fence>,
+    position: [u32; 2],
+}
+
+/// Asynchronous single-pixel readback of the presented image at the mouse cursor
+pub struct PixelProbe {
+    fence_timeout: FenceTimeout,
+    slots: Vec<Slot>,
+    /// Slots replaced while their copies were in flight
+    retired_slots: Retired<Slot>,
+    format: Option<ReadbackFormat>,
+    current: usize,
+    /// Whether the current slot had a copy recorded this frame, and needs [`Self::end_frame()`]
+    recorded: bool,
+    last: Option<ProbedPixel>,
+}
+
+impl PixelProbe {
+    /// Returns [`None`] unless `--pixel-probe` is passed
+    pub fn new(opts: &PixelProbeOpts, fence_timeout: FenceTimeout) -> Option<Self> {
+        opts.pixel_probe.then(|| Self {
+            fence_timeout,
+            slots: Vec::new(),
+            retired_slots: Retired::default(),
+            format: None,
+            current: 0,
+            recorded: false,
+            last: None,
+        })
+    }
+
+    /// Collects the oldest in-flight readback and, if the cursor is over `image`, records a copy
+    /// of the pixel at `cursor` into `cmd`. Call before the UI is drawn into `image`, so the
+    /// probe doesn't read back the overlay.
+    pub fn record(
+        &mut self,
+        device: &dyn Device,
+        cmd: &mut CommandBuffer,
+        image: &Texture,
+        cursor: Option<[u32; 2]>,
+    ) -> Result<()> {
+        let format = ReadbackFormat::from_texture_format(image.format())
+            .with_context(|| format!("Cannot probe pixels of {:?} images", image.format()))?;
+        if self.format != Some(format) {
+            // The pending values are dropped, but the buffers are kept until the GPU is done
+            // writing them
+            for slot in self.slots.drain(..) {
+                if slot.fence.is_some() {
+                    self.retired_slots.retire(slot);
+                }
+            }
+            self.slots = (0..FRAMES_IN_FLIGHT)
+                .map(|i| Slot {
+                    readback: TextureReadback::new(
+                        device,
+                        &format!("pixel probe readback {i}"),
+                        [1, 1],
+                        format,
+                    ),
+                    fence: None,
+                    position: [0, 0],
+                })
+                .collect();
+            self.format = Some(format);
+            self.current = 0;
+        }
+
+        self.collect_current()?;
+
+        let size = image.size();
+        let Some(position) = cursor.filter(|p| p[0] < size[0] && p[1] < size[1]) else {
+            return Ok(());
+        };
+        let slot = &mut self.slots[self.current];
+        slot.readback.record_region_copy(cmd, image, position)?;
+        slot.position = position;
+        self.recorded = true;
+        Ok(())
+    }
+
+    /// Drops the readback slots, which live on `device`, for example before switching devices.
+    /// Waits for the copies still in flight first, unless `device` is lost.
+    pub fn reset(&mut self, device: &dyn Device) -> Result<()> {
+        let slots = self.slots.iter().chain(self.retired_slots.iter());
+        for fence in slots.filter_map(|slot| slot.fence.as_ref()) {
+            wait_unless_lost(device, || {
+                self.fence_timeout
+                    .wait(fence, "reading back the probed pixel before dropping it")
+            })?;
+        }
+        self.slots.clear();
+        self.retired_slots = Retired::default();
+        self.format = None;
+        self.recorded = false;
+        Ok(())
+    }
+
+    /// Hands over the fence of the submission containing the copy from [`Self::record()`], if any
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.retired_slots.end_frame(&fence);
+        if std::mem::take(&mut self.recorded) {
+            self.slots[self.current].fence = Some(fence);
+            self.current = (self.current + 1) % self.slots.len();
+        }
+    }
+
+    fn collect_current(&mut self) -> Result<()> {
+        let slot = &mut self.slots[self.current];
+        let Some(fence) = slot.fence.take() else {
+            return Ok(());
+        };
+        self.fence_timeout
+            .wait(&fence, "reading back the probed pixel")?;
+
+        self.last = slot
+            .readback
+            .read_texels()
+            .first()
+            .map(|&rgba| ProbedPixel {
+                position: slot.position,
+                rgba,
+            });
+        Ok(())
+    }
+
+    /// Shows the last probed value, along with what it encodes when `decoder` maps it back, see
+    /// [`crate::raytracer::RaytracerSettings::pixel_decoder()`]
+    pub fn ui(&self, ui: &mut egui::Ui, decoder: Option<impl Fn([f32; 4]) -> DecodedPixel>) {
+        let Some(ProbedPixel { position, rgba }) = self.last else {
+            ui.label("Hover the viewport to probe a pixel");
+            return;
+        };
+        ui.label(format!("Pixel {}, {}", position[0], position[1]));
+        ui.monospace(format!(
+            "R {:.4}  G {:.4}  B {:.4}  A {:.4}",
+            rgba[0], rgba[1], rgba[2], rgba[3]
+        ));
+        let Some(decoder) = decoder else {
+            return;
+        };
+        match decoder(rgba) {
+            DecodedPixel::Miss => ui.label("Miss"),
+            DecodedPixel::HitDistance(t) => ui.label(format!("Hit distance: {t:.3}")),
+            DecodedPixel::Barycentrics([u, v, w]) => {
+                ui.monospace(format!("Barycentrics {u:.4}  {v:.4}  {w:.4}"))
+            }
+            DecodedPixel::InstanceHash => {
+                ui.label("The instance index is hashed into the color and can't be decoded")
+            }
+            DecodedPixel::Normal([x, y, z]) => {
+                ui.monospace(format!("Normal {x:.4}  {y:.4}  {z:.4}"))
+            }
+        };
+    }
+}
@@ -0,0 +1,127 @@
+use breda::{egui, render_loop::v2::event::EventSender, renderer};
+use clap::{Args, ValueEnum};
+
+/// How presented images are handed to the display
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PresentMode {
+    /// Vsync: waits for the next vertical blank, never tears
+    #[default]
+    Fifo,
+    /// Replaces the queued image with the newest one every frame, never tears or blocks
+    Mailbox,
+    /// Shows every image right away, tearing where it lands mid-scanout
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [Self; 3] = [Self::Fifo, Self::Mailbox, Self::Immediate];
+
+    /// Spelled like on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fifo => "fifo",
+            Self::Mailbox => "mailbox",
+            Self::Immediate => "immediate",
+        }
+    }
+
+    pub fn swapchain_present_mode(self) -> renderer::PresentMode {
+        match self {
+            Self::Fifo => renderer::PresentMode::Fifo,
+            Self::Mailbox => renderer::PresentMode::Mailbox,
+            Self::Immediate => renderer::PresentMode::Immediate,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct PresentModeOpts {
+    /// Presentation mode of the window's swapchain, falling back to `fifo` when the device and
+    /// display don't support it
+    #[clap(long, value_enum, default_value_t)]
+    pub present_mode: PresentMode,
+}
+
+/// Switches the swapchain to the present mode picked on the command line or in the UI, the
+/// render loop recreates the swapchain to apply it.
+///
+/// Every surface supports FIFO, which is used instead of any mode that the current one doesn't.
+/// The substitution is logged once per requested mode.
+pub struct PresentModeSelector {
+    requested: PresentMode,
+    /// The mode [`Self::requested`] resolved to on the last frame
+    effective: PresentMode,
+    /// Of the current swapchain, shown in the UI
+    supported: Vec<renderer::PresentMode>,
+    /// The last unsupported mode that was logged
+    reported: Option<PresentMode>,
+    /// The last mode the render loop was asked for, which it is only asked once
+    sent: Option<PresentMode>,
+}
+
+impl PresentModeSelector {
+    pub fn new(present_mode: PresentMode) -> Self {
+        Self {
+            requested: present_mode,
+            effective: present_mode,
+            supported: vec![],
+            reported: None,
+            sent: None,
+        }
+    }
+
+    /// Call once per frame with what the current swapchain supports and uses. Asks the render
+    /// loop to switch when that differs from the requested mode, or from FIFO when it is not
+    /// supported.
+    pub fn frame(
+        &mut self,
+        supported: Vec<renderer::PresentMode>,
+        current: renderer::PresentMode,
+        event_sender: &EventSender,
+    ) {
+        self.supported = supported;
+        self.effective = if self.is_supported(self.requested) {
+            self.requested
+        } else {
+            if self.reported != Some(self.requested) {
+                log::warn!(
+                    "Present mode {} is not supported by this device and display, falling back \
+                     to fifo",
+                    self.requested.name()
+                );
+                self.reported = Some(self.requested);
+            }
+            PresentMode::Fifo
+        };
+
+        let present_mode = self.effective.swapchain_present_mode();
+        if current != present_mode && self.sent != Some(self.effective) {
+            log::info!("Switching to present mode {}", self.effective.name());
+            event_sender.request_present_mode(present_mode);
+            self.sent = Some(self.effective);
+        }
+    }
+
+    fn is_supported(&self, present_mode: PresentMode) -> bool {
+        self.supported
+            .contains(&present_mode.swapchain_present_mode())
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let supported = PresentMode::ALL.map(|mode| self.is_supported(mode));
+        egui::ComboBox::from_label("Present mode")
+            .selected_text(self.effective.name())
+            .show_ui(ui, |ui| {
+                for (mode, supported) in PresentMode::ALL.into_iter().zip(supported) {
+                    ui.add_enabled_ui(supported, |ui| {
+                        ui.selectable_value(&mut self.requested, mode, mode.name());
+                    });
+                }
+            });
+        ui.label(if self.effective == PresentMode::Fifo {
+            "Vsync on"
+        } else {
+            "Vsync off"
+        });
+    }
+}
@@ -0,0 +1,724 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use breda::{
+    egui,
+    render_graph::{RasterPass, RenderGraph, RenderGraphTexture},
+    renderer::{Buffer, Device, Fence, Format, LoadOp, RasterPipeline, StoreOp, TextureCreateDesc},
+    shader_database::{AssetsShaderDatabase, ShaderDatabase},
+};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    background::{parse_color, Background, BackgroundConstants},
+    camera::{Camera, CameraConstants},
+    color_grading::ColorGrading,
+    color_mode::{is_srgb_format, srgb_to_linear},
+    deferred::GBuffer,
+    fence_timeout::FenceTimeout,
+    fog::Fog,
+    frame_ring::FrameRing,
+    fullscreen::FullscreenPass,
+    instance_colors::{record_instance_colors, InstanceColorConstants},
+    mesh::Aabb,
+    msaa::MsaaTarget,
+    rng::Rng,
+    scene::TriangleScene,
+    user_uniforms::UserUniformValues,
+};
+
+/// How the raytraced result is presented, see `main.ps.hlsl`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ViewMode {
+    #[default]
+    Shaded = 0,
+    /// Drawn with its own `{{ template_id }}-wireframe` pipeline
+    Wireframe = 1,
+    /// Wireframe blended over the shaded result with [`RaytracerSettings::wireframe_opacity`]
+    ShadedWireframe = 2,
+}
+
+impl ViewMode {
+    pub const ALL: [Self; 3] = [Self::Shaded, Self::Wireframe, Self::ShadedWireframe];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Shaded => "Shaded",
+            Self::Wireframe => "Wireframe",
+            Self::ShadedWireframe => "Shaded + wireframe",
+        }
+    }
+}
+
+/// Raw hit data the raytracer can output instead of the shaded result, see `debugVisualization()`
+/// in `main.ps.hlsl`. Misses are drawn black.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u32)]
+pub enum RtDebugMode {
+    #[default]
+    Off = 0,
+    /// The three barycentric coordinates of the hit as red, green and blue
+    Barycentrics = 1,
+    /// A color hashed from the hit instance's index in the TLAS
+    InstanceIndex = 2,
+    /// White at the start of the ray interval, fading to black at its end
+    HitDistance = 3,
+    /// Faceted normal derived from the hit position, mapped from `-1..1` to `0..1`
+    GeometryNormal = 4,
+    /// Interpolated vertex normal the hit is lit with, mapped like [`Self::GeometryNormal`]
+    ShadingNormal = 5,
+}
+
+impl RtDebugMode {
+    pub const ALL: [Self; 6] = [
+        Self::Off,
+        Self::Barycentrics,
+        Self::InstanceIndex,
+        Self::HitDistance,
+        Self::GeometryNormal,
+        Self::ShadingNormal,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Barycentrics => "Barycentrics",
+            Self::InstanceIndex => "Instance index",
+            Self::HitDistance => "Hit distance",
+            Self::GeometryNormal => "Geometry normal",
+            Self::ShadingNormal => "Shading normal",
+        }
+    }
+}
+
+/// What a pixel of the raytraced image stands for, see [`RaytracerSettings::pixel_decoder()`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecodedPixel {
+    Miss,
+    /// Distance along the ray to the hit
+    HitDistance(f32),
+    /// The `u, v, w` barycentric coordinates of the hit
+    Barycentrics([f32; 3]),
+    /// The instance index is hashed into a color, which can't be mapped back to the index
+    InstanceHash,
+    /// Geometry or shading normal of the hit, in world space
+    Normal([f32; 3]),
+}
+
+/// What the main pass draws. Except for [`Self::Deferred`] its pixel shader traces a ray per
+/// pixel, the draw only decides which pixels it runs for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum DrawMode {
+    /// A triangle covering the whole target, every pixel traces a ray, see [`FullscreenPass`]
+    #[default]
+    #[value(alias = "fullscreen-quad")]
+    FullscreenTriangle,
+    /// The scene's own triangles, rasterized through the camera so that only the pixels they
+    /// cover trace a ray, as a starting point for hybrid raster and raytracing. The orthographic
+    /// views and the shader clock always draw the full-screen triangle.
+    SceneGeometry,
+    /// No rays at all: the scene's triangles are rasterized into a [`GBuffer`], which a lighting
+    /// pass shades into an HDR target that is then tonemapped, see `deferred.ps.hlsl`. A
+    /// starting point for classic deferred shading, without fog, wireframe, debug modes or MSAA.
+    /// Falls back to the full-screen triangle like [`Self::SceneGeometry`].
+    Deferred,
+}
+
+impl DrawMode {
+    pub const ALL: [Self; 3] = [
+        Self::FullscreenTriangle,
+        Self::SceneGeometry,
+        Self::Deferred,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FullscreenTriangle => "Full-screen triangle",
+            Self::SceneGeometry => "Scene geometry",
+            Self::Deferred => "Deferred lighting",
+        }
+    }
+
+    /// Whether the draw rasterizes the scene's triangles and binds its raster buffers
+    pub fn rasterizes_scene(self) -> bool {
+        matches!(self, Self::SceneGeometry | Self::Deferred)
+    }
+
+    /// Issues the main pass' draw of `pipeline`
+    pub fn draw(self, pass: RasterPass<'_>, pipeline: &RasterPipeline, scene: &TriangleScene) {
+        match self {
+            Self::FullscreenTriangle => FullscreenPass::draw(pass, pipeline),
+            Self::SceneGeometry | Self::Deferred => pass.draw(
+                pipeline,
+                3 * scene.max_mesh_triangle_count as u32,
+                scene.instance_count as u32,
+            ),
+        }
+    }
+}
+
+pub const DEFAULT_RAY_T_MIN: f32 = 0.1;
+pub const DEFAULT_RAY_T_MAX: f32 = 1000.0;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct RaytracerOpts {
+    /// Start of the primary ray interval, too small values cause self-intersection artifacts
+    #[clap(long, value_name = "T")]
+    pub ray_t_min: Option<f32>,
+    /// End of the primary ray interval, geometry beyond it is clipped
+    #[clap(long, value_name = "T")]
+    pub ray_t_max: Option<f32>,
+    /// Only raytrace the pixels inside this rectangle and leave the rest black
+    #[clap(long, value_name = "X,Y,W,H", value_parser = parse_region)]
+    pub render_region: Option<[u32; 4]>,
+    /// Clear to this color where rays miss instead of drawing the background gradient, with
+    /// components between 0 and 1
+    #[clap(long, value_name = "R,G,B,A", value_parser = parse_color)]
+    pub clear_color: Option<[f32; 4]>,
+    /// Geometry the main pass draws to run the raytracer's pixel shader, or `deferred` to shade
+    /// the scene with raster passes instead
+    #[clap(long, value_enum)]
+    pub draw_mode: Option<DrawMode>,
+}
+
+fn parse_region(s: &str) -> Result<[u32; 4]> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .context("Expected four integers like `100,100,640,360`")?;
+    let region: [u32; 4] = values
+        .try_into()
+        .map_err(|values: Vec<_>| anyhow!("Expected 4 values, got {}", values.len()))?;
+    ensure!(
+        region[2] > 0 && region[3] > 0,
+        "Render region must not be empty"
+    );
+    Ok(region)
+}
+
+/// User-tweakable inputs to the raytracing pass
+#[derive(Clone, Debug, PartialEq)]
+pub struct RaytracerSettings {
+    pub draw_mode: DrawMode,
+    pub view_mode: ViewMode,
+    /// Replaces the output of every view mode when not [`RtDebugMode::Off`]
+    pub debug_mode: RtDebugMode,
+    pub wireframe_opacity: f32,
+    /// Line width in pixels
+    pub wireframe_width: f32,
+    /// Direction the directional light travels in, which lights every hit of the shaded views
+    pub light_direction: [f32; 3],
+    /// Replace the main view with an orthographic view along [`Self::light_direction`] that
+    /// covers the scene bounds, showing the depth as seen by the light
+    pub view_from_light: bool,
+    /// Replace the main view with an orthographic view along this direction that covers the
+    /// scene bounds, like [`Self::view_from_light`] but shaded
+    pub view_direction: Option<[f32; 3]>,
+    /// Interval of the main view's rays, the framed orthographic views derive theirs from the
+    /// scene bounds instead
+    pub ray_t_min: f32,
+    pub ray_t_max: f32,
+    /// `[x, y, width, height]` of the pixels that are raytraced, everything else stays black
+    pub render_region: Option<[u32; 4]>,
+    pub fog: Fog,
+    /// Shown where the rays miss, unless fog covers them
+    pub background: Background,
+    pub color_grading: ColorGrading,
+    /// Packed `UserUniforms`, from `--uniforms`
+    pub user_uniforms: UserUniformValues,
+    /// Mixed into the hash of every instance color, see `instance_colors.cs.hlsl`
+    pub instance_color_seed: u32,
+}
+
+impl Default for RaytracerSettings {
+    fn default() -> Self {
+        Self {
+            draw_mode: DrawMode::default(),
+            view_mode: ViewMode::default(),
+            debug_mode: RtDebugMode::default(),
+            wireframe_opacity: 0.5,
+            wireframe_width: 1.0,
+            light_direction: [0.3, 0.5, 1.0],
+            view_from_light: false,
+            view_direction: None,
+            ray_t_min: DEFAULT_RAY_T_MIN,
+            ray_t_max: DEFAULT_RAY_T_MAX,
+            render_region: None,
+            fog: Fog::default(),
+            background: Background::default(),
+            color_grading: ColorGrading::default(),
+            user_uniforms: UserUniformValues::default(),
+            instance_color_seed: 0,
+        }
+    }
+}
+
+impl RaytracerSettings {
+    /// The defaults, overridden by whatever was passed on the command line. Every mode creates
+    /// its settings through here. The random settings are drawn from `rng` in the same order
+    /// every time, so that a seed always gives the same image.
+    pub fn new(opts: &RaytracerOpts, rng: &mut Rng) -> Self {
+        let defaults = Self::default();
+        Self {
+            instance_color_seed: rng.next_u32(),
+            ray_t_min: opts.ray_t_min.unwrap_or(defaults.ray_t_min),
+            ray_t_max: opts.ray_t_max.unwrap_or(defaults.ray_t_max),
+            render_region: opts.render_region,
+            draw_mode: opts.draw_mode.unwrap_or(defaults.draw_mode),
+            background: opts
+                .clear_color
+                .map_or(defaults.background, Background::from_clear_color),
+            ..defaults
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Draw mode")
+            .selected_text(self.draw_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in DrawMode::ALL {
+                    ui.selectable_value(&mut self.draw_mode, mode, mode.name());
+                }
+            });
+        egui::ComboBox::from_label("View mode")
+            .selected_text(self.view_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in ViewMode::ALL {
+                    ui.selectable_value(&mut self.view_mode, mode, mode.name());
+                }
+            });
+        egui::ComboBox::from_label("Debug mode")
+            .selected_text(self.debug_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in RtDebugMode::ALL {
+                    ui.selectable_value(&mut self.debug_mode, mode, mode.name());
+                }
+            });
+        ui.add_enabled(
+            self.view_mode == ViewMode::ShadedWireframe,
+            egui::Slider::new(&mut self.wireframe_opacity, 0.0..=1.0).text("Wireframe opacity"),
+        );
+        ui.add_enabled(
+            self.view_mode != ViewMode::Shaded,
+            egui::Slider::new(&mut self.wireframe_width, 0.5..=5.0).text("Wireframe width"),
+        );
+
+        ui.horizontal(|ui| {
+            ui.label("Light direction");
+            for component in &mut self.light_direction {
+                ui.add(
+                    egui::DragValue::new(component)
+                        .speed(0.01)
+                        .clamp_range(-1.0..=1.0),
+                );
+            }
+        });
+        ui.checkbox(&mut self.view_from_light, "View from light");
+
+        ui.add(
+            egui::Slider::new(&mut self.ray_t_min, 0.0..=10.0)
+                .logarithmic(true)
+                .text("Ray T-min"),
+        );
+        ui.add(
+            egui::Slider::new(&mut self.ray_t_max, 1.0..=10000.0)
+                .logarithmic(true)
+                .text("Ray T-max"),
+        );
+
+        ui.collapsing("Fog", |ui| self.fog.ui(ui));
+        ui.collapsing("Background", |ui| self.background.ui(ui));
+        ui.collapsing("Color grading", |ui| self.color_grading.ui(ui));
+    }
+
+    /// Inverse of the encodings of the light view and of every [`RtDebugMode`] in `traceScene()`,
+    /// for values read back from an image in `format`. An sRGB image holds the shader's output
+    /// encoded, which is undone first. The light view's depth assumes neutral color grading, the
+    /// debug modes aren't graded. Returns [`None`] when the image shows neither.
+    pub fn pixel_decoder(
+        &self,
+        scene_bounds: &Aabb,
+        format: Format,
+    ) -> Option<impl Fn([f32; 4]) -> DecodedPixel> {
+        let radius = scene_bounds.radius();
+        let srgb = is_srgb_format(format);
+        let debug_mode = self.debug_mode;
+        // Same interval as `Self::constants()` hands to the shader
+        let (t_min, t_max) = (self.ray_t_min, self.ray_t_max.max(self.ray_t_min));
+        (debug_mode != RtDebugMode::Off || self.view_from_light).then_some(move |rgba: [f32; 4]| {
+            let rgb = [rgba[0], rgba[1], rgba[2]].map(|c| if srgb { srgb_to_linear(c) } else { c });
+            // Misses are black in every mode. Neither normals nor barycentrics can be,
+            // distances at the far end of their range can't be told apart from misses.
+            if rgb == [0.0; 3] {
+                return DecodedPixel::Miss;
+            }
+            match debug_mode {
+                // Only reached for the light view, which the debug modes replace
+                RtDebugMode::Off => {
+                    DecodedPixel::HitDistance(radius + (1.0 - rgb[0]) * 2.0 * radius)
+                }
+                RtDebugMode::HitDistance => {
+                    DecodedPixel::HitDistance(t_min + (1.0 - rgb[0]) * (t_max - t_min))
+                }
+                RtDebugMode::Barycentrics => DecodedPixel::Barycentrics(rgb),
+                RtDebugMode::InstanceIndex => DecodedPixel::InstanceHash,
+                RtDebugMode::GeometryNormal | RtDebugMode::ShadingNormal => {
+                    DecodedPixel::Normal(rgb.map(|c| c * 2.0 - 1.0))
+                }
+            }
+        })
+    }
+
+    fn constants(&self, scene_bounds: &Aabb, viewport_size: [u32; 2]) -> RaytracerConstants {
+        RaytracerConstants {
+            view_mode: self.view_mode as u32,
+            wireframe_opacity: self.wireframe_opacity,
+            wireframe_width: self.wireframe_width,
+            view_from_light: self.view_from_light.into(),
+            light_direction: self.light_direction,
+            scene_radius: scene_bounds.radius(),
+            scene_center: scene_bounds.center(),
+            _pad0: 0.0,
+            viewport_size: viewport_size.map(|c| c as f32),
+            gamma: self.color_grading.gamma,
+            contrast: self.color_grading.contrast,
+            saturation: self.color_grading.saturation,
+            use_view_direction: self.view_direction.is_some().into(),
+            view_direction: self.view_direction.unwrap_or_default(),
+            ray_t_min: self.ray_t_min,
+            ray_t_max: self.ray_t_max.max(self.ray_t_min),
+            _pad1: 0.0,
+            render_region: self.render_region.unwrap_or([0, 0, u32::MAX, u32::MAX]),
+            fog_mode: self.fog.mode as u32,
+            fog_density: self.fog.density,
+            fog_start: self.fog.start,
+            fog_end: self.fog.end.max(self.fog.start + f32::EPSILON),
+            fog_color: self.fog.color,
+            _pad2: 0.0,
+            debug_mode: self.debug_mode as u32,
+            _pad3: [0; 3],
+        }
+    }
+}
+
+/// Mirrors `RaytracerConstants` in `constants.hlsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct RaytracerConstants {
+    view_mode: u32,
+    wireframe_opacity: f32,
+    wireframe_width: f32,
+    view_from_light: u32,
+    light_direction: [f32; 3],
+    scene_radius: f32,
+    scene_center: [f32; 3],
+    _pad0: f32,
+    viewport_size: [f32; 2],
+    gamma: f32,
+    contrast: f32,
+    saturation: f32,
+    use_view_direction: u32,
+    view_direction: [f32; 3],
+    ray_t_min: f32,
+    ray_t_max: f32,
+    _pad1: f32,
+    render_region: [u32; 4],
+    fog_mode: u32,
+    fog_density: f32,
+    fog_start: f32,
+    fog_end: f32,
+    fog_color: [f32; 3],
+    _pad2: f32,
+    debug_mode: u32,
+    _pad3: [u32; 3],
+}
+
+/// Per-frame depth target for the main pass. It lives in the render graph, so it always matches
+/// `size` without having to be recreated on resize. `samples` must match the color target's.
+/// The main pass clears it and discards it afterwards, so it only depth tests the main pass' own
+/// draws. A pass added after it that depth tests against the scene needs it stored, like
+/// [`MsaaTarget::depth_store_op()`] does for --depth-resolve.
+pub fn create_depth_target(
+    render_graph: &mut RenderGraph,
+    size: [u32; 2],
+    samples: u32,
+) -> RenderGraphTexture {
+    render_graph.create_texture(
+        "main pass depth",
+        &TextureCreateDesc::depth_target_2d(size, Format::D32Sfloat).with_sample_count(samples),
+    )
+}
+
+/// The constant buffers [`record_main_pass()`] rewrites every frame
+pub struct MainPassBuffers {
+    constants: FrameRing<RaytracerConstants>,
+    user_uniforms: FrameRing<u32>,
+    camera: FrameRing<CameraConstants>,
+    background: FrameRing<BackgroundConstants>,
+    instance_colors: FrameRing<InstanceColorConstants>,
+}
+
+impl MainPassBuffers {
+    pub fn new(device: &dyn Device, fence_timeout: FenceTimeout) -> Self {
+        Self {
+            constants: FrameRing::new(device, "raytracer constants", 1, fence_timeout),
+            user_uniforms: FrameRing::new(
+                device,
+                "user uniforms",
+                UserUniformValues::default().0.len(),
+                fence_timeout,
+            ),
+            camera: FrameRing::new(device, "camera constants", 1, fence_timeout),
+            background: FrameRing::new(device, "background constants", 1, fence_timeout),
+            instance_colors: FrameRing::new(device, "instance color constants", 1, fence_timeout),
+        }
+    }
+
+    /// Hands over the fence of the submission the frame's [`record_main_pass()`] went into, call
+    /// once per frame
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.constants.advance(fence.clone());
+        self.user_uniforms.advance(fence.clone());
+        self.camera.advance(fence.clone());
+        self.background.advance(fence.clone());
+        self.instance_colors.advance(fence);
+    }
+}
+
+/// Records the instance colors, the background and the raytracing pass over it into `target`,
+/// shared by the windowed and headless paths so that both produce identical images.
+///
+/// With `msaa` both raster passes draw into its multisampled target instead, which is then
+/// resolved into `target`. Returns the main pass' depth resolved by
+/// [`MsaaTarget::resolve_depth()`], when it asks for that. [`DrawMode::Deferred`] ignores `msaa`
+/// and replaces the raytracing pass with the passes of a [`GBuffer`].
+pub fn record_main_pass(
+    render_graph: &mut RenderGraph,
+    target: &RenderGraphTexture,
+    target_size: [u32; 2],
+    scene: &TriangleScene,
+    shader_db: &AssetsShaderDatabase,
+    settings: &RaytracerSettings,
+    camera: Option<&Camera>,
+    shader_clock: Option<&Buffer>,
+    msaa: Option<&MsaaTarget>,
+    buffers: &mut MainPassBuffers,
+) -> Result<Option<RenderGraphTexture>> {
+    let tlas = render_graph.import_tlas(scene.raytracing.tlas());
+    let constants_rg = render_graph.import_buffer(
+        buffers
+            .constants
+            .write(&[settings.constants(&scene.bounds, target_size)])?,
+    );
+    let user_uniforms_rg =
+        render_graph.import_buffer(buffers.user_uniforms.write(&settings.user_uniforms.0)?);
+    let camera_rg = render_graph.import_buffer(
+        buffers
+            .camera
+            .write(&[camera.map(Camera::constants).unwrap_or_default()])?,
+    );
+    let shader_clock_rg = shader_clock.map(|buffer| render_graph.import_buffer(buffer));
+
+    // Rasterized coverage only lines up with the rays of the main view
+    let orthographic_view = settings.view_from_light || settings.view_direction.is_some();
+    let draw_mode = if shader_clock.is_some() || orthographic_view {
+        DrawMode::FullscreenTriangle
+    } else {
+        settings.draw_mode
+    };
+    // The G-buffer is single-sampled, its passes draw to `target` directly
+    let gbuffer =
+        (draw_mode == DrawMode::Deferred).then(|| GBuffer::create(render_graph, target_size));
+    let msaa = msaa.filter(|_| gbuffer.is_none());
+
+    let samples = msaa.map_or(1, |msaa| msaa.samples);
+    let color_rg = msaa.map_or(target, |msaa| &msaa.color);
+    let depth_rg = create_depth_target(render_graph, target_size, samples);
+
+    let shading_buffers_rg = scene
+        .shading_buffers()
+        .map(|buffer| render_graph.import_buffer(buffer));
+    let raster_buffers_rg = draw_mode.rasterizes_scene().then(|| {
+        scene
+            .raster_buffers()
+            .map(|buffer| render_graph.import_buffer(buffer))
+    });
+
+    let instance_colors_rg = record_instance_colors(
+        render_graph,
+        scene.instance_count,
+        settings.instance_color_seed,
+        &mut buffers.instance_colors,
+        shader_db,
+    )?;
+
+    settings.background.record(
+        render_graph,
+        color_rg,
+        target_size,
+        samples,
+        &mut buffers.background,
+        shader_db,
+    )?;
+
+    // Rays that miss discard their pixel, leaving the background in place. The G-buffer pass
+    // binds the same buffers, but draws into the G-buffer instead.
+    let mut main_pass = RasterPass::new("Main pass", render_graph).sample_count(samples);
+    main_pass = if let Some(gbuffer) = &gbuffer {
+        main_pass
+            .render_target(&gbuffer.albedo, LoadOp::Load, StoreOp::Store)
+            .render_target(&gbuffer.normal, LoadOp::Load, StoreOp::Store)
+    } else {
+        main_pass.render_target(color_rg, LoadOp::Load, StoreOp::Store)
+    };
+    main_pass = main_pass
+        .depth_target(
+            &depth_rg,
+            LoadOp::Clear,
+            msaa.map_or(StoreOp::Discard, MsaaTarget::depth_store_op),
+        )
+        .tlas(&tlas)
+        .read_buffer(&constants_rg)
+        .read_buffer(&user_uniforms_rg)
+        .read_buffer(&camera_rg)
+        .read_buffer(&instance_colors_rg);
+    for buffer_rg in &shading_buffers_rg {
+        main_pass = main_pass.read_buffer(buffer_rg);
+    }
+    let pipeline = if let Some(shader_clock_rg) = &shader_clock_rg {
+        // Measures whichever view mode is selected, without swapping pipelines
+        main_pass = main_pass.rw_buffer(shader_clock_rg);
+        shader_db.get_pipeline("{{ template_id }}-raytracer-shader-clock")
+    } else if let Some(raster_buffers_rg) = &raster_buffers_rg {
+        for buffer_rg in raster_buffers_rg {
+            main_pass = main_pass.read_buffer(buffer_rg);
+        }
+        if gbuffer.is_some() {
+            shader_db.get_pipeline("{{ template_id }}-gbuffer")
+        } else {
+            // Honors every view mode, like the shader clock pipeline
+            shader_db.get_pipeline("{{ template_id }}-raytracer-geometry")
+        }
+    } else {
+        shader_db.get_pipeline(main_pipeline_name(settings.view_mode, shader_db))
+    };
+    draw_mode.draw(main_pass, &pipeline, scene);
+
+    if let Some(gbuffer) = &gbuffer {
+        gbuffer.record_lighting(
+            render_graph,
+            target,
+            target_size,
+            &constants_rg,
+            &user_uniforms_rg,
+            shader_db,
+        );
+    }
+    let Some(msaa) = msaa else {
+        return Ok(None);
+    };
+    msaa.resolve(render_graph, target, shader_db);
+    Ok(msaa.resolve_depth(render_graph, &depth_rg, target_size, shader_db))
+}
+
+/// Set once the missing wireframe pipeline has been reported, so it isn't logged every frame
+static MISSING_WIREFRAME_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// The main pass pipeline for `view_mode`, falling back to the shaded pipeline when the shader
+/// database doesn't have the wireframe one
+fn main_pipeline_name(view_mode: ViewMode, shader_db: &AssetsShaderDatabase) -> &'static str {
+    const SHADED: &str = "{{ template_id }}-raytracer";
+    const WIREFRAME: &str = "{{ template_id }}-wireframe";
+
+    if view_mode != ViewMode::Wireframe {
+        return SHADED;
+    }
+    if shader_db.has_pipeline(WIREFRAME) {
+        return WIREFRAME;
+    }
+    if !MISSING_WIREFRAME_WARNED.swap(true, Ordering::Relaxed) {
+        log::warn!(
+            "Shader database has no `{WIREFRAME}` pipeline, drawing the shaded view instead"
+        );
+    }
+    SHADED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(settings: &RaytracerSettings, format: Format, rgba: [f32; 4]) -> DecodedPixel {
+        let bounds = Aabb {
+            min: [-1.0; 3],
+            max: [1.0; 3],
+        };
+        settings.pixel_decoder(&bounds, format).unwrap()(rgba)
+    }
+
+    #[test]
+    fn pixel_decoder_inverts_the_debug_modes() {
+        let settings = |debug_mode| RaytracerSettings {
+            debug_mode,
+            ray_t_min: 1.0,
+            ray_t_max: 5.0,
+            ..Default::default()
+        };
+        let format = Format::R16g16b16a16Sfloat;
+        assert!(RaytracerSettings::default()
+            .pixel_decoder(&Aabb::EMPTY, format)
+            .is_none());
+
+        assert_eq!(
+            decode(
+                &settings(RtDebugMode::HitDistance),
+                format,
+                [0.75, 0.75, 0.75, 1.0]
+            ),
+            DecodedPixel::HitDistance(2.0)
+        );
+        assert_eq!(
+            decode(
+                &settings(RtDebugMode::GeometryNormal),
+                format,
+                [0.5, 1.0, 0.5, 1.0]
+            ),
+            DecodedPixel::Normal([0.0, 1.0, 0.0])
+        );
+        assert_eq!(
+            decode(
+                &settings(RtDebugMode::Barycentrics),
+                format,
+                [0.25, 0.25, 0.5, 1.0]
+            ),
+            DecodedPixel::Barycentrics([0.25, 0.25, 0.5])
+        );
+        assert_eq!(
+            decode(
+                &settings(RtDebugMode::ShadingNormal),
+                format,
+                [0.0, 0.0, 0.0, 1.0]
+            ),
+            DecodedPixel::Miss
+        );
+    }
+
+    #[test]
+    fn pixel_decoder_undoes_srgb_first() {
+        let settings = RaytracerSettings {
+            debug_mode: RtDebugMode::Barycentrics,
+            ..Default::default()
+        };
+        let DecodedPixel::Barycentrics(uvw) =
+            decode(&settings, Format::R8g8b8a8Srgb, [0.5, 0.0, 1.0, 1.0])
+        else {
+            panic!("expected barycentrics");
+        };
+        assert!((uvw[0] - srgb_to_linear(0.5)).abs() < 1e-6);
+        assert_eq!(uvw[1..], [0.0, 1.0]);
+    }
+}
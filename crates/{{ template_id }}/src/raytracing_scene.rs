@@ -0,0 +1,785 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Result};
+use breda::renderer::{
+    AccelerationStructure, AccelerationStructureBuildLocation, Buffer, BufferCreateDesc,
+    BuildFlags, CommandBuffer, Device, Fence, InstanceFlags, QueueSubmitInfo,
+    RaytracingInstanceDesc, TriangleGeometry, TriangleGeometryCreateDesc,
+};
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::Retired,
+    scene::InstanceDescription,
+    upload::UploadHandoff,
+};
+
+/// Uploaded vertex and index buffers shared by every BLAS, along with how each BLAS' geometries
+/// interpret their own range of them
+pub struct SceneGeometry {
+    pub position_buffer: Buffer,
+    pub index_buffer: Buffer,
+    /// The geometries of one BLAS per mesh, referenced by [`InstanceDescription::mesh`]
+    pub meshes: Vec<Vec<TriangleGeometryCreateDesc>>,
+}
+
+/// Offset alignment of every build's region in a shared scratch buffer, covering the
+/// `minAccelerationStructureScratchOffsetAlignment` of all vendors
+const SCRATCH_ALIGNMENT: usize = 256;
+
+/// A TLAS over one BLAS per mesh, owning every resource the acceleration structures reference.
+///
+/// The BLASes are only referenced from the TLAS by their device address, so dropping them, or
+/// the geometry buffers they were built from, would leave the TLAS pointing at freed memory.
+/// Keeping them all in here ties their lifetime to the TLAS.
+pub struct RaytracingScene {
+    tlas: AccelerationStructure,
+    /// Indexed by [`InstanceDescription::mesh`]
+    blases: Vec<AccelerationStructure>,
+    /// Shared by all builds and updates, grown when one needs more than it holds
+    scratch: Buffer,
+    scratch_size: usize,
+    /// Sizes of all of [`Self::blases`] together and of [`Self::tlas`] in bytes, after
+    /// compaction
+    blas_size: usize,
+    tlas_size: usize,
+    /// What the TLAS was last built or updated with, see [`Self::set_instances()`]
+    instances: Vec<RaytracingInstanceDesc>,
+    /// Number of instances the TLAS has room for, the ones beyond [`Self::instances`] are masked
+    /// out. Only grows, see [`Self::rebuild_tlas()`].
+    instance_capacity: usize,
+    /// Whether [`Self::tlas`] was built with [`BuildFlags::ALLOW_UPDATE`], for
+    /// [`Self::instance_capacity`] instances
+    updatable: bool,
+    geometry: SceneGeometry,
+    /// Replaced by [`Self::rebuild_tlas()`] and [`Self::ensure_scratch()`] while the frames in
+    /// flight may still trace against, or build with, the old ones
+    retired_tlases: Retired<AccelerationStructure>,
+    retired_scratch: Retired<Buffer>,
+}
+
+impl RaytracingScene {
+    /// Builds a BLAS over every mesh in `geometry` and a TLAS with one instance per entry in
+    /// `instances`, waiting for the GPU to finish. All BLASes are built in a single batch that
+    /// shares one scratch buffer. The first build waits for `uploads`, which wrote the geometry
+    /// buffers.
+    ///
+    /// With `separate_submissions` each level is built and waited for in its own submission, and
+    /// both build times are logged.
+    ///
+    /// With `compact` the BLASes are copied into acceleration structures of their compacted size
+    /// before the TLAS is built over them. Those sizes are only known once the BLASes are built,
+    /// so this costs an extra submission and wait.
+    pub fn build(
+        device: &dyn Device,
+        geometry: SceneGeometry,
+        uploads: UploadHandoff,
+        instances: &[InstanceDescription],
+        separate_submissions: bool,
+        compact: bool,
+        fence_timeout: FenceTimeout,
+    ) -> Result<Self> {
+        ensure!(
+            !geometry.meshes.is_empty() && geometry.meshes.iter().all(|mesh| !mesh.is_empty()),
+            "Scene needs at least one mesh, each with at least one geometry"
+        );
+        ensure!(!instances.is_empty(), "Scene needs at least one instance");
+        ensure!(
+            instances
+                .iter()
+                .all(|instance| instance.mesh < geometry.meshes.len()),
+            "Scene instances reference meshes beyond the {} it has",
+            geometry.meshes.len()
+        );
+
+        let blas_flags = if compact {
+            BuildFlags::FAST_TRACE | BuildFlags::ALLOW_COMPACTION
+        } else {
+            BuildFlags::FAST_TRACE
+        };
+        let tri_geometries = create_tri_geometries(device, &geometry);
+        let blas_requests = tri_geometries
+            .iter()
+            .enumerate()
+            .map(|(mesh, tri_geometries)| {
+                device.create_blas_build_request(
+                    AccelerationStructureBuildLocation::Device,
+                    blas_flags,
+                    tri_geometries,
+                    &format!("blas {mesh}"),
+                )
+            })
+            .collect::<Vec<_>>();
+        let blas_sizes = blas_requests
+            .iter()
+            .map(|request| {
+                request
+                    .build_info
+                    .size_requirements()
+                    .acceleration_structure_size_in_bytes as usize
+            })
+            .collect::<Vec<_>>();
+        for (mesh, &size) in blas_sizes.iter().enumerate() {
+            log_allocation(
+                ResourceKind::AccelerationStructure,
+                &format!("blas {mesh}"),
+                size,
+                MemoryKind::DeviceLocal,
+            );
+        }
+        let mut scratch_size = batch_scratch_size(
+            blas_requests
+                .iter()
+                .map(|request| request.build_info.size_requirements().scratch_size_in_bytes),
+        );
+        let mut scratch = create_scratch(device, scratch_size);
+
+        // The TLAS has to reference the compacted copies, so compaction happens before it is
+        // created
+        let (blases, blas_size, pending_blas_builds) = if compact {
+            let uncompacted = blas_requests
+                .iter()
+                .map(|request| request.blas.clone())
+                .collect::<Vec<_>>();
+            let size_queries = (0..uncompacted.len())
+                .map(|mesh| {
+                    device.create_buffer(
+                        &format!("blas {mesh} compacted size"),
+                        std::mem::size_of::<u64>(),
+                        &BufferCreateDesc::gpu_to_cpu(),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+            let mut as_enc = cmd.acceleration_structure_encoder();
+            as_enc.batch_build_bottom_level(&blas_requests, &scratch);
+            for (blas, size_query) in uncompacted.iter().zip(&size_queries) {
+                as_enc.write_compacted_size(blas, size_query);
+            }
+            cmd.end_acceleration_structure(as_enc);
+            submit_and_wait(
+                device,
+                cmd,
+                uploads.submit_info(),
+                fence_timeout,
+                "building the blases for compaction",
+            )?;
+
+            let compacted_sizes = size_queries
+                .iter()
+                .map(|size_query| size_query.read_data::<u64>()[0] as usize)
+                .collect::<Vec<_>>();
+            let compacted = compacted_sizes
+                .iter()
+                .enumerate()
+                .map(|(mesh, &size)| {
+                    device.create_compacted_blas(&format!("blas {mesh} (compacted)"), size)
+                })
+                .collect::<Vec<_>>();
+            let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+            let mut as_enc = cmd.acceleration_structure_encoder();
+            for (uncompacted, compacted) in uncompacted.iter().zip(&compacted) {
+                as_enc.copy_compacted(uncompacted, compacted);
+            }
+            cmd.end_acceleration_structure(as_enc);
+            submit_and_wait(
+                device,
+                cmd,
+                QueueSubmitInfo::no_sync(),
+                fence_timeout,
+                "compacting the blases",
+            )?;
+
+            let uncompacted_size = blas_sizes.iter().sum::<usize>();
+            let compacted_size = compacted_sizes.iter().sum::<usize>();
+            log::info!(
+                "Compacted {} BLASes from {uncompacted_size} to {compacted_size} bytes, {:.1}% \
+                 smaller",
+                compacted.len(),
+                100.0 * (1.0 - compacted_size as f64 / uncompacted_size as f64)
+            );
+            for (mesh, &size) in compacted_sizes.iter().enumerate() {
+                log_allocation(
+                    ResourceKind::AccelerationStructure,
+                    &format!("blas {mesh} (compacted)"),
+                    size,
+                    MemoryKind::DeviceLocal,
+                );
+            }
+            (compacted, compacted_size, None)
+        } else {
+            (
+                blas_requests
+                    .iter()
+                    .map(|request| request.blas.clone())
+                    .collect(),
+                blas_sizes.iter().sum(),
+                Some(blas_requests),
+            )
+        };
+
+        let instances = instance_descs(instances, &blas_handles(&blases));
+        let tlas_request = device.create_tlas_build_request_from_instances(
+            AccelerationStructureBuildLocation::Device,
+            BuildFlags::FAST_BUILD,
+            &instances,
+            "tlas",
+        );
+        let instance_capacity = instances.len();
+
+        let tlas_size_requirements = tlas_request.build_info.size_requirements();
+        log::debug!(
+            "TLAS over {} instances needs {} bytes of scratch memory",
+            instances.len(),
+            tlas_size_requirements.scratch_size_in_bytes
+        );
+        log_allocation(
+            ResourceKind::AccelerationStructure,
+            "tlas",
+            tlas_size_requirements.acceleration_structure_size_in_bytes as usize,
+            MemoryKind::DeviceLocal,
+        );
+        // Any BLAS build that used the scratch buffer so far has been waited for
+        if tlas_size_requirements.scratch_size_in_bytes as usize > scratch_size {
+            scratch_size = tlas_size_requirements.scratch_size_in_bytes as usize;
+            scratch = create_scratch(device, scratch_size);
+        }
+
+        let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+
+        match pending_blas_builds {
+            // Built and waited for by the compaction
+            None => {
+                let mut as_enc = cmd.acceleration_structure_encoder();
+                as_enc.build_top_level(&tlas_request, &scratch);
+                cmd.end_acceleration_structure(as_enc);
+                submit_and_wait(
+                    device,
+                    cmd,
+                    QueueSubmitInfo::no_sync(),
+                    fence_timeout,
+                    "building the tlas",
+                )?;
+            }
+            Some(blas_requests) if separate_submissions => {
+                // Build each level in its own submission, so the wait covers nothing but that
+                // build
+                let blas_start = Instant::now();
+                let mut as_enc = cmd.acceleration_structure_encoder();
+                as_enc.batch_build_bottom_level(&blas_requests, &scratch);
+                cmd.end_acceleration_structure(as_enc);
+                submit_and_wait(
+                    device,
+                    cmd,
+                    uploads.submit_info(),
+                    fence_timeout,
+                    "building the blases",
+                )?;
+                let blas_time = blas_start.elapsed();
+
+                let tlas_start = Instant::now();
+                let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+                let mut as_enc = cmd.acceleration_structure_encoder();
+                as_enc.build_top_level(&tlas_request, &scratch);
+                cmd.end_acceleration_structure(as_enc);
+                submit_and_wait(
+                    device,
+                    cmd,
+                    QueueSubmitInfo::no_sync(),
+                    fence_timeout,
+                    "building the tlas",
+                )?;
+                let tlas_time = tlas_start.elapsed();
+
+                log::info!(
+                    "BLAS builds: {:.3} ms, TLAS build: {:.3} ms",
+                    blas_time.as_secs_f64() * 1000.0,
+                    tlas_time.as_secs_f64() * 1000.0
+                );
+            }
+            Some(blas_requests) => {
+                let mut as_enc = cmd.acceleration_structure_encoder();
+                as_enc.batch_build_bottom_level(&blas_requests, &scratch);
+                as_enc.build_top_level(&tlas_request, &scratch);
+                cmd.end_acceleration_structure(as_enc);
+                submit_and_wait(
+                    device,
+                    cmd,
+                    uploads.submit_info(),
+                    fence_timeout,
+                    "building acceleration structures",
+                )?;
+            }
+        }
+        uploads.finish(fence_timeout)?;
+
+        Ok(Self {
+            tlas: tlas_request.tlas,
+            blases,
+            scratch,
+            scratch_size,
+            blas_size,
+            tlas_size: tlas_size_requirements.acceleration_structure_size_in_bytes as usize,
+            instances,
+            instance_capacity,
+            updatable: false,
+            geometry,
+            retired_tlases: Retired::default(),
+            retired_scratch: Retired::default(),
+        })
+    }
+
+    /// Replaces the instances the next [`Self::rebuild_tlas()`] or [`Self::update_instances()`]
+    /// builds the TLAS with. Their custom indices follow their order in `instances`.
+    pub fn set_instances(&mut self, instances: &[InstanceDescription]) -> Result<()> {
+        ensure!(
+            instances
+                .iter()
+                .all(|instance| instance.mesh < self.blases.len()),
+            "Scene instances reference meshes beyond the {} it has",
+            self.blases.len()
+        );
+        self.instances = instance_descs(instances, &blas_handles(&self.blases));
+        Ok(())
+    }
+
+    /// Records a build of the TLAS over the instances from [`Self::set_instances()`] into
+    /// `cmd`, with [`BuildFlags::ALLOW_UPDATE`].
+    ///
+    /// The TLAS is built for [`Self::instance_capacity`] instances, which doubles whenever the
+    /// instances outgrow it. The unused slots are masked out, so removing instances, or adding
+    /// them back within the capacity, keeps the TLAS refittable by [`Self::update_instances()`].
+    /// Without any instances the TLAS only has masked out slots, which every ray misses.
+    ///
+    /// A TLAS that was built updatable for the same capacity is rebuilt in place, without
+    /// allocating a new one. Otherwise the new TLAS replaces the old one, which is kept alive
+    /// until the frames in flight are done with it, see [`Self::end_frame()`].
+    pub fn rebuild_tlas(&mut self, device: &dyn Device, cmd: &mut CommandBuffer) {
+        let capacity = grown_capacity(self.instance_capacity, self.instances.len());
+        let reuse = self.updatable && capacity == self.instance_capacity;
+        if capacity != self.instance_capacity {
+            log::debug!(
+                "Growing the TLAS instance capacity from {} to {capacity}",
+                self.instance_capacity
+            );
+            self.instance_capacity = capacity;
+        }
+
+        let flags = BuildFlags::FAST_BUILD | BuildFlags::ALLOW_UPDATE;
+        let tlas_request = if reuse {
+            device.create_tlas_rebuild_request(&self.tlas, flags, &self.padded_instances())
+        } else {
+            device.create_tlas_build_request_from_instances(
+                AccelerationStructureBuildLocation::Device,
+                flags,
+                &self.padded_instances(),
+                "tlas",
+            )
+        };
+        let size_requirements = tlas_request.build_info.size_requirements();
+        self.ensure_scratch(device, size_requirements.scratch_size_in_bytes as usize);
+        let mut as_enc = cmd.acceleration_structure_encoder();
+        as_enc.build_top_level(&tlas_request, &self.scratch);
+        cmd.end_acceleration_structure(as_enc);
+
+        if reuse {
+            return;
+        }
+        log_allocation(
+            ResourceKind::AccelerationStructure,
+            "updatable tlas",
+            size_requirements.acceleration_structure_size_in_bytes as usize,
+            MemoryKind::DeviceLocal,
+        );
+        let replaced = std::mem::replace(&mut self.tlas, tlas_request.tlas);
+        self.retired_tlases.retire(replaced);
+        self.tlas_size = size_requirements.acceleration_structure_size_in_bytes as usize;
+        self.updatable = true;
+    }
+
+    /// Records new transforms for the instances of the TLAS into `cmd`.
+    ///
+    /// The first call, and any call with more instances than the TLAS has room for, goes through
+    /// [`Self::rebuild_tlas()`]. Later calls refit it in place, which is much cheaper but lets
+    /// the quality of the TLAS degrade the further instances move.
+    pub fn update_instances(
+        &mut self,
+        device: &dyn Device,
+        instances: &[InstanceDescription],
+        cmd: &mut CommandBuffer,
+    ) -> Result<()> {
+        self.set_instances(instances)?;
+        if !self.updatable || self.instances.len() > self.instance_capacity {
+            self.rebuild_tlas(device, cmd);
+            return Ok(());
+        }
+
+        let update_request =
+            device.create_tlas_update_request(&self.tlas, &self.padded_instances());
+        self.ensure_scratch(
+            device,
+            update_request
+                .build_info
+                .size_requirements()
+                .update_scratch_size_in_bytes as usize,
+        );
+        let mut as_enc = cmd.acceleration_structure_encoder();
+        as_enc.update_top_level(&update_request, &self.scratch);
+        cmd.end_acceleration_structure(as_enc);
+        Ok(())
+    }
+
+    /// [`Self::instances`] followed by masked out instances filling the capacity, scaled down to
+    /// a point at the origin
+    fn padded_instances(&self) -> Vec<RaytracingInstanceDesc> {
+        let unused = RaytracingInstanceDesc::new(
+            [0.0; 12],
+            0,
+            0x00,
+            0,
+            InstanceFlags::TRIANGLE_CULL_DISABLE,
+            blas_handles(&self.blases[..1])[0],
+        );
+        padded(&self.instances, self.instance_capacity, unused)
+    }
+
+    /// Number of instances the TLAS has room for, see [`Self::rebuild_tlas()`]
+    pub fn instance_capacity(&self) -> usize {
+        self.instance_capacity
+    }
+
+    /// Builds throwaway BLASes over every mesh of [`Self::geometry()`] `iterations` times, as one
+    /// batch in a submission of its own each, and returns the GPU time of every batch as measured
+    /// by timestamp queries.
+    ///
+    /// Uses the same flags as [`Self::build()`] without compaction. Returns [`None`] when the
+    /// device lacks timestamp query support.
+    pub fn time_blas_builds(
+        &mut self,
+        device: &dyn Device,
+        iterations: u32,
+        fence_timeout: FenceTimeout,
+    ) -> Result<Option<Vec<Duration>>> {
+        let capabilities = device.capabilities();
+        if !capabilities.supports_timestamp_queries {
+            return Ok(None);
+        }
+        let timestamp_period_ns = f64::from(capabilities.timestamp_period_ns);
+
+        let queries = device.create_timestamp_query_pool("blas build timer", 2);
+        let results_size = 2 * std::mem::size_of::<u64>();
+        log_allocation(
+            ResourceKind::Buffer,
+            "blas build timer results",
+            results_size,
+            MemoryKind::Readback,
+        );
+        let results = device.create_buffer(
+            "blas build timer results",
+            results_size,
+            &BufferCreateDesc::gpu_to_cpu(),
+        );
+
+        let tri_geometries = create_tri_geometries(device, &self.geometry);
+        let mut times = Vec::with_capacity(iterations as usize);
+        for _ in 0..iterations {
+            let blas_requests = tri_geometries
+                .iter()
+                .map(|tri_geometries| {
+                    device.create_blas_build_request(
+                        AccelerationStructureBuildLocation::Device,
+                        BuildFlags::FAST_TRACE,
+                        tri_geometries,
+                        "benchmark blas",
+                    )
+                })
+                .collect::<Vec<_>>();
+            self.ensure_scratch(
+                device,
+                batch_scratch_size(
+                    blas_requests.iter().map(|request| {
+                        request.build_info.size_requirements().scratch_size_in_bytes
+                    }),
+                ),
+            );
+
+            let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+            cmd.write_timestamp(&queries, 0);
+            let mut as_enc = cmd.acceleration_structure_encoder();
+            as_enc.batch_build_bottom_level(&blas_requests, &self.scratch);
+            cmd.end_acceleration_structure(as_enc);
+            cmd.write_timestamp(&queries, 1);
+            cmd.resolve_timestamps(&queries, &results);
+            let fence = submit_and_wait(
+                device,
+                cmd,
+                QueueSubmitInfo::no_sync(),
+                fence_timeout,
+                "benchmarking the blas build",
+            )?;
+            // Nothing else ends frames while the benchmark runs, release the replaced scratch here
+            self.end_frame(&fence);
+
+            let [begin, end] = results.read_data::<[u64; 2]>()[0];
+            let ns = end.saturating_sub(begin) as f64 * timestamp_period_ns;
+            times.push(Duration::from_nanos(ns as u64));
+        }
+        Ok(Some(times))
+    }
+
+    fn ensure_scratch(&mut self, device: &dyn Device, size: usize) {
+        if size > self.scratch_size {
+            let replaced = std::mem::replace(&mut self.scratch, create_scratch(device, size));
+            self.retired_scratch.retire(replaced);
+            self.scratch_size = size;
+        }
+    }
+
+    /// Hands over the fence of the frame's submission and drops the TLASes and scratch buffers
+    /// that only finished frames used, call once per frame
+    pub fn end_frame(&mut self, fence: &Fence) {
+        self.retired_tlases.end_frame(fence);
+        self.retired_scratch.end_frame(fence);
+    }
+
+    /// The buffers and geometries the BLASes were built from
+    pub fn geometry(&self) -> &SceneGeometry {
+        &self.geometry
+    }
+
+    pub fn blas_size(&self) -> usize {
+        self.blas_size
+    }
+
+    pub fn tlas_size(&self) -> usize {
+        self.tlas_size
+    }
+
+    pub fn scratch_size(&self) -> usize {
+        self.scratch_size
+    }
+
+    /// The TLAS, for `RenderGraph::import_tlas()`
+    pub fn tlas(&self) -> &AccelerationStructure {
+        &self.tlas
+    }
+}
+
+fn blas_handles(blases: &[AccelerationStructure]) -> Vec<u64> {
+    blases
+        .iter()
+        .map(|blas| unsafe { blas.blas_handle(AccelerationStructureBuildLocation::Device) })
+        .collect()
+}
+
+/// `capacity` if it holds `count` instances, the next power of two otherwise, and at least one
+fn grown_capacity(capacity: usize, count: usize) -> usize {
+    if count > capacity {
+        count.next_power_of_two()
+    } else {
+        capacity.max(1)
+    }
+}
+
+/// `items` followed by copies of `filler` up to `capacity`, but never truncated
+fn padded<T: Clone>(items: &[T], capacity: usize, filler: T) -> Vec<T> {
+    let mut padded = items.to_vec();
+    padded.resize(capacity.max(items.len()), filler);
+    padded
+}
+
+/// One descriptor per instance, pointing at the BLAS of its mesh in `blas_handles`, with its
+/// index as custom index
+fn instance_descs(
+    instances: &[InstanceDescription],
+    blas_handles: &[u64],
+) -> Vec<RaytracingInstanceDesc> {
+    instances
+        .iter()
+        .enumerate()
+        .map(|(instance_custom_index, instance)| {
+            RaytracingInstanceDesc::new(
+                instance.transform,
+                instance_custom_index as u32,
+                0xff,
+                instance.hit_group_offset,
+                InstanceFlags::TRIANGLE_CULL_DISABLE,
+                blas_handles[instance.mesh],
+            )
+        })
+        .collect()
+}
+
+/// The geometries of every mesh's BLAS
+fn create_tri_geometries(
+    device: &dyn Device,
+    geometry: &SceneGeometry,
+) -> Vec<Vec<TriangleGeometry>> {
+    geometry
+        .meshes
+        .iter()
+        .map(|descs| {
+            descs
+                .iter()
+                .map(|desc| {
+                    device.create_tri_geometry(
+                        "inline tri geom",
+                        &geometry.position_buffer,
+                        Some(&geometry.index_buffer),
+                        None,
+                        desc,
+                        None,
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Scratch memory for building BLASes that need `sizes` in a single
+/// `batch_build_bottom_level()`, which gives every build its own aligned region of the buffer
+fn batch_scratch_size(sizes: impl IntoIterator<Item = u64>) -> usize {
+    sizes
+        .into_iter()
+        .map(|size| (size as usize).next_multiple_of(SCRATCH_ALIGNMENT))
+        .sum()
+}
+
+fn create_scratch(device: &dyn Device, size: usize) -> Buffer {
+    device.create_buffer(
+        "acceleration_structure_scratch",
+        size,
+        &BufferCreateDesc::gpu_only_scratch_build(),
+    )
+}
+
+fn submit_and_wait(
+    device: &dyn Device,
+    cmd: CommandBuffer,
+    info: QueueSubmitInfo,
+    fence_timeout: FenceTimeout,
+    what: &str,
+) -> Result<Fence> {
+    let fence = device.get_gfx_queue().lock().submit(vec![cmd], info);
+    fence_timeout.wait(&fence, what)?;
+    Ok(fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math;
+
+    /// Builds the default scene and keeps nothing of it but its [`RaytracingScene`]. Every
+    /// buffer the scene was built from goes out of scope in here, along with the rest of the
+    /// [`crate::scene::TriangleScene`].
+    #[cfg(not(target_os = "android"))]
+    fn build_and_drop_the_rest(
+        device: &dyn Device,
+        opts: &crate::CommandlineOpts,
+    ) -> Result<RaytracingScene> {
+        let scene = crate::scene::TriangleScene::new(
+            device,
+            &opts.scene,
+            opts.fence_timeout.timeout(),
+            &crate::scene::SceneDescription::from_opts(&opts.scene)?,
+        )?;
+        Ok(scene.raytracing)
+    }
+
+    /// Run with `cargo test -- --ignored` on a machine with such a GPU
+    #[cfg(not(target_os = "android"))]
+    #[test]
+    #[ignore = "needs a GPU with inline raytracing"]
+    fn tlas_outlives_the_builder() -> Result<()> {
+        use clap::Parser;
+
+        // Validation reports acceleration structures referencing freed memory
+        let opts = crate::CommandlineOpts::parse_from(["raytracing_scene_test", "--validation"]);
+        let mut breda_desc = opts.breda.into();
+        opts.validation.apply(&mut breda_desc);
+        let breda = breda::Breda::new("raytracing_scene_test", breda_desc)?;
+        let device = breda
+            .devices()
+            .find(|d| d.capabilities().supports_inline_ray_tracing)
+            .cloned()
+            .expect("No device supports inline raytracing");
+        let device = device.as_ref();
+        let fence_timeout = opts.fence_timeout.timeout();
+
+        let mut scene = build_and_drop_the_rest(device, &opts)?;
+
+        // A refit reads the TLAS and the BLAS addresses it holds, a rebuild the BLASes again
+        let instances = [InstanceDescription {
+            transform: math::IDENTITY,
+            mesh: 0,
+            hit_group_offset: 0,
+        }];
+        for rebuild in [true, false] {
+            let mut cmd = device.get_gfx_queue().lock().create_command_buffer();
+            if rebuild {
+                scene.set_instances(&instances)?;
+                scene.rebuild_tlas(device, &mut cmd);
+            } else {
+                scene.update_instances(device, &instances, &mut cmd)?;
+            }
+            submit_and_wait(
+                device,
+                cmd,
+                QueueSubmitInfo::no_sync(),
+                fence_timeout,
+                "building against the kept tlas",
+            )?;
+        }
+        assert_eq!(crate::validation::error_count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn capacity_grows_to_powers_of_two() {
+        assert_eq!(grown_capacity(0, 0), 1);
+        assert_eq!(grown_capacity(0, 3), 4);
+        assert_eq!(grown_capacity(4, 3), 4);
+        assert_eq!(grown_capacity(4, 4), 4);
+        assert_eq!(grown_capacity(4, 5), 8);
+        // Never shrinks when instances are removed, so the TLAS stays refittable
+        assert_eq!(grown_capacity(8, 1), 8);
+    }
+
+    #[test]
+    fn padding_fills_up_to_the_capacity() {
+        assert_eq!(padded(&[1, 2, 3], 4, 0), [1, 2, 3, 0]);
+        assert_eq!(padded(&[1, 2, 3], 3, 0), [1, 2, 3]);
+        assert_eq!(padded(&[1, 2, 3], 2, 0), [1, 2, 3]);
+        assert_eq!(padded(&[], 2, 0), [0, 0]);
+    }
+
+    #[test]
+    fn instances_point_at_the_blas_of_their_mesh() {
+        let instance = |mesh, hit_group_offset| InstanceDescription {
+            transform: math::translation([mesh as f32, 0.0, 0.0]),
+            mesh,
+            hit_group_offset,
+        };
+        let instances = [instance(1, 0), instance(0, 2), instance(1, 1)];
+        // Handles of the BLASes of mesh 0 and 1
+        let descs = instance_descs(&instances, &[100, 200]);
+
+        let expected = |index: usize, blas_handle| {
+            RaytracingInstanceDesc::new(
+                instances[index].transform,
+                index as u32,
+                0xff,
+                instances[index].hit_group_offset,
+                InstanceFlags::TRIANGLE_CULL_DISABLE,
+                blas_handle,
+            )
+        };
+        assert_eq!(
+            descs,
+            [expected(0, 200), expected(1, 100), expected(2, 200)]
+        );
+    }
+}
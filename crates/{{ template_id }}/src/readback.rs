@@ -0,0 +1,252 @@
+use anyhow::{ensure, Context, Result};
+use breda::renderer::{Buffer, BufferCreateDesc, CommandBuffer, Device, Format, Texture};
+use clap::{Args, ValueEnum};
+use half::f16;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+
+use crate::allocations::{log_allocation, MemoryKind, ResourceKind};
+
+/// Row pitch alignment required for texture-to-buffer copies across all backends
+const ROW_PITCH_ALIGNMENT: usize = 256;
+
+/// Pixel format of offscreen targets that are read back to the CPU
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReadbackFormat {
+    #[default]
+    Rgba8,
+    Bgra8,
+    Rgba16f,
+    /// 10 bits per color channel and 2 alpha bits packed into 32, like HDR10 swapchains
+    Rgb10a2,
+}
+
+/// Unpacks an `A2B10G10R10` texel: red in the lowest bits, alpha in the highest
+pub fn unpack_rgb10a2(bytes: [u8; 4]) -> [f32; 4] {
+    let packed = u32::from_le_bytes(bytes);
+    let [r, g, b] = [0, 10, 20].map(|shift| ((packed >> shift) & 0x3ff) as f32 / 1023.0);
+    [r, g, b, (packed >> 30) as f32 / 3.0]
+}
+
+impl ReadbackFormat {
+    pub fn texture_format(self) -> Format {
+        match self {
+            Self::Rgba8 => Format::R8g8b8a8Unorm,
+            Self::Bgra8 => Format::B8g8r8a8Unorm,
+            Self::Rgba16f => Format::R16g16b16a16Sfloat,
+            Self::Rgb10a2 => Format::A2b10g10r10UnormPack32,
+        }
+    }
+
+    /// Inverse of [`Self::texture_format()`], for textures that weren't created from a
+    /// [`ReadbackFormat`] such as swapchain images.
+    ///
+    /// sRGB formats map to their UNORM counterparts: the copy transfers the encoded bytes, which
+    /// is what PNGs and GIFs store, so they are saved without applying the transfer function twice.
+    pub fn from_texture_format(format: Format) -> Option<Self> {
+        match format {
+            Format::R8g8b8a8Unorm | Format::R8g8b8a8Srgb => Some(Self::Rgba8),
+            Format::B8g8r8a8Unorm | Format::B8g8r8a8Srgb => Some(Self::Bgra8),
+            Format::R16g16b16a16Sfloat => Some(Self::Rgba16f),
+            Format::A2b10g10r10UnormPack32 => Some(Self::Rgb10a2),
+            _ => None,
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Bgra8 | Self::Rgb10a2 => 4,
+            Self::Rgba16f => 8,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ReadbackOpts {
+    /// Pixel format of the offscreen target rendered to in headless modes
+    #[clap(long, value_enum, default_value_t)]
+    pub readback_format: ReadbackFormat,
+}
+
+/// A host-visible buffer that receives a copy of a texture in one of the [`ReadbackFormat`]s
+pub struct TextureReadback {
+    buffer: Buffer,
+    size: [u32; 2],
+    format: ReadbackFormat,
+    row_pitch: usize,
+}
+
+impl TextureReadback {
+    pub fn new(device: &dyn Device, name: &str, size: [u32; 2], format: ReadbackFormat) -> Self {
+        let row_pitch =
+            (size[0] as usize * format.bytes_per_pixel()).next_multiple_of(ROW_PITCH_ALIGNMENT);
+        let buffer_size = row_pitch * size[1] as usize;
+        let buffer = device.create_buffer(name, buffer_size, &BufferCreateDesc::gpu_to_cpu());
+        log_allocation(
+            ResourceKind::Buffer,
+            name,
+            buffer_size,
+            MemoryKind::Readback,
+        );
+
+        Self {
+            buffer,
+            size,
+            format,
+            row_pitch,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn format(&self) -> ReadbackFormat {
+        self.format
+    }
+
+    /// Records a copy of `texture` into the readback buffer
+    pub fn record_copy(&self, cmd: &mut CommandBuffer, texture: &Texture) -> Result<()> {
+        let texture_size = texture.size();
+        ensure!(
+            texture_size == self.size,
+            "Cannot read back a {}x{} texture into a {}x{} readback buffer",
+            texture_size[0],
+            texture_size[1],
+            self.size[0],
+            self.size[1]
+        );
+        ensure!(
+            ReadbackFormat::from_texture_format(texture.format()) == Some(self.format),
+            "Cannot read back a {:?} texture as {:?}",
+            texture.format(),
+            self.format
+        );
+        cmd.copy_texture_to_buffer(texture, &self.buffer, self.row_pitch);
+        Ok(())
+    }
+
+    /// Records a copy of the region of `texture` starting at `origin` with the size of the
+    /// readback buffer, for reading back a few pixels without copying the whole texture
+    pub fn record_region_copy(
+        &self,
+        cmd: &mut CommandBuffer,
+        texture: &Texture,
+        origin: [u32; 2],
+    ) -> Result<()> {
+        let texture_size = texture.size();
+        ensure!(
+            origin[0] + self.size[0] <= texture_size[0]
+                && origin[1] + self.size[1] <= texture_size[1],
+            "Region {}x{} at {},{} is outside of the {}x{} texture",
+            self.size[0],
+            self.size[1],
+            origin[0],
+            origin[1],
+            texture_size[0],
+            texture_size[1]
+        );
+        ensure!(
+            ReadbackFormat::from_texture_format(texture.format()) == Some(self.format),
+            "Cannot read back a {:?} texture as {:?}",
+            texture.format(),
+            self.format
+        );
+        cmd.copy_texture_region_to_buffer(texture, origin, self.size, &self.buffer, self.row_pitch);
+        Ok(())
+    }
+
+    /// Returns the rows of the readback buffer without their alignment padding, in
+    /// [`Self::format()`]
+    pub fn packed_bytes(&self) -> Vec<u8> {
+        let data = self.buffer.read_data::<u8>();
+        let row_size = self.size[0] as usize * self.format.bytes_per_pixel();
+
+        data.chunks_exact(self.row_pitch)
+            .take(self.size[1] as usize)
+            .flat_map(|row| &row[..row_size])
+            .copied()
+            .collect()
+    }
+
+    /// Converts the contents to RGBA texels in row-major order. Unlike [`Self::read_image()`]
+    /// `Rgba16f` values are returned as they are, without clamping.
+    ///
+    /// Must only be called once the submission containing the copy has completed.
+    pub fn read_texels(&self) -> Vec<[f32; 4]> {
+        let bytes = self.packed_bytes();
+        match self.format {
+            ReadbackFormat::Rgba8 | ReadbackFormat::Bgra8 => bytes
+                .chunks_exact(4)
+                .map(|c| {
+                    let texel = [c[0], c[1], c[2], c[3]].map(|c| f32::from(c) / 255.0);
+                    if self.format == ReadbackFormat::Bgra8 {
+                        [texel[2], texel[1], texel[0], texel[3]]
+                    } else {
+                        texel
+                    }
+                })
+                .collect(),
+            ReadbackFormat::Rgba16f => bytes
+                .chunks_exact(8)
+                .map(|c| {
+                    std::array::from_fn(|i| f16::from_le_bytes([c[i * 2], c[i * 2 + 1]]).to_f32())
+                })
+                .collect(),
+            ReadbackFormat::Rgb10a2 => bytes
+                .chunks_exact(4)
+                .map(|c| unpack_rgb10a2([c[0], c[1], c[2], c[3]]))
+                .collect(),
+        }
+    }
+
+    /// Converts the contents to an image with RGBA channel order. 8-bit formats produce an 8-bit
+    /// image, `Rgba16f` is clamped to 0-1 and kept at 16 bits per channel to avoid losing
+    /// precision, like `Rgb10a2`. Values are stored as they are, see
+    /// [`crate::color_mode::encode_framebuffer()`] for converting HDR contents for display.
+    ///
+    /// Must only be called once the submission containing [`Self::record_copy()`] has completed.
+    pub fn read_image(&self) -> Result<DynamicImage> {
+        let [width, height] = self.size;
+        let mut bytes = self.packed_bytes();
+
+        let image = match self.format {
+            ReadbackFormat::Rgba8 => DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(width, height, bytes).context("Readback size mismatch")?,
+            ),
+            ReadbackFormat::Bgra8 => {
+                for pixel in bytes.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+                DynamicImage::ImageRgba8(
+                    RgbaImage::from_raw(width, height, bytes).context("Readback size mismatch")?,
+                )
+            }
+            ReadbackFormat::Rgba16f => {
+                let channels = bytes
+                    .chunks_exact(2)
+                    .map(|c| {
+                        let value = f16::from_le_bytes([c[0], c[1]]).to_f32();
+                        (value.clamp(0.0, 1.0) * f32::from(u16::MAX)).round() as u16
+                    })
+                    .collect();
+                DynamicImage::ImageRgba16(
+                    ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, channels)
+                        .context("Readback size mismatch")?,
+                )
+            }
+            ReadbackFormat::Rgb10a2 => {
+                let channels = bytes
+                    .chunks_exact(4)
+                    .flat_map(|c| unpack_rgb10a2([c[0], c[1], c[2], c[3]]))
+                    .map(|value| (value * f32::from(u16::MAX)).round() as u16)
+                    .collect();
+                DynamicImage::ImageRgba16(
+                    ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, channels)
+                        .context("Readback size mismatch")?,
+                )
+            }
+        };
+
+        Ok(image)
+    }
+}
@@ -0,0 +1,113 @@
+use std::{collections::HashMap, fmt::Write, path::PathBuf};
+
+use anyhow::{Context, Result};
+use breda::render_graph::{CompiledRenderGraph, RenderGraph};
+use clap::Args;
+
+/// Where F8 writes the render graph without --dump-graph, relative to the working directory
+const DEFAULT_DUMP_PATH: &str = "render_graph.dot";
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct RenderGraphDebugOpts {
+    /// Make the render graph insert full barriers between all passes instead of the minimal
+    /// ones it derives from resource usage. A bug that disappears with this points at a missing
+    /// or incorrect barrier.
+    #[clap(long)]
+    pub conservative_barriers: bool,
+    /// Write the first frame's compiled render graph to this Graphviz DOT file: its passes in
+    /// execution order and the resources each of them reads and writes. F8 writes the graph of
+    /// the current frame again, to `render_graph.dot` without this flag.
+    #[clap(long, value_name = "PATH")]
+    pub dump_graph: Option<PathBuf>,
+}
+
+impl RenderGraphDebugOpts {
+    /// Applies the debug settings to a freshly created render graph
+    pub fn configure(&self, render_graph: &mut RenderGraph) {
+        if self.conservative_barriers {
+            render_graph.set_conservative_barriers(true);
+        }
+    }
+}
+
+/// Writes a compiled render graph to a DOT file when requested, see
+/// [`RenderGraphDebugOpts::dump_graph`]
+pub struct GraphDump {
+    path: PathBuf,
+    requested: bool,
+}
+
+impl GraphDump {
+    pub fn new(opts: &RenderGraphDebugOpts) -> Self {
+        Self {
+            path: opts
+                .dump_graph
+                .clone()
+                .unwrap_or_else(|| DEFAULT_DUMP_PATH.into()),
+            requested: opts.dump_graph.is_some(),
+        }
+    }
+
+    /// Writes the graph passed to the next [`Self::write_if_requested()`]
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Call between compiling and executing every frame's graph. A failed write is logged and
+    /// not retried, it shouldn't take the frame down with it.
+    pub fn write_if_requested(&mut self, compiled_rg: &CompiledRenderGraph) {
+        if !std::mem::take(&mut self.requested) {
+            return;
+        }
+        let result = std::fs::write(&self.path, to_dot(compiled_rg))
+            .with_context(|| format!("Failed to write `{}`", self.path.display()));
+        match result {
+            Ok(()) => log::info!("Wrote the render graph to `{}`", self.path.display()),
+            Err(e) => log::warn!("{e:#}"),
+        }
+    }
+}
+
+fn quote(label: &str) -> String {
+    format!("\"{}\"", label.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Graphviz DOT of `compiled_rg`, with a box per pass labeled with its position in execution
+/// order and an ellipse per resource. Edges run from every resource to the passes reading it and
+/// from every pass to the resources it writes, so a resource that is both read and written by a
+/// pass gets an edge either way.
+///
+/// Resources are told apart by name, resources sharing one show up as a single node.
+pub fn to_dot(compiled_rg: &CompiledRenderGraph) -> String {
+    let mut resources = HashMap::<String, usize>::new();
+    let mut nodes = String::new();
+    let mut edges = String::new();
+
+    let mut resource_node = |name: &str, nodes: &mut String| {
+        let next = resources.len();
+        let index = *resources.entry(name.to_owned()).or_insert(next);
+        if index == next {
+            let _ = writeln!(
+                nodes,
+                "    r{index} [label={}, shape=ellipse];",
+                quote(name)
+            );
+        }
+        format!("r{index}")
+    };
+
+    for (index, pass) in compiled_rg.passes().enumerate() {
+        let label = quote(&format!("{index}: {}", pass.name()));
+        let _ = writeln!(nodes, "    p{index} [label={label}, shape=box];");
+        for name in pass.reads() {
+            let resource = resource_node(name, &mut nodes);
+            let _ = writeln!(edges, "    {resource} -> p{index};");
+        }
+        for name in pass.writes() {
+            let resource = resource_node(name, &mut nodes);
+            let _ = writeln!(edges, "    p{index} -> {resource};");
+        }
+    }
+
+    format!("digraph render_graph {{\n    rankdir=LR;\n{nodes}{edges}}}\n")
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use breda::renderer::{CommandBuffer, Device, Fence, Texture};
+use clap::Args;
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    imageops::{self, FilterType},
+    Delay, Frame, RgbaImage,
+};
+
+use crate::{
+    color_mode::{encode_framebuffer, ColorMode},
+    device_loss::wait_unless_lost,
+    fence_timeout::FenceTimeout,
+    frame_ring::{Retired, FRAMES_IN_FLIGHT},
+    readback::{ReadbackFormat, TextureReadback},
+};
+
+pub const DEFAULT_REPLAY_MAX_MB: usize = 256;
+pub const DEFAULT_REPLAY_DOWNSAMPLE: u32 = 2;
+
+/// Frames are captured at this rate rather than every frame, which is plenty for a GIF
+const CAPTURE_FPS: f32 = 15.0;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ReplayOpts {
+    /// Keep the last SECONDS of downsampled frames in memory, and save them as a GIF when F9 is
+    /// pressed
+    #[clap(long, value_name = "SECONDS")]
+    pub replay_seconds: Option<f32>,
+    /// Upper bound on the memory used by --replay-seconds, older frames are dropped first
+    #[clap(long, value_name = "MB")]
+    pub replay_max_mb: Option<usize>,
+    /// Factor by which frames kept for --replay-seconds are downsampled
+    #[clap(long, value_name = "N")]
+    pub replay_downsample: Option<u32>,
+    /// Where --replay-seconds GIFs are written to
+    #[clap(long, default_value = "replay.gif")]
+    pub replay_output: PathBuf,
+}
+
+struct ReplayFrame {
+    image: RgbaImage,
+    captured_at: Instant,
+}
+
+struct Slot {
+    readback: TextureReadback,
+    fence: Option<Fence>,
+    captured_at: Instant,
+}
+
+/// Retroactive capture: a bounded ring of recent frames, read back asynchronously and encoded
+/// into a GIF on request
+pub struct ReplayBuffer {
+    duration: Duration,
+    max_bytes: usize,
+    downsample: u32,
+    output: PathBuf,
+    /// Of the swapchain, which the captured frames are decoded from like screenshots
+    color_mode: ColorMode,
+    fence_timeout: FenceTimeout,
+
+    slots: Vec<Slot>,
+    current: usize,
+    /// Whether the current slot had a copy recorded this frame, and needs [`Self::end_frame()`]
+    recorded: bool,
+    last_capture: Option<Instant>,
+    /// Slots of the previous size whose copies were still in flight when it changed
+    retired_slots: Retired<Slot>,
+
+    frames: VecDeque<ReplayFrame>,
+    frame_bytes: usize,
+    encoder: Option<JoinHandle<Result<PathBuf>>>,
+}
+
+impl ReplayBuffer {
+    /// Returns [`None`] unless `--replay-seconds` is passed
+    pub fn new(
+        opts: &ReplayOpts,
+        color_mode: ColorMode,
+        fence_timeout: FenceTimeout,
+    ) -> Option<Self> {
+        let seconds = opts.replay_seconds?;
+
+        Some(Self {
+            duration: Duration::from_secs_f32(seconds.max(0.0)),
+            max_bytes: opts.replay_max_mb.unwrap_or(DEFAULT_REPLAY_MAX_MB) * 1024 * 1024,
+            downsample: opts
+                .replay_downsample
+                .unwrap_or(DEFAULT_REPLAY_DOWNSAMPLE)
+                .max(1),
+            output: opts.replay_output.clone(),
+            color_mode,
+            fence_timeout,
+            slots: Vec::new(),
+            current: 0,
+            recorded: false,
+            last_capture: None,
+            retired_slots: Retired::default(),
+            frames: VecDeque::new(),
+            frame_bytes: 0,
+            encoder: None,
+        })
+    }
+
+    /// When the next capture is due, collects the oldest in-flight capture and records a copy of
+    /// `image` into `cmd`. Call after everything that should end up in the capture is recorded.
+    pub fn capture(
+        &mut self,
+        device: &dyn Device,
+        cmd: &mut CommandBuffer,
+        image: &Texture,
+    ) -> Result<()> {
+        let now = Instant::now();
+        let capture_interval = Duration::from_secs_f32(1.0 / CAPTURE_FPS);
+        if self
+            .last_capture
+            .is_some_and(|last| now - last < capture_interval)
+        {
+            return Ok(());
+        }
+
+        let format = ReadbackFormat::from_texture_format(image.format())
+            .with_context(|| format!("Cannot capture {:?} images for replays", image.format()))?;
+        if self
+            .slots
+            .first()
+            .is_some_and(|slot| slot.readback.size() != image.size())
+        {
+            // Frames of different sizes can't go into a single GIF, start over at the new size.
+            // Copies still in flight are dropped along with the frames, but their buffers are
+            // kept until the GPU is done writing them.
+            for slot in self.slots.drain(..) {
+                if slot.fence.is_some() {
+                    self.retired_slots.retire(slot);
+                }
+            }
+            self.frames.clear();
+            self.frame_bytes = 0;
+        }
+        if self.slots.is_empty() {
+            self.slots = (0..FRAMES_IN_FLIGHT)
+                .map(|i| Slot {
+                    readback: TextureReadback::new(
+                        device,
+                        &format!("replay readback {i}"),
+                        image.size(),
+                        format,
+                    ),
+                    fence: None,
+                    captured_at: now,
+                })
+                .collect();
+            self.current = 0;
+        }
+
+        self.collect_current()?;
+
+        let slot = &mut self.slots[self.current];
+        slot.readback.record_copy(cmd, image)?;
+        slot.captured_at = now;
+        self.recorded = true;
+        self.last_capture = Some(now);
+        Ok(())
+    }
+
+    /// Drops the readback slots, which live on `device`, for example before switching devices.
+    /// Waits for the copies still in flight first, unless `device` is lost. Already collected
+    /// frames are kept.
+    pub fn reset(&mut self, device: &dyn Device) -> Result<()> {
+        let slots = self.slots.iter().chain(self.retired_slots.iter());
+        for fence in slots.filter_map(|slot| slot.fence.as_ref()) {
+            wait_unless_lost(device, || {
+                self.fence_timeout
+                    .wait(fence, "copying a replay frame before dropping it")
+            })?;
+        }
+        self.slots.clear();
+        self.retired_slots = Retired::default();
+        self.recorded = false;
+        Ok(())
+    }
+
+    /// Hands over the fence of the submission containing the copy from [`Self::capture()`], if any
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.retired_slots.end_frame(&fence);
+        if std::mem::take(&mut self.recorded) {
+            self.slots[self.current].fence = Some(fence);
+            self.current = (self.current + 1) % self.slots.len();
+        }
+    }
+
+    fn collect_current(&mut self) -> Result<()> {
+        let slot = &mut self.slots[self.current];
+        let Some(fence) = slot.fence.take() else {
+            return Ok(());
+        };
+        self.fence_timeout.wait(&fence, "copying a replay frame")?;
+
+        let [width, height] = slot.readback.size();
+        let bytes = encode_framebuffer(
+            &slot.readback.packed_bytes(),
+            slot.readback.format(),
+            self.color_mode,
+        )?;
+        let image = RgbaImage::from_raw(width, height, bytes).context("Readback size mismatch")?;
+        let image = if self.downsample > 1 {
+            imageops::resize(
+                &image,
+                (image.width() / self.downsample).max(1),
+                (image.height() / self.downsample).max(1),
+                FilterType::Triangle,
+            )
+        } else {
+            image
+        };
+
+        self.frame_bytes += image.as_raw().len();
+        self.frames.push_back(ReplayFrame {
+            image,
+            captured_at: slot.captured_at,
+        });
+
+        let oldest_kept = slot.captured_at.checked_sub(self.duration);
+        while let Some(front) = self.frames.front() {
+            let too_old = oldest_kept.is_some_and(|oldest| front.captured_at < oldest);
+            if !too_old && self.frame_bytes <= self.max_bytes {
+                break;
+            }
+            self.frame_bytes -= front.image.as_raw().len();
+            self.frames.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Encodes the buffered frames into a GIF on a background thread
+    pub fn save(&mut self) {
+        if self.is_saving() {
+            log::warn!("Still encoding the previous replay");
+            return;
+        }
+        if self.frames.is_empty() {
+            log::warn!("No replay frames captured yet");
+            return;
+        }
+
+        let frames = self
+            .frames
+            .iter()
+            .zip(self.frames.iter().skip(1).map(Some).chain([None]))
+            .map(|(frame, next)| {
+                let duration = next.map_or(Duration::from_secs_f32(1.0 / CAPTURE_FPS), |next| {
+                    next.captured_at - frame.captured_at
+                });
+                Frame::from_parts(
+                    frame.image.clone(),
+                    0,
+                    0,
+                    Delay::from_saturating_duration(duration),
+                )
+            })
+            .collect::<Vec<_>>();
+        let output = self.output.clone();
+
+        self.encoder = Some(std::thread::spawn(move || {
+            let file = File::create(&output)
+                .with_context(|| format!("Failed to create `{}`", output.display()))?;
+            let mut encoder = GifEncoder::new(BufWriter::new(file));
+            encoder.set_repeat(Repeat::Infinite)?;
+            encoder.encode_frames(frames)?;
+            Ok(output)
+        }));
+    }
+
+    pub fn is_saving(&self) -> bool {
+        self.encoder
+            .as_ref()
+            .is_some_and(|encoder| !encoder.is_finished())
+    }
+
+    /// Reports the result of a finished [`Self::save()`], call once per frame
+    pub fn poll_save(&mut self) {
+        if self.is_saving() {
+            return;
+        }
+        if let Some(encoder) = self.encoder.take() {
+            match encoder.join().expect("Replay encoder thread panicked") {
+                Ok(output) => log::info!("Saved replay to `{}`", output.display()),
+                Err(e) => log::error!("Failed to save replay: {e:?}"),
+            }
+        }
+    }
+
+    /// Length of the time span currently buffered
+    pub fn buffered_duration(&self) -> Duration {
+        match (self.frames.front(), self.frames.back()) {
+            (Some(front), Some(back)) => back.captured_at - front.captured_at,
+            _ => Duration::ZERO,
+        }
+    }
+
+    pub fn buffered_bytes(&self) -> usize {
+        self.frame_bytes
+    }
+}
@@ -0,0 +1,252 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use clap::Args;
+use serde_json::{json, Value};
+
+use crate::{
+    mesh::Mesh,
+    raytracer::RaytracerSettings,
+    scene::{InstanceDescription, SceneDescription},
+};
+
+/// Bumped whenever the layout of the exported directory changes
+const REPRO_VERSION: u64 = 3;
+
+const MANIFEST: &str = "manifest.json";
+const POSITIONS: &str = "positions.bin";
+const NORMALS: &str = "normals.bin";
+const INDICES: &str = "indices.bin";
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ReproOpts {
+    /// Write the scene and settings selected by the other options to this directory and exit,
+    /// to be reproduced later with --load-repro
+    #[clap(long, value_name = "DIR")]
+    pub export_repro: Option<PathBuf>,
+}
+
+/// Writes the geometry as raw little-endian buffers next to a manifest with the instances, the
+/// raytracer settings and the command line the scene was generated with.
+///
+/// Only the geometry and instances are read back by [`load_repro()`], the rest documents how to
+/// get the same settings again.
+pub fn export_repro(
+    dir: &Path,
+    description: &SceneDescription,
+    settings: &RaytracerSettings,
+) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+
+    // The meshes are written back to back, the manifest holds the size of each
+    let SceneDescription { meshes, instances } = description;
+    let vectors = |vectors: fn(&Mesh) -> &[[f32; 3]]| {
+        meshes
+            .iter()
+            .flat_map(vectors)
+            .flatten()
+            .flat_map(|c| c.to_le_bytes())
+            .collect::<Vec<_>>()
+    };
+    let (positions, normals) = (
+        vectors(|mesh| &mesh.positions),
+        vectors(|mesh| &mesh.normals),
+    );
+    let indices = meshes
+        .iter()
+        .flat_map(|mesh| &mesh.indices)
+        .flat_map(|i| i.to_le_bytes())
+        .collect::<Vec<_>>();
+    for (name, bytes) in [
+        (POSITIONS, positions),
+        (NORMALS, normals),
+        (INDICES, indices),
+    ] {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    }
+
+    let manifest = json!({
+        "version": REPRO_VERSION,
+        "positions": POSITIONS,
+        "normals": NORMALS,
+        "indices": INDICES,
+        "meshes": meshes
+            .iter()
+            .map(|mesh| json!({
+                "vertex_count": mesh.positions.len(),
+                "index_count": mesh.indices.len(),
+            }))
+            .collect::<Vec<_>>(),
+        "instances": instances
+            .iter()
+            .map(|instance| json!({
+                "transform": instance.transform,
+                "mesh": instance.mesh,
+                "hit_group_offset": instance.hit_group_offset,
+            }))
+            .collect::<Vec<_>>(),
+        "settings": {
+            "view_mode": settings.view_mode.name(),
+            "view_from_light": settings.view_from_light,
+            "view_direction": settings.view_direction,
+            "light_direction": settings.light_direction,
+            "ray_t_min": settings.ray_t_min,
+            "ray_t_max": settings.ray_t_max,
+        },
+        "command_line": std::env::args().collect::<Vec<_>>(),
+    });
+    let path = dir.join(MANIFEST);
+    std::fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write `{}`", path.display()))?;
+
+    log::info!("Exported repro to `{}`", dir.display());
+    Ok(())
+}
+
+fn read_words<const N: usize>(path: &Path) -> Result<Vec<[u8; 4]>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+    ensure!(
+        bytes.len() % (4 * N) == 0,
+        "`{}` is {} bytes, which is not a multiple of {}",
+        path.display(),
+        bytes.len(),
+        4 * N
+    );
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|word| word.try_into().unwrap())
+        .collect())
+}
+
+fn manifest_field<'a>(manifest: &'a Value, field: &str) -> Result<&'a Value> {
+    manifest
+        .get(field)
+        .with_context(|| format!("Repro manifest is missing `{field}`"))
+}
+
+/// Reads back the scene written by [`export_repro()`]
+pub fn load_repro(dir: &Path) -> Result<SceneDescription> {
+    let manifest_path = dir.join(MANIFEST);
+    let manifest = serde_json::from_str::<Value>(
+        &std::fs::read_to_string(&manifest_path)
+            .with_context(|| format!("Failed to read `{}`", manifest_path.display()))?,
+    )
+    .with_context(|| format!("Invalid JSON in `{}`", manifest_path.display()))?;
+
+    let version = manifest_field(&manifest, "version")?.as_u64();
+    ensure!(
+        version == Some(REPRO_VERSION),
+        "Repro version {version:?} is not supported, expected {REPRO_VERSION}"
+    );
+
+    let file = |field| -> Result<PathBuf> {
+        let name = manifest_field(&manifest, field)?
+            .as_str()
+            .with_context(|| format!("`{field}` must be a file name"))?;
+        Ok(dir.join(name))
+    };
+    let vectors = |field| -> Result<Vec<[f32; 3]>> {
+        Ok(read_words::<3>(&file(field)?)?
+            .chunks_exact(3)
+            .map(|p| std::array::from_fn(|i| f32::from_le_bytes(p[i])))
+            .collect())
+    };
+    let (positions, normals) = (vectors("positions")?, vectors("normals")?);
+    ensure!(
+        normals.len() == positions.len(),
+        "Repro has {} normals for {} positions",
+        normals.len(),
+        positions.len()
+    );
+    let indices = read_words::<1>(&file("indices")?)?
+        .into_iter()
+        .map(u32::from_le_bytes)
+        .collect::<Vec<_>>();
+
+    let (mut positions, mut normals, mut indices) =
+        (positions.as_slice(), normals.as_slice(), indices.as_slice());
+    let meshes = manifest_field(&manifest, "meshes")?
+        .as_array()
+        .context("`meshes` must be an array")?
+        .iter()
+        .enumerate()
+        .map(|(index, mesh)| {
+            let count = |field| {
+                mesh.get(field)
+                    .and_then(Value::as_u64)
+                    .map(|count| count as usize)
+                    .with_context(|| format!("Mesh {index} needs a `{field}`"))
+            };
+            let (vertex_count, index_count) = (count("vertex_count")?, count("index_count")?);
+            ensure!(
+                vertex_count <= positions.len() && index_count <= indices.len(),
+                "Mesh {index} reaches beyond the end of the repro buffers"
+            );
+            let (mesh_positions, rest) = positions.split_at(vertex_count);
+            positions = rest;
+            let (mesh_normals, rest) = normals.split_at(vertex_count);
+            normals = rest;
+            let (mesh_indices, rest) = indices.split_at(index_count);
+            indices = rest;
+            ensure!(
+                mesh_indices.iter().all(|&i| (i as usize) < vertex_count),
+                "Mesh {index} indices reference vertices beyond its {vertex_count} positions"
+            );
+            Ok(Mesh {
+                positions: mesh_positions.to_vec(),
+                normals: mesh_normals.to_vec(),
+                indices: mesh_indices.to_vec(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let instances = manifest_field(&manifest, "instances")?
+        .as_array()
+        .context("`instances` must be an array")?
+        .iter()
+        .enumerate()
+        .map(|(index, instance)| {
+            let transform = instance
+                .get("transform")
+                .and_then(Value::as_array)
+                .filter(|transform| transform.len() == 12)
+                .and_then(|transform| {
+                    transform
+                        .iter()
+                        .map(|v| v.as_f64().map(|v| v as f32))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .with_context(|| format!("Instance {index} needs a `transform` of 12 numbers"))?;
+            let mesh = instance
+                .get("mesh")
+                .and_then(Value::as_u64)
+                .map(|mesh| mesh as usize)
+                .filter(|&mesh| mesh < meshes.len())
+                .with_context(|| {
+                    format!("Instance {index} needs a `mesh` below {}", meshes.len())
+                })?;
+            let hit_group_offset = instance
+                .get("hit_group_offset")
+                .and_then(Value::as_u64)
+                .with_context(|| format!("Instance {index} needs a `hit_group_offset`"))?;
+            Ok(InstanceDescription {
+                transform: transform.try_into().unwrap(),
+                mesh,
+                hit_group_offset: hit_group_offset as u32,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    log::info!(
+        "Loaded repro from `{}`: {} meshes, {} triangles, {} instances",
+        dir.display(),
+        meshes.len(),
+        meshes.iter().map(Mesh::triangle_count).sum::<usize>(),
+        instances.len()
+    );
+    Ok(SceneDescription { meshes, instances })
+}
@@ -0,0 +1,156 @@
+use anyhow::{bail, Result};
+use breda::renderer::Device;
+use clap::Args;
+
+use crate::validation;
+
+pub const DEFAULT_RESIZE_STRESS_CYCLES: usize = 300;
+
+/// Window sizes cycled through, including degenerate and very large ones
+const SIZES: &[[u32; 2]] = &[
+    [1, 1],
+    [1280, 720],
+    [1, 2048],
+    [640, 480],
+    [2048, 1],
+    [3840, 2160],
+    [17, 9],
+    [1920, 1080],
+    [4096, 4096],
+    [333, 777],
+];
+
+/// Frames to render at every size before checking it and moving on to the next one
+const SETTLE_FRAMES: u32 = 3;
+
+/// Growth in device memory usage that is tolerated between the end of the first pass over all
+/// sizes and the end of the run
+const LEAK_TOLERANCE_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ResizeStressOpts {
+    /// Repeatedly resize the window through a sequence of sizes to stress swapchain recreation,
+    /// then exit with an error if device memory grew, the swapchain didn't follow or the
+    /// validation layers reported an error. Implies --validation.
+    #[clap(long)]
+    pub resize_stress: bool,
+    /// Number of resizes to perform with --resize-stress
+    #[clap(long, value_name = "N")]
+    pub resize_stress_cycles: Option<usize>,
+}
+
+pub enum ResizeStressStep {
+    Continue,
+    /// Request the window to be resized to this size
+    Resize([u32; 2]),
+    Finished,
+}
+
+pub struct ResizeStress {
+    total_cycles: usize,
+    cycle: usize,
+    frames_at_size: u32,
+    /// Sizes the window system refused to apply, e.g. because they are below the minimum size
+    mismatches: usize,
+    /// Device memory usage after the first pass over all sizes, once caches are warm
+    baseline_memory: Option<u64>,
+    /// [`validation::error_count()`] before the first resize
+    validation_errors_at_start: usize,
+}
+
+impl ResizeStress {
+    pub fn new(opts: &ResizeStressOpts) -> Option<Self> {
+        opts.resize_stress.then(|| Self {
+            total_cycles: opts
+                .resize_stress_cycles
+                .unwrap_or(DEFAULT_RESIZE_STRESS_CYCLES),
+            cycle: 0,
+            frames_at_size: 0,
+            mismatches: 0,
+            baseline_memory: None,
+            validation_errors_at_start: validation::error_count(),
+        })
+    }
+
+    fn requested_size(&self) -> [u32; 2] {
+        SIZES[self.cycle % SIZES.len()]
+    }
+
+    /// Call at the start of every frame with the current swapchain size
+    pub fn frame(
+        &mut self,
+        device: &dyn Device,
+        swapchain_size: [u32; 2],
+    ) -> Result<ResizeStressStep> {
+        if self.cycle == 0 && self.frames_at_size == 0 {
+            self.frames_at_size += 1;
+            return Ok(ResizeStressStep::Resize(self.requested_size()));
+        }
+
+        self.frames_at_size += 1;
+        if self.frames_at_size < SETTLE_FRAMES {
+            return Ok(ResizeStressStep::Continue);
+        }
+
+        let requested = self.requested_size();
+        if swapchain_size != requested {
+            log::warn!(
+                "Resize stress cycle {}: requested {requested:?} but the swapchain is {swapchain_size:?}",
+                self.cycle
+            );
+            self.mismatches += 1;
+        }
+
+        self.cycle += 1;
+        self.frames_at_size = 0;
+
+        if self.cycle == SIZES.len() {
+            self.baseline_memory = Some(device.allocated_memory_bytes());
+        }
+
+        if self.cycle < self.total_cycles {
+            return Ok(ResizeStressStep::Resize(self.requested_size()));
+        }
+
+        self.finish(device)?;
+        Ok(ResizeStressStep::Finished)
+    }
+
+    fn finish(&self, device: &dyn Device) -> Result<()> {
+        let memory = device.allocated_memory_bytes();
+        log::info!(
+            "Resize stress finished after {} cycles, {} size mismatches, {memory} bytes of device memory in use",
+            self.cycle,
+            self.mismatches
+        );
+
+        if let Some(baseline) = self.baseline_memory {
+            if memory > baseline + LEAK_TOLERANCE_BYTES {
+                bail!(
+                    "Device memory grew from {baseline} to {memory} bytes over {} resizes, resources are leaking",
+                    self.cycle
+                );
+            }
+        }
+
+        let validation_errors = validation::error_count() - self.validation_errors_at_start;
+        if validation_errors > 0 {
+            bail!(
+                "The validation layers reported {validation_errors} errors over {} resizes",
+                self.cycle
+            );
+        }
+
+        // Sizes below the platform minimum are legitimately clamped, but the swapchain should
+        // follow the bulk of them
+        if self.mismatches > self.cycle / 2 {
+            bail!(
+                "Swapchain did not match the requested size in {} of {} cycles",
+                self.mismatches,
+                self.cycle
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use clap::Args;
+
+pub const DEFAULT_SEED: u64 = 0;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct SeedOpts {
+    /// Seed of everything random, like the instance colors and the accumulation jitter. With the
+    /// same seed and --frames, headless output is byte-identical on the same device and driver.
+    /// Golden-image tests rely on this, so it must hold for any randomness that is added.
+    #[clap(long, value_name = "SEED")]
+    pub seed: Option<u64>,
+}
+
+impl SeedOpts {
+    pub fn rng(&self) -> Rng {
+        Rng::new(self.seed.unwrap_or(DEFAULT_SEED))
+    }
+}
+
+/// SplitMix64, the single source of randomness of every mode, seeded by --seed.
+///
+/// Nothing may draw from the OS, the time or an unseeded generator instead. Values are drawn in
+/// the same order in every mode, see [`crate::raytracer::RaytracerSettings::new()`], so that the
+/// same seed gives the same scene regardless of which mode renders it.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    /// Uniform in `0..1`
+    pub fn next_f32(&mut self) -> f32 {
+        // The top 24 bits fill the mantissa exactly
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
@@ -0,0 +1,1014 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use breda::{
+    egui,
+    renderer::{
+        create_buffer_with_data, AccelerationStructureBuildLocation, Buffer, BufferCreateDesc,
+        CommandBuffer, Device, Fence, GeometryFlags, IndexBufferFormat, TriangleGeometryCreateDesc,
+        VertexFormat,
+    },
+};
+
+use clap::{Args, ValueEnum};
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::{FrameRing, Retired},
+    gltf_scene,
+    math::{self, Transform},
+    memory_stats::MemoryStats,
+    mesh::{Aabb, Mesh},
+    obj,
+    raytracing_scene::{RaytracingScene, SceneGeometry},
+    repro, scene_json,
+    upload::{UploadBatcher, UploadOpts},
+};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct SceneOpts {
+    #[clap(flatten)]
+    pub upload: UploadOpts,
+    /// Merge vertices closer than this distance before uploading meshes, unless their normals
+    /// differ, which keeps hard edges
+    #[clap(long, value_name = "EPSILON")]
+    pub weld: Option<f32>,
+    /// Replace the default scene with a mesh generated on the CPU
+    #[clap(long, value_enum)]
+    pub proc_scene: Option<ProcScene>,
+    /// Tessellation level of --proc-scene
+    #[clap(long, value_name = "N")]
+    pub subdivisions: Option<u32>,
+    /// Build the BLASes and the TLAS each in a submission of its own and wait for it, logging
+    /// the two build times separately, for benchmarking the builds in isolation from the uploads
+    /// and from each other. Acceleration structures are never cached across runs either way.
+    #[clap(long)]
+    pub separate_as_submissions: bool,
+    /// Compact the BLAS after building it, which saves memory on large meshes but costs an extra
+    /// wait for the GPU while loading the scene
+    #[clap(long)]
+    pub compact_as: bool,
+    /// Geometry flags of the mesh. Without `opaque` every hit runs through the raytracer's
+    /// candidate loop, which is slower but allows alpha testing.
+    #[clap(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [GeometryFlag::Opaque]
+    )]
+    pub geometry_flags: Vec<GeometryFlag>,
+    /// Mesh file to render instead of the default scene, the format is picked by its extension:
+    /// `.obj`, `.gltf` or `.glb`. A `.json` file arranges several of them instead, as a `meshes`
+    /// array of objects with a `path` and optionally a `position`, `rotation` in degrees and
+    /// `scale`.
+    #[clap(long, alias = "mesh", value_name = "PATH")]
+    pub scene: Option<PathBuf>,
+    /// Load the scene from a directory written by --export-repro instead
+    #[clap(long, value_name = "DIR")]
+    pub load_repro: Option<PathBuf>,
+    /// Upper bound on the number of TLAS instances, further instances are dropped with a warning.
+    /// The device's own limit applies regardless.
+    #[clap(long, value_name = "N")]
+    pub max_instances: Option<usize>,
+    /// Spin every instance around the view axis through its center, refitting the TLAS every
+    /// frame
+    #[clap(long, value_name = "RADIANS_PER_SECOND")]
+    pub spin: Option<f32>,
+    /// Instance the scene's meshes N times N on a grid in the view plane, alternating between
+    /// them. The instances of a mesh share its BLAS, and every instance is shaded in its own color.
+    #[clap(long, value_name = "N")]
+    pub instance_grid: Option<u32>,
+    /// Uniformly scale the whole scene around the center of its bounds
+    #[clap(long, value_name = "FACTOR")]
+    pub scene_scale: Option<f32>,
+    /// A `.glb` file that was read without a path, like from the Android asset manager where there
+    /// is no command line to pass --scene on. It takes the place of --scene when that is not set.
+    #[clap(skip)]
+    pub embedded_scene: Option<EmbeddedScene>,
+}
+
+/// Contents of a `.glb` file, see [`SceneOpts::embedded_scene`]
+#[derive(Clone)]
+pub struct EmbeddedScene {
+    pub name: String,
+    pub bytes: Arc<[u8]>,
+}
+
+impl std::fmt::Debug for EmbeddedScene {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddedScene")
+            .field("name", &self.name)
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GeometryFlag {
+    /// No flags
+    None,
+    /// Skip any-hit processing, hits are committed right away
+    Opaque,
+    /// Guarantee a single any-hit invocation per primitive
+    NoDuplicateAnyHit,
+}
+
+impl GeometryFlag {
+    pub fn to_geometry_flags(flags: &[Self]) -> GeometryFlags {
+        flags
+            .iter()
+            .fold(GeometryFlags::empty(), |geometry_flags, flag| {
+                geometry_flags
+                    | match flag {
+                        Self::None => GeometryFlags::empty(),
+                        Self::Opaque => GeometryFlags::OPAQUE,
+                        Self::NoDuplicateAnyHit => GeometryFlags::NO_DUPLICATE_ANY_HIT_INVOCATION,
+                    }
+            })
+    }
+}
+
+pub const DEFAULT_SUBDIVISIONS: u32 = 32;
+
+/// Where generated and loaded meshes are placed, in the pixel-sized units the raytracer's
+/// orthographic camera looks at
+const PROC_SCENE_CENTER: [f32; 3] = [250.0, 250.0, 300.0];
+const PROC_SCENE_SIZE: f32 = 300.0;
+
+/// Procedurally generated replacement for the default scene
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ProcScene {
+    Sphere,
+    Torus,
+    Plane,
+}
+
+impl ProcScene {
+    pub fn mesh(self, subdivisions: u32) -> Mesh {
+        let radius = PROC_SCENE_SIZE * 0.5;
+        let mut mesh = match self {
+            Self::Sphere => Mesh::sphere(radius, subdivisions),
+            Self::Torus => Mesh::torus(radius * 0.7, radius * 0.3, subdivisions),
+            Self::Plane => Mesh::plane(PROC_SCENE_SIZE, subdivisions),
+        };
+        mesh.translate(PROC_SCENE_CENTER);
+        mesh
+    }
+}
+
+/// Whether --scene points at a description of several meshes rather than a mesh itself
+fn is_scene_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Loads an `.obj`, `.gltf` or `.glb` file as it is, picked by its extension
+pub fn load_mesh_file(path: &Path) -> Result<Mesh> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("obj") => obj::load_obj_scene(path),
+        Some(extension)
+            if extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb") =>
+        {
+            gltf_scene::load_gltf_scene(path)
+        }
+        _ => bail!(
+            "Unsupported scene format `{}`, expected an `.obj`, `.gltf` or `.glb` file",
+            path.display()
+        ),
+    }
+}
+
+/// Scales and moves `bounds` into the region procedural meshes occupy, flipping Y so that Y-up
+/// assets appear upright in the raytracer's Y-down orthographic view
+pub fn fit_to_view_transform(bounds: &Aabb) -> Transform {
+    let extent = (0..3)
+        .map(|i| bounds.max[i] - bounds.min[i])
+        .fold(0.0f32, f32::max);
+    let scale = if extent > 0.0 {
+        PROC_SCENE_SIZE / extent
+    } else {
+        1.0
+    };
+    math::mul(
+        &math::translation(PROC_SCENE_CENTER),
+        &math::mul(
+            &math::scale([scale, -scale, scale]),
+            &math::translation(bounds.center().map(|c| -c)),
+        ),
+    )
+}
+
+/// Applies [`fit_to_view_transform()`] to a loaded mesh
+fn fit_to_view(mut mesh: Mesh) -> Mesh {
+    let transform = fit_to_view_transform(&mesh.bounds());
+    for p in &mut mesh.positions {
+        *p = math::transform_point(&transform, *p);
+    }
+    for n in &mut mesh.normals {
+        *n = math::transform_normal(&transform, *n);
+    }
+    mesh
+}
+
+/// Placement and hit behavior of a single TLAS instance
+#[derive(Clone, Debug)]
+pub struct InstanceDescription {
+    /// Object-to-world transform
+    pub transform: Transform,
+    /// Index of the placed mesh in [`SceneDescription::meshes`], and of its BLAS
+    pub mesh: usize,
+    /// Hit group offset (`InstanceContributionToHitGroupIndex`) of this instance.
+    ///
+    /// There is no shader binding table with inline raytracing, but the value is still readable
+    /// through `RayQuery::CommittedInstanceContributionToHitGroupIndex()`. The raytracer uses it to
+    /// select the hit behavior, see [`Material`].
+    pub hit_group_offset: u32,
+}
+
+/// Two copies of the first mesh next to each other that shade differently through their hit
+/// group offsets
+pub fn default_instances() -> Vec<InstanceDescription> {
+    vec![
+        InstanceDescription {
+            transform: math::IDENTITY,
+            mesh: 0,
+            hit_group_offset: 0,
+        },
+        InstanceDescription {
+            transform: math::translation([350.0, 0.0, 0.0]),
+            mesh: 0,
+            hit_group_offset: 1,
+        },
+    ]
+}
+
+/// Hit behaviors of the raytracer, one per hit group offset, which `traceScene()` in
+/// `main.ps.hlsl` branches on through its `HIT_GROUP_*` constants.
+///
+/// The custom index of every instance stays its index in the TLAS, which the shaders use to look
+/// up its color and mesh. Offsets without a material are shaded like [`Self::Magenta`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Material {
+    Magenta = 0,
+    Orange = 1,
+    /// The color of the instance in `instanceColors`
+    InstanceColor = 2,
+}
+
+impl Material {
+    pub const ALL: [Self; 3] = [Self::Magenta, Self::Orange, Self::InstanceColor];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Magenta => "Magenta",
+            Self::Orange => "Orange",
+            Self::InstanceColor => "Instance color",
+        }
+    }
+
+    pub fn from_hit_group_offset(hit_group_offset: u32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|material| *material as u32 == hit_group_offset)
+    }
+}
+
+/// Mirrors `HIT_GROUP_INSTANCE_COLOR` in `main.ps.hlsl`
+pub const HIT_GROUP_INSTANCE_COLOR: u32 = Material::InstanceColor as u32;
+
+/// Shown when no other scene is selected: a triangle and a quad next to it, each in a BLAS of its
+/// own and colored by its instance index through [`HIT_GROUP_INSTANCE_COLOR`]
+fn default_scene() -> (Vec<Mesh>, Vec<InstanceDescription>) {
+    let triangle = Mesh {
+        positions: vec![
+            [100.0f32, 100.1f32, 100.0f32],
+            [200.0f32, 100.2f32, 3.1f32],
+            [302.0f32, 403.0f32, 3.2f32],
+        ],
+        normals: vec![],
+        indices: vec![0u32, 1, 2],
+    };
+    let quad = Mesh {
+        positions: vec![
+            [450.0, 100.0, 50.0],
+            [650.0, 100.0, 50.0],
+            [650.0, 300.0, 50.0],
+            [450.0, 300.0, 50.0],
+        ],
+        normals: vec![],
+        indices: vec![0, 1, 2, 0, 2, 3],
+    };
+    let instances = (0..2)
+        .map(|mesh| InstanceDescription {
+            transform: math::IDENTITY,
+            mesh,
+            hit_group_offset: HIT_GROUP_INSTANCE_COLOR,
+        })
+        .collect();
+    (vec![triangle, quad], instances)
+}
+
+/// Room between neighboring instances of [`grid_instances()`], relative to the mesh size
+const GRID_SPACING: f32 = 1.25;
+
+/// `n * n` instances laid out on the XY plane around the meshes' own positions, cycling through
+/// `mesh_count` meshes within `mesh_bounds`. Shaded through [`HIT_GROUP_INSTANCE_COLOR`] so each
+/// one gets a color derived from its index.
+pub fn grid_instances(n: u32, mesh_bounds: &Aabb, mesh_count: usize) -> Vec<InstanceDescription> {
+    let spacing = (0..2)
+        .map(|i| mesh_bounds.max[i] - mesh_bounds.min[i])
+        .fold(0.0f32, f32::max)
+        * GRID_SPACING;
+    let offset = |i: u32| (i as f32 - (n - 1) as f32 * 0.5) * spacing;
+
+    (0..n)
+        .flat_map(|y| (0..n).map(move |x| (x, y)))
+        .map(|(x, y)| InstanceDescription {
+            transform: math::translation([offset(x), offset(y), 0.0]),
+            mesh: (x + y) as usize % mesh_count.max(1),
+            hit_group_offset: HIT_GROUP_INSTANCE_COLOR,
+        })
+        .collect()
+}
+
+/// CPU-side contents of a scene, before anything is uploaded
+#[derive(Clone, Debug)]
+pub struct SceneDescription {
+    /// Built into one BLAS each
+    pub meshes: Vec<Mesh>,
+    pub instances: Vec<InstanceDescription>,
+}
+
+impl SceneDescription {
+    /// The scene selected on the command line: a repro exported earlier, a mesh file, scene
+    /// description or embedded scene, a [`ProcScene`], or the default triangle and quad,
+    /// optionally welded. Meshes without normals get flat ones, see [`Mesh::ensure_normals()`].
+    pub fn from_opts(opts: &SceneOpts) -> Result<Self> {
+        if let Some(dir) = &opts.load_repro {
+            return repro::load_repro(dir);
+        }
+
+        let mesh = match (&opts.scene, &opts.embedded_scene, opts.proc_scene) {
+            (Some(path), _, _) if is_scene_json(path) => None,
+            (Some(path), _, _) => Some(fit_to_view(load_mesh_file(path)?)),
+            (None, Some(embedded), _) => Some(fit_to_view(gltf_scene::load_gltf_bytes(
+                &embedded.name,
+                &embedded.bytes,
+                None,
+            )?)),
+            (None, None, Some(proc_scene)) => {
+                let subdivisions = opts.subdivisions.unwrap_or(DEFAULT_SUBDIVISIONS);
+                let mesh = proc_scene.mesh(subdivisions);
+                log::info!(
+                    "Generated {proc_scene:?} with {subdivisions} subdivisions: {} triangles",
+                    mesh.triangle_count()
+                );
+                Some(mesh)
+            }
+            (None, None, None) => None,
+        };
+        let (mut meshes, default_instances) = match (mesh, &opts.scene) {
+            (Some(mesh), _) => (vec![mesh], default_instances()),
+            (None, Some(path)) if is_scene_json(path) => {
+                let scene = scene_json::load_scene_json(path)?;
+                (scene.meshes, scene.instances)
+            }
+            (None, _) => default_scene(),
+        };
+
+        if let Some(epsilon) = opts.weld {
+            for mesh in &mut meshes {
+                let welded = mesh.weld(epsilon);
+                log::info!(
+                    "Welded {} vertices into {} (epsilon {epsilon})",
+                    mesh.positions.len(),
+                    welded.positions.len()
+                );
+                *mesh = welded;
+            }
+        }
+
+        for mesh in &mut meshes {
+            if mesh.ensure_normals() {
+                log::info!(
+                    "Computed flat normals for a mesh with {} triangles",
+                    mesh.triangle_count()
+                );
+            }
+        }
+
+        let mut instances = match opts.instance_grid {
+            Some(n) => {
+                ensure!(n > 0, "--instance-grid needs at least one instance per row");
+                let mesh_bounds = meshes
+                    .iter()
+                    .fold(Aabb::EMPTY, |bounds, mesh| bounds.union(&mesh.bounds()));
+                grid_instances(n, &mesh_bounds, meshes.len())
+            }
+            None => default_instances,
+        };
+
+        if let Some(factor) = opts.scene_scale {
+            ensure!(factor > 0.0, "--scene-scale must be positive");
+            let center = instance_bounds(&meshes, &instances).center();
+            let scale = math::around(center, &math::scale([factor; 3]));
+            for instance in &mut instances {
+                instance.transform = math::mul(&scale, &instance.transform);
+            }
+        }
+
+        Ok(Self { meshes, instances })
+    }
+}
+
+/// World-space bounds of every instance of `meshes`
+pub fn instance_bounds(meshes: &[Mesh], instances: &[InstanceDescription]) -> Aabb {
+    let mesh_bounds = meshes.iter().map(Mesh::bounds).collect::<Vec<_>>();
+    transformed_bounds(&mesh_bounds, instances)
+}
+
+/// [`instance_bounds()`] from the bounds of every mesh
+fn transformed_bounds(mesh_bounds: &[Aabb], instances: &[InstanceDescription]) -> Aabb {
+    instances.iter().fold(Aabb::EMPTY, |bounds, instance| {
+        bounds.union(&mesh_bounds[instance.mesh].transformed(&instance.transform))
+    })
+}
+
+/// Mirrors `InstanceMesh` in `constants.hlsl`: where the indices and vertices of an instance's
+/// mesh start in the shared buffers
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct InstanceMesh {
+    first_index: u32,
+    index_count: u32,
+    base_vertex: u32,
+    /// [`InstanceDescription::hit_group_offset`], for the raster passes of
+    /// [`DrawMode::Deferred`](crate::raytracer::DrawMode::Deferred)
+    hit_group_offset: u32,
+}
+
+impl InstanceMesh {
+    /// The entry of `instance` in the instance mesh buffer, `ranges` is indexed by mesh
+    fn of_instance(ranges: &[Self], instance: &InstanceDescription) -> Self {
+        Self {
+            hit_group_offset: instance.hit_group_offset,
+            ..ranges[instance.mesh]
+        }
+    }
+}
+
+/// Interactive change to the instances of a [`TriangleScene`], see
+/// [`TriangleScene::edit_instances()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstanceEdit {
+    /// Appends an instance next to the last one, cycling through the meshes
+    Add,
+    /// Drops the last instance
+    Remove,
+    /// Changes the hit group offset of one instance
+    SetMaterial { instance: usize, material: Material },
+}
+
+/// The GPU side of a [`SceneDescription`]: a BLAS per mesh, instanced once per
+/// [`InstanceDescription`]
+pub struct TriangleScene {
+    pub raytracing: RaytracingScene,
+    /// World-space bounds of all instances, kept as they were when the last one is removed
+    pub bounds: Aabb,
+    /// Number of instances in the TLAS, at most [`Self::instance_limit`]
+    pub instance_count: usize,
+    /// Number of instances the scene asked for, before clamping, or after the last
+    /// [`Self::edit_instances()`]
+    pub requested_instance_count: usize,
+    /// The lower of `--max-instances` and the device's TLAS instance limit
+    pub instance_limit: usize,
+    /// Triangles in all meshes together, counting every mesh once
+    pub triangle_count: usize,
+    /// Triangles in the largest mesh, which every instance's draw covers when rasterizing
+    pub max_mesh_triangle_count: usize,
+    /// Transform of every instance, for rasterizing the scene. Bound in place of
+    /// [`Self::spin_transforms`] until the scene spins.
+    ///
+    /// This and [`Self::instance_meshes`] hold a single placeholder when there are no instances,
+    /// so that there is still a buffer to bind.
+    instance_transforms: Buffer,
+    /// [`Self::instance_transforms`] rotated by the last [`Self::spin()`], rewritten every frame.
+    /// Created by the first spin after the scene was built or edited, never while it is empty.
+    spin_transforms: Option<FrameRing<Transform>>,
+    /// [`InstanceMesh`] of every instance, for rasterizing and shading the scene
+    instance_meshes: Buffer,
+    /// Normal of every vertex, laid out like the positions. Only read when shading hits, the
+    /// acceleration structures are built from the positions alone.
+    normal_buffer: Buffer,
+    /// Sizes of the uploaded buffers in bytes, see [`Self::memory_stats()`]
+    position_bytes: usize,
+    normal_bytes: usize,
+    index_bytes: usize,
+    instance_transform_bytes: usize,
+    /// Instances as they were built or edited, before [`Self::spin()`]
+    instances: Vec<InstanceDescription>,
+    /// Both indexed by [`InstanceDescription::mesh`]
+    mesh_ranges: Vec<InstanceMesh>,
+    mesh_bounds: Vec<Aabb>,
+    fence_timeout: FenceTimeout,
+    /// Replaced by [`Self::edit_instances()`] while the frames in flight may still read them
+    retired_buffers: Retired<Buffer>,
+    retired_spin_transforms: Retired<FrameRing<Transform>>,
+}
+
+impl TriangleScene {
+    /// Uploads the meshes and builds their acceleration structures, waiting for the GPU to
+    /// finish
+    pub fn new(
+        device: &dyn Device,
+        opts: &SceneOpts,
+        fence_timeout: FenceTimeout,
+        description: &SceneDescription,
+    ) -> Result<Self> {
+        let SceneDescription { meshes, instances } = description;
+        ensure!(!meshes.is_empty(), "Scene needs at least one mesh");
+        ensure!(!instances.is_empty(), "Scene needs at least one instance");
+
+        let device_limit = device.capabilities().max_tlas_instance_count as usize;
+        let instance_limit = opts
+            .max_instances
+            .map_or(device_limit, |max| max.min(device_limit))
+            .max(1);
+        let requested_instance_count = instances.len();
+        if requested_instance_count > instance_limit {
+            log::warn!(
+                "Scene has {requested_instance_count} instances but the limit is {instance_limit} \
+                 ({}), dropping the remaining {}",
+                if instance_limit == device_limit {
+                    "device maximum"
+                } else {
+                    "--max-instances"
+                },
+                requested_instance_count - instance_limit
+            );
+        }
+        let instances = &instances[..requested_instance_count.min(instance_limit)];
+
+        // All meshes share one position, normal and index buffer, every BLAS geometry and raster
+        // draw reads its own range of them. Indices stay relative to their mesh.
+        let mut ranges = Vec::with_capacity(meshes.len());
+        let (mut positions, mut normals, mut indices) = (vec![], vec![], vec![]);
+        for mesh in meshes {
+            ensure!(
+                mesh.normals.len() == mesh.positions.len(),
+                "Mesh has {} normals for {} vertices",
+                mesh.normals.len(),
+                mesh.positions.len()
+            );
+            ranges.push(InstanceMesh {
+                first_index: indices.len() as u32,
+                index_count: mesh.indices.len() as u32,
+                base_vertex: positions.len() as u32,
+                hit_group_offset: 0,
+            });
+            positions.extend_from_slice(&mesh.positions);
+            normals.extend_from_slice(&mesh.normals);
+            indices.extend_from_slice(&mesh.indices);
+        }
+
+        let mut uploads = UploadBatcher::new(
+            device,
+            opts.upload.buffer_memory,
+            opts.upload.max_batch_bytes(),
+            fence_timeout,
+        );
+        let position_buffer = uploads.upload(
+            "inline position buffer",
+            &BufferCreateDesc::gpu_only_storage(),
+            &positions,
+        )?;
+        let normal_buffer = uploads.upload(
+            "inline normal buffer",
+            &BufferCreateDesc::gpu_only_storage(),
+            &normals,
+        )?;
+        let index_buffer = uploads.upload(
+            "inline index buffer",
+            &BufferCreateDesc::gpu_only_storage(),
+            &indices,
+        )?;
+        let instance_transforms = uploads.upload(
+            "instance transform buffer",
+            &BufferCreateDesc::gpu_only_storage(),
+            &instances
+                .iter()
+                .map(|instance| instance.transform)
+                .collect::<Vec<_>>(),
+        )?;
+        let instance_meshes = uploads.upload(
+            "instance mesh buffer",
+            &BufferCreateDesc::gpu_only_storage(),
+            &instances
+                .iter()
+                .map(|instance| InstanceMesh::of_instance(&ranges, instance))
+                .collect::<Vec<_>>(),
+        )?;
+        let uploads = uploads.finish()?;
+
+        let vertex_format = VertexFormat::R32g32b32Sfloat;
+        let geometry_flags = GeometryFlag::to_geometry_flags(&opts.geometry_flags);
+        let geometry = SceneGeometry {
+            position_buffer,
+            index_buffer,
+            meshes: meshes
+                .iter()
+                .zip(&ranges)
+                .map(|(mesh, range)| {
+                    vec![TriangleGeometryCreateDesc {
+                        vertex_format,
+                        vertex_offset_in_bytes: range.base_vertex as usize
+                            * vertex_format.size_in_bytes(),
+                        vertex_count: mesh.positions.len(),
+                        vertex_stride_in_bytes: vertex_format.size_in_bytes(),
+                        index_format: Some(IndexBufferFormat::Uint32),
+                        index_offset_in_bytes: range.first_index as usize
+                            * std::mem::size_of::<u32>(),
+                        index_count: mesh.indices.len(),
+                        transform_offset_in_bytes: 0,
+                        geometry_flags,
+                        build_location: AccelerationStructureBuildLocation::Device,
+                    }]
+                })
+                .collect(),
+        };
+
+        let raytracing = RaytracingScene::build(
+            device,
+            geometry,
+            uploads,
+            instances,
+            opts.separate_as_submissions,
+            opts.compact_as,
+            fence_timeout,
+        )?;
+
+        Ok(Self {
+            raytracing,
+            bounds: instance_bounds(meshes, instances),
+            instance_count: instances.len(),
+            requested_instance_count,
+            instance_limit,
+            triangle_count: meshes.iter().map(Mesh::triangle_count).sum(),
+            max_mesh_triangle_count: meshes.iter().map(Mesh::triangle_count).max().unwrap_or(0),
+            instance_transforms,
+            spin_transforms: None,
+            instance_meshes,
+            normal_buffer,
+            position_bytes: std::mem::size_of_val(positions.as_slice()),
+            normal_bytes: std::mem::size_of_val(normals.as_slice()),
+            index_bytes: std::mem::size_of_val(indices.as_slice()),
+            instance_transform_bytes: instances.len() * std::mem::size_of::<Transform>(),
+            instances: instances.to_vec(),
+            mesh_ranges: ranges,
+            mesh_bounds: meshes.iter().map(Mesh::bounds).collect(),
+            fence_timeout,
+            retired_buffers: Retired::default(),
+            retired_spin_transforms: Retired::default(),
+        })
+    }
+
+    /// Applies `edit` and records the rebuild of the TLAS into `cmd`, see
+    /// [`RaytracingScene::rebuild_tlas()`]. Removing the last instance leaves an empty TLAS.
+    ///
+    /// The per-instance raster and shading buffers are replaced by host-visible ones holding the
+    /// new instances. The old ones, like the old TLAS, are kept alive until the frames in flight
+    /// are done with them, see [`Self::end_frame()`]. Changing a material replaces the instance
+    /// mesh buffer and updates the TLAS through [`RaytracingScene::update_instances()`], the rest
+    /// stays. That is a rebuild while the TLAS built at load time is still in use, which isn't
+    /// updatable, and a refit once an earlier edit or [`Self::spin()`] has replaced it.
+    ///
+    /// An edit that fails changes nothing, the scene keeps its instances, buffers and TLAS.
+    pub fn edit_instances(
+        &mut self,
+        device: &dyn Device,
+        cmd: &mut CommandBuffer,
+        edit: InstanceEdit,
+    ) -> Result<()> {
+        let mut instances = self.instances.clone();
+        match edit {
+            InstanceEdit::Add => {
+                ensure!(
+                    instances.len() < self.instance_limit,
+                    "Scene already has the maximum of {} instances",
+                    self.instance_limit
+                );
+                let spacing = self
+                    .mesh_bounds
+                    .iter()
+                    .map(|bounds| bounds.max[0] - bounds.min[0])
+                    .fold(0.0f32, f32::max)
+                    * GRID_SPACING;
+                let transform = instances.last().map_or(math::IDENTITY, |last| {
+                    math::mul(&math::translation([spacing, 0.0, 0.0]), &last.transform)
+                });
+                instances.push(InstanceDescription {
+                    transform,
+                    mesh: instances.len() % self.mesh_bounds.len(),
+                    hit_group_offset: HIT_GROUP_INSTANCE_COLOR,
+                });
+            }
+            InstanceEdit::Remove => {
+                instances.pop();
+            }
+            InstanceEdit::SetMaterial { instance, material } => {
+                let count = instances.len();
+                instances
+                    .get_mut(instance)
+                    .with_context(|| format!("No instance {instance}, the scene has {count}"))?
+                    .hit_group_offset = material as u32;
+
+                // Only the TLAS instances change, so it can be refit. That rejects bad instances
+                // before recording anything, and nothing after it can fail, so the old instance
+                // mesh buffer is only retired once the edit is sure to go through.
+                self.raytracing.update_instances(device, &instances, cmd)?;
+
+                // The deferred passes read the material from the instance meshes
+                let ranges = instances
+                    .iter()
+                    .map(|instance| InstanceMesh::of_instance(&self.mesh_ranges, instance))
+                    .collect::<Vec<_>>();
+                log_allocation(
+                    ResourceKind::Buffer,
+                    "instance mesh buffer",
+                    std::mem::size_of_val(ranges.as_slice()),
+                    MemoryKind::HostVisible,
+                );
+                let meshes = create_buffer_with_data(
+                    device,
+                    "instance mesh buffer",
+                    &BufferCreateDesc::cpu_to_gpu(),
+                    &ranges,
+                );
+                let replaced = std::mem::replace(&mut self.instance_meshes, meshes);
+                self.retired_buffers.retire(replaced);
+                self.instances = instances;
+                return Ok(());
+            }
+        }
+
+        // Fails before anything is replaced, leaving the old TLAS and buffers in use
+        self.raytracing.set_instances(&instances)?;
+
+        let mut transforms = instances
+            .iter()
+            .map(|instance| instance.transform)
+            .collect::<Vec<_>>();
+        let mut ranges = instances
+            .iter()
+            .map(|instance| InstanceMesh::of_instance(&self.mesh_ranges, instance))
+            .collect::<Vec<_>>();
+        if instances.is_empty() {
+            transforms.push(math::IDENTITY);
+            ranges.push(InstanceMesh::default());
+        }
+        for (name, size) in [
+            (
+                "instance transform buffer",
+                std::mem::size_of_val(transforms.as_slice()),
+            ),
+            (
+                "instance mesh buffer",
+                std::mem::size_of_val(ranges.as_slice()),
+            ),
+        ] {
+            log_allocation(ResourceKind::Buffer, name, size, MemoryKind::HostVisible);
+        }
+        let transform_bytes = std::mem::size_of_val(transforms.as_slice());
+        let transforms = create_buffer_with_data(
+            device,
+            "instance transform buffer",
+            &BufferCreateDesc::cpu_to_gpu(),
+            &transforms,
+        );
+        let meshes = create_buffer_with_data(
+            device,
+            "instance mesh buffer",
+            &BufferCreateDesc::cpu_to_gpu(),
+            &ranges,
+        );
+        for replaced in [
+            std::mem::replace(&mut self.instance_transforms, transforms),
+            std::mem::replace(&mut self.instance_meshes, meshes),
+        ] {
+            self.retired_buffers.retire(replaced);
+        }
+        // Sized for the old instances, the next spin creates it anew
+        if let Some(replaced) = self.spin_transforms.take() {
+            self.retired_spin_transforms.retire(replaced);
+        }
+
+        self.raytracing.rebuild_tlas(device, cmd);
+
+        self.instance_transform_bytes = transform_bytes;
+        self.instance_count = instances.len();
+        self.requested_instance_count = self.instance_count;
+        if !instances.is_empty() {
+            self.bounds = transformed_bounds(&self.mesh_bounds, &instances);
+        }
+        self.instances = instances;
+        Ok(())
+    }
+
+    /// Picks the [`Material`] of the instance at index `selected`, returning the edit to apply
+    /// when a different one was chosen
+    pub fn material_ui(&self, ui: &mut egui::Ui, selected: &mut usize) -> Option<InstanceEdit> {
+        let last = self.instances.len().checked_sub(1)?;
+        let mut edit = None;
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(selected)
+                    .clamp_range(0..=last)
+                    .prefix("Instance "),
+            );
+            *selected = (*selected).min(last);
+            let hit_group_offset = self.instances[*selected].hit_group_offset;
+            let current = Material::from_hit_group_offset(hit_group_offset);
+            egui::ComboBox::from_label("Material")
+                .selected_text(current.map_or_else(
+                    || format!("Hit group {hit_group_offset}"),
+                    |material| material.name().to_string(),
+                ))
+                .show_ui(ui, |ui| {
+                    for material in Material::ALL {
+                        let is_current = current == Some(material);
+                        if ui.selectable_label(is_current, material.name()).clicked() && !is_current
+                        {
+                            edit = Some(InstanceEdit::SetMaterial {
+                                instance: *selected,
+                                material,
+                            });
+                        }
+                    }
+                });
+        });
+        edit
+    }
+
+    /// Device memory held by the scene right now, the TLAS grows once [`Self::spin()`] makes it
+    /// updatable
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            position_buffer: self.position_bytes,
+            normal_buffer: self.normal_bytes,
+            index_buffer: self.index_bytes,
+            instance_transforms: self.instance_transform_bytes,
+            blas: self.raytracing.blas_size(),
+            tlas: self.raytracing.tlas_size(),
+            scratch: self.raytracing.scratch_size(),
+        }
+    }
+
+    /// Index, normal and instance mesh buffers, in the order the main pass binds them after the
+    /// instance colors for interpolating the normals of hits
+    pub fn shading_buffers(&self) -> [&Buffer; 3] {
+        [
+            &self.raytracing.geometry().index_buffer,
+            &self.normal_buffer,
+            &self.instance_meshes,
+        ]
+    }
+
+    /// Position and instance transform buffers, which `geometry.vs.hlsl` binds after
+    /// [`Self::shading_buffers()`]
+    pub fn raster_buffers(&self) -> [&Buffer; 2] {
+        [
+            &self.raytracing.geometry().position_buffer,
+            self.spin_transforms
+                .as_ref()
+                .map_or(&self.instance_transforms, FrameRing::current),
+        ]
+    }
+
+    /// Records a TLAS update into `cmd` that rotates every instance by `angle` radians around the
+    /// Z axis through the center of its mesh, and writes the rotated transforms for rasterizing.
+    /// The rotation stays within the bounding sphere, so [`Self::bounds`] is not updated.
+    pub fn spin(&mut self, device: &dyn Device, cmd: &mut CommandBuffer, angle: f32) -> Result<()> {
+        let instances = self
+            .instances
+            .iter()
+            .map(|instance| {
+                let rotation = math::around(
+                    self.mesh_bounds[instance.mesh].center(),
+                    &math::rotation_z(angle),
+                );
+                InstanceDescription {
+                    transform: math::mul(&instance.transform, &rotation),
+                    mesh: instance.mesh,
+                    hit_group_offset: instance.hit_group_offset,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !instances.is_empty() {
+            let transforms = instances
+                .iter()
+                .map(|instance| instance.transform)
+                .collect::<Vec<_>>();
+            let fence_timeout = self.fence_timeout;
+            self.spin_transforms
+                .get_or_insert_with(|| {
+                    FrameRing::new(
+                        device,
+                        "spin transform buffer",
+                        transforms.len(),
+                        fence_timeout,
+                    )
+                })
+                .write(&transforms)?;
+        }
+        self.raytracing.update_instances(device, &instances, cmd)
+    }
+
+    /// Hands over the fence of the frame's submission and drops the replaced buffers and
+    /// acceleration structures that only finished frames used, call once per frame
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.raytracing.end_frame(&fence);
+        self.retired_buffers.end_frame(&fence);
+        self.retired_spin_transforms.end_frame(&fence);
+        if let Some(spin_transforms) = &mut self.spin_transforms {
+            spin_transforms.advance(fence);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_os = "android")))]
+mod tests {
+    use breda::renderer::QueueSubmitInfo;
+    use clap::Parser;
+
+    use super::*;
+    use crate::CommandlineOpts;
+
+    /// Applies `edit` like the render loop does and waits for whatever it recorded
+    fn edit_and_wait(
+        device: &dyn Device,
+        scene: &mut TriangleScene,
+        edit: InstanceEdit,
+    ) -> Result<()> {
+        let queue = device.get_gfx_queue();
+        let mut cmd = queue.lock().create_command_buffer();
+        let result = scene.edit_instances(device, &mut cmd, edit);
+        let fence = queue.lock().submit(vec![cmd], QueueSubmitInfo::no_sync());
+        scene.end_frame(fence.clone());
+        scene
+            .fence_timeout
+            .wait(&fence, "applying an instance edit")?;
+        result
+    }
+
+    /// Run with `cargo test -- --ignored` on a machine with such a GPU
+    #[test]
+    #[ignore = "needs a GPU with inline raytracing"]
+    fn rejected_and_emptying_edits_leave_the_scene_usable() -> Result<()> {
+        let opts = CommandlineOpts::parse_from(["scene_test", "--max-instances", "2"]);
+        let breda = breda::Breda::new("scene_test", opts.breda.into())?;
+        let device = breda
+            .devices()
+            .find(|d| d.capabilities().supports_inline_ray_tracing)
+            .cloned()
+            .expect("No device supports inline raytracing");
+        let device = device.as_ref();
+        let mut scene = TriangleScene::new(
+            device,
+            &opts.scene,
+            opts.fence_timeout.timeout(),
+            &SceneDescription::from_opts(&opts.scene)?,
+        )?;
+
+        while scene.instance_count < scene.instance_limit {
+            edit_and_wait(device, &mut scene, InstanceEdit::Add)?;
+        }
+        let bounds = scene.bounds;
+        assert!(edit_and_wait(device, &mut scene, InstanceEdit::Add).is_err());
+        assert_eq!(scene.instance_count, scene.instance_limit);
+        assert_eq!(scene.instances.len(), scene.instance_limit);
+        assert_eq!(scene.bounds, bounds);
+
+        while scene.instance_count > 0 {
+            edit_and_wait(device, &mut scene, InstanceEdit::Remove)?;
+        }
+        // An empty scene still has a TLAS to trace, and removing from it changes nothing
+        edit_and_wait(device, &mut scene, InstanceEdit::Remove)?;
+        assert_eq!(scene.instance_count, 0);
+        let material = InstanceEdit::SetMaterial {
+            instance: 0,
+            material: Material::Orange,
+        };
+        assert!(edit_and_wait(device, &mut scene, material).is_err());
+
+        // Edits that follow the rejected ones still apply
+        edit_and_wait(device, &mut scene, InstanceEdit::Add)?;
+        assert_eq!(scene.instance_count, 1);
+        Ok(())
+    }
+}
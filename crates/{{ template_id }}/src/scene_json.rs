@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{ensure, Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    math::{self, Transform},
+    scene::{self, InstanceDescription, SceneDescription},
+};
+
+/// Top level of a `--scene` `.json` file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    meshes: Vec<SceneFileMesh>,
+}
+
+/// A mesh file placed in the scene, in the units of the file. Listing the same file more than once
+/// instances it, sharing a single BLAS.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFileMesh {
+    /// `.obj`, `.gltf` or `.glb`, relative to the JSON file
+    path: PathBuf,
+    #[serde(default)]
+    position: [f32; 3],
+    /// Euler angles in degrees, applied around X, then Y, then Z
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default = "unit_scale")]
+    scale: [f32; 3],
+}
+
+fn unit_scale() -> [f32; 3] {
+    [1.0; 3]
+}
+
+impl SceneFileMesh {
+    /// Scales, then rotates, then translates
+    fn transform(&self) -> Transform {
+        let [x, y, z] = self.rotation.map(f32::to_radians);
+        let rotation = math::mul(
+            &math::rotation_z(z),
+            &math::mul(&math::rotation_y(y), &math::rotation_x(x)),
+        );
+        math::compose(
+            &math::translation(self.position),
+            &rotation,
+            &math::scale(self.scale),
+        )
+    }
+}
+
+/// Loads a scene of meshes placed with their own transforms, like
+///
+/// ```json
+/// {
+///     "meshes": [
+///         { "path": "floor.obj", "scale": [10, 1, 10] },
+///         { "path": "bunny.glb", "position": [0, 1, 0], "rotation": [0, 90, 0] }
+///     ]
+/// }
+/// ```
+///
+/// `path` is required, the transform fields default to the identity. Unknown fields are rejected
+/// so that typos don't go unnoticed. The whole arrangement is then fit into view like a single
+/// `--scene` mesh is, keeping the meshes' relative placement and size.
+pub fn load_scene_json(path: &Path) -> Result<SceneDescription> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read `{}`", path.display()))?;
+    let file = serde_json::from_str::<SceneFile>(&json)
+        .with_context(|| format!("Invalid scene description in `{}`", path.display()))?;
+    ensure!(
+        !file.meshes.is_empty(),
+        "Scene description `{}` lists no meshes",
+        path.display()
+    );
+
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut meshes = vec![];
+    let mut mesh_indices = HashMap::new();
+    let mut instances = vec![];
+    for (index, entry) in file.meshes.iter().enumerate() {
+        ensure!(
+            entry.scale.iter().all(|&s| s != 0.0),
+            "Mesh {index} (`{}`) in `{}` has a zero scale",
+            entry.path.display(),
+            path.display()
+        );
+        let mesh_path = dir.join(&entry.path);
+        let mesh = match mesh_indices.get(&mesh_path) {
+            Some(&mesh) => mesh,
+            None => {
+                meshes.push(scene::load_mesh_file(&mesh_path).with_context(|| {
+                    format!("Failed to load mesh {index} of `{}`", path.display())
+                })?);
+                mesh_indices.insert(mesh_path, meshes.len() - 1);
+                meshes.len() - 1
+            }
+        };
+        instances.push(InstanceDescription {
+            transform: entry.transform(),
+            mesh,
+            hit_group_offset: 0,
+        });
+    }
+    log::info!(
+        "Loaded {} instances of {} meshes from `{}`",
+        instances.len(),
+        meshes.len(),
+        path.display()
+    );
+
+    let fit = scene::fit_to_view_transform(&scene::instance_bounds(&meshes, &instances));
+    for instance in &mut instances {
+        instance.transform = math::mul(&fit, &instance.transform);
+    }
+
+    Ok(SceneDescription { meshes, instances })
+}
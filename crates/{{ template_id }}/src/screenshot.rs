@@ -0,0 +1,100 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use breda::renderer::{CommandBuffer, Device, Fence, Texture};
+use image::RgbaImage;
+
+use crate::{
+    color_mode::{encode_framebuffer, ColorMode},
+    fence_timeout::FenceTimeout,
+    readback::{ReadbackFormat, TextureReadback},
+};
+
+/// Saves the contents of an image, typically the swapchain, to a timestamped 8-bit sRGB PNG in the
+/// working directory
+pub struct Screenshot {
+    /// File name prefix derived from the app name
+    prefix: String,
+    /// Of the swapchain, decides how its contents are converted to sRGB
+    color_mode: ColorMode,
+    fence_timeout: FenceTimeout,
+    requested: bool,
+    /// Copy recorded by [`Self::capture()`] that [`Self::end_frame()`] waits for
+    pending: Option<TextureReadback>,
+}
+
+impl Screenshot {
+    /// `app_name` is turned into a file name prefix, with anything but letters and digits replaced
+    /// by underscores
+    pub fn new(app_name: &str, color_mode: ColorMode, fence_timeout: FenceTimeout) -> Self {
+        let prefix = app_name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        Self {
+            prefix,
+            color_mode,
+            fence_timeout,
+            requested: false,
+            pending: None,
+        }
+    }
+
+    /// Takes a screenshot at the next [`Self::capture()`]
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    /// Records a copy of `image` into `cmd` if a screenshot was requested. Call after everything
+    /// that should end up in the screenshot is recorded.
+    pub fn capture(
+        &mut self,
+        device: &dyn Device,
+        cmd: &mut CommandBuffer,
+        image: &Texture,
+    ) -> Result<()> {
+        if !std::mem::take(&mut self.requested) {
+            return Ok(());
+        }
+
+        let format = ReadbackFormat::from_texture_format(image.format())
+            .with_context(|| format!("Cannot take screenshots of {:?} images", image.format()))?;
+        let readback = TextureReadback::new(device, "screenshot readback", image.size(), format);
+        readback.record_copy(cmd, image)?;
+        self.pending = Some(readback);
+        Ok(())
+    }
+
+    /// Waits for the submission containing the copy from [`Self::capture()`], if any, and writes
+    /// the PNG
+    pub fn end_frame(&mut self, fence: &Fence) -> Result<()> {
+        let Some(readback) = self.pending.take() else {
+            return Ok(());
+        };
+        self.fence_timeout.wait(fence, "copying a screenshot")?;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = PathBuf::from(format!("{}_{timestamp}.png", self.prefix));
+        let [width, height] = readback.size();
+        let bytes =
+            encode_framebuffer(&readback.packed_bytes(), readback.format(), self.color_mode)?;
+        RgbaImage::from_raw(width, height, bytes)
+            .context("Readback size mismatch")?
+            .save(&path)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+        log::info!("Saved screenshot to `{}`", path.display());
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use breda::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::{Camera, CameraInputSettings},
+    camera_bookmarks::CameraBookmarks,
+    raytracer::{RaytracerOpts, RaytracerSettings, RtDebugMode},
+    ui_scale::{UiScale, DEFAULT_UI_SCALE},
+};
+
+/// Which of the UI's windows are shown, toggled in the "Windows" window or by closing them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenWindows {
+    pub current_gpu: bool,
+    pub raytracer: bool,
+    pub camera: bool,
+    pub accumulation: bool,
+    pub viewport_preview: bool,
+    pub latency: bool,
+    pub dropped_frames: bool,
+    pub memory: bool,
+    pub frame_time: bool,
+    pub shader_clock: bool,
+    pub pixel_probe: bool,
+    pub replay: bool,
+}
+
+impl Default for OpenWindows {
+    fn default() -> Self {
+        Self {
+            current_gpu: true,
+            raytracer: true,
+            camera: true,
+            accumulation: true,
+            viewport_preview: true,
+            latency: true,
+            dropped_frames: true,
+            memory: true,
+            frame_time: true,
+            shader_clock: true,
+            pixel_probe: true,
+            replay: true,
+        }
+    }
+}
+
+impl OpenWindows {
+    /// Windows that only exist with their command line flag are listed regardless
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        for (name, open) in [
+            ("Current GPU", &mut self.current_gpu),
+            ("Raytracer", &mut self.raytracer),
+            ("Camera", &mut self.camera),
+            ("Accumulation", &mut self.accumulation),
+            ("Viewport preview", &mut self.viewport_preview),
+            ("Latency", &mut self.latency),
+            ("Dropped frames", &mut self.dropped_frames),
+            ("Memory", &mut self.memory),
+            ("Frame time", &mut self.frame_time),
+            ("Shader clock", &mut self.shader_clock),
+            ("Pixel probe", &mut self.pixel_probe),
+            ("Replay", &mut self.replay),
+        ] {
+            ui.checkbox(open, name);
+        }
+    }
+}
+
+/// UI state that is restored on the next start, loaded by [`Settings::load()`] and written on a
+/// clean exit by [`Settings::save()`].
+///
+/// Stored as JSON in the user's config directory, see [`Settings::path()`]. Fields missing from
+/// the file keep their defaults, so that files written by older versions still load.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub windows: OpenWindows,
+    /// Whether [`Self::clear_color`] replaces the background gradient
+    pub solid_background: bool,
+    pub clear_color: [f32; 4],
+    pub debug_mode: RtDebugMode,
+    /// Of the overlays, see [`UiScale`]
+    pub ui_scale: f32,
+    pub camera_input: CameraInputSettings,
+    pub camera_bookmarks: CameraBookmarks,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let defaults = RaytracerSettings::default();
+        Self {
+            windows: OpenWindows::default(),
+            solid_background: defaults.background.solid,
+            clear_color: defaults.background.clear_color,
+            debug_mode: defaults.debug_mode,
+            ui_scale: DEFAULT_UI_SCALE,
+            camera_input: CameraInputSettings::default(),
+            camera_bookmarks: CameraBookmarks::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// `<config dir>/<crate>/settings.json`, like `~/.config` on Linux, `%APPDATA%` on Windows
+    /// and `~/Library/Application Support` on macOS. [`None`] where the platform has no such
+    /// directory, like Android.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("settings.json"))
+    }
+
+    /// Missing files give the defaults, unreadable or malformed ones too after logging a warning
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else {
+            log::warn!("No config directory on this platform, settings are not restored");
+            return Self::default();
+        };
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("Failed to read settings from `{}`: {e}", path.display());
+                return Self::default();
+            }
+        };
+        match serde_json::from_str(&json) {
+            Ok(settings) => {
+                log::info!("Restored settings from `{}`", path.display());
+                settings
+            }
+            Err(e) => {
+                log::warn!(
+                    "Ignoring malformed settings in `{}`, using the defaults: {e}",
+                    path.display()
+                );
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create `{}`", dir.display()))?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write settings to `{}`", path.display()))
+    }
+
+    /// Captures the persisted parts of the UI state
+    pub fn new(
+        windows: &OpenWindows,
+        raytracer_settings: &RaytracerSettings,
+        ui_scale: &UiScale,
+        camera: &Camera,
+        camera_bookmarks: &CameraBookmarks,
+    ) -> Self {
+        Self {
+            windows: windows.clone(),
+            solid_background: raytracer_settings.background.solid,
+            clear_color: raytracer_settings.background.clear_color,
+            debug_mode: raytracer_settings.debug_mode,
+            ui_scale: ui_scale.scale(),
+            camera_input: camera.input_filter.settings,
+            camera_bookmarks: camera_bookmarks.clone(),
+        }
+    }
+
+    /// Restores the persisted parts of `raytracer_settings`, leaving the clear color alone when
+    /// it was given on the command line
+    pub fn apply(&self, raytracer_settings: &mut RaytracerSettings, opts: &RaytracerOpts) {
+        if opts.clear_color.is_none() {
+            raytracer_settings.background.solid = self.solid_background;
+            raytracer_settings.background.clear_color = self.clear_color;
+        }
+        raytracer_settings.debug_mode = self.debug_mode;
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use breda::renderer::{create_buffer_with_data, Buffer, BufferCreateDesc, Device, Fence};
+use clap::Args;
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::FRAMES_IN_FLIGHT,
+};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ShaderClockOpts {
+    /// Measure per-pixel raytracing cost with the shader clock, if the device supports it
+    #[clap(long)]
+    pub shader_clock: bool,
+}
+
+/// `[min, max, sum_lo, sum_hi, count]`, matching the layout written by `mainShaderClock`
+const STATS_INIT: [u32; 5] = [u32::MAX, 0, 0, 0, 0];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ShaderClockStats {
+    pub min_cycles: u32,
+    pub avg_cycles: f64,
+    pub max_cycles: u32,
+}
+
+struct Slot {
+    buffer: Buffer,
+    fence: Option<Fence>,
+}
+
+/// Aggregates per-pixel shader clock deltas written by the raytracer into min/avg/max cycles.
+///
+/// The shader atomically accumulates into a small host-visible buffer, which is read back a
+/// couple of frames later once its fence has signaled.
+pub struct ShaderClock {
+    slots: Vec<Slot>,
+    current: usize,
+    last_stats: Option<ShaderClockStats>,
+    fence_timeout: FenceTimeout,
+}
+
+impl ShaderClock {
+    /// Returns [`None`] when the device lacks shader clock support, after logging a warning
+    pub fn new(device: &dyn Device, fence_timeout: FenceTimeout) -> Option<Self> {
+        if !device.capabilities().supports_shader_clock {
+            log::warn!(
+                "--shader-clock requested but `{}` does not support reading the shader clock",
+                device.driver_info().device_name
+            );
+            return None;
+        }
+
+        let slots = (0..FRAMES_IN_FLIGHT)
+            .map(|i| {
+                let name = format!("shader clock stats {i}");
+                log_allocation(
+                    ResourceKind::Buffer,
+                    &name,
+                    std::mem::size_of_val(&STATS_INIT),
+                    MemoryKind::Readback,
+                );
+                Slot {
+                    buffer: create_buffer_with_data(
+                        device,
+                        &name,
+                        &BufferCreateDesc::gpu_to_cpu(),
+                        &STATS_INIT,
+                    ),
+                    fence: None,
+                }
+            })
+            .collect();
+
+        Some(Self {
+            slots,
+            current: 0,
+            last_stats: None,
+            fence_timeout,
+        })
+    }
+
+    /// Collects the results of the oldest frame and returns its buffer, reset for reuse
+    pub fn begin_frame(&mut self) -> Result<&Buffer> {
+        let slot = &mut self.slots[self.current];
+
+        if let Some(fence) = slot.fence.take() {
+            self.fence_timeout
+                .wait(&fence, "writing shader clock stats")?;
+
+            let [min, max, sum_lo, sum_hi, count] = slot.buffer.read_data::<[u32; 5]>()[0];
+            if count > 0 {
+                let sum = (u64::from(sum_hi) << 32) | u64::from(sum_lo);
+                self.last_stats = Some(ShaderClockStats {
+                    min_cycles: min,
+                    avg_cycles: sum as f64 / f64::from(count),
+                    max_cycles: max,
+                });
+            }
+        }
+
+        slot.buffer.write_data(&STATS_INIT);
+        Ok(&slot.buffer)
+    }
+
+    /// Hands over the fence of the submission that wrote to the buffer from [`Self::begin_frame()`]
+    pub fn end_frame(&mut self, fence: Fence) {
+        self.slots[self.current].fence = Some(fence);
+        self.current = (self.current + 1) % self.slots.len();
+    }
+
+    pub fn last_stats(&self) -> Option<ShaderClockStats> {
+        self.last_stats
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Error;
+
+/// Shader sources of this crate, only present when running from a checkout
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/shaders");
+/// How often [`ShaderReload::poll_changes()`] walks the shader sources, rather than every frame
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches the shader sources for changes, so the main loop can rebuild the workspace and swap in
+/// the new shader database while it keeps rendering with the last one that built.
+///
+/// Like [`crate::user_uniforms::UserUniformFile`], changes are detected by polling modification
+/// times.
+pub struct ShaderReload {
+    dir: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    /// Set from the moment a rebuild starts until its shader database has been streamed in
+    pending: bool,
+    /// Why the last rebuild failed, cleared once one succeeds
+    error: Option<String>,
+}
+
+impl ShaderReload {
+    /// Returns [`None`] when the shader sources aren't available, like in packaged builds
+    pub fn new() -> Option<Self> {
+        let dir = PathBuf::from(SHADER_DIR);
+        dir.is_dir().then(|| Self {
+            modified: latest_modification(&dir),
+            last_poll: Instant::now(),
+            dir,
+            pending: false,
+            error: None,
+        })
+    }
+
+    /// Returns `true` once for every change to the shader sources, and marks a rebuild as pending.
+    /// Looks at the sources at most every [`POLL_INTERVAL`].
+    pub fn poll_changes(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = latest_modification(&self.dir);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        self.pending = true;
+        true
+    }
+
+    /// Call when the rebuild failed, the previous shaders stay in use
+    pub fn failed(&mut self, error: &Error) {
+        self.pending = false;
+        self.error = Some(format!("{error:#}"));
+    }
+
+    /// Call whenever a shader database is available, finishing a pending rebuild
+    pub fn loaded(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            self.error = None;
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// Newest modification time of any file below `dir`
+fn latest_modification(dir: &Path) -> Option<SystemTime> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            if metadata.is_dir() {
+                latest_modification(&entry.path())
+            } else {
+                metadata.modified().ok()
+            }
+        })
+        .max()
+}
@@ -0,0 +1,105 @@
+use std::{sync::Arc, thread::JoinHandle, time::Duration};
+
+use anyhow::{anyhow, Result};
+use breda::{egui, renderer::Device, streaming_system::StreamingSystem};
+use clap::Args;
+
+use crate::workspace_build_recipe;
+
+pub const DEFAULT_SHADER_DB_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct StartupOpts {
+    /// Start rendering immediately and show a loading screen while the workspace builds and the
+    /// shaders load in the background, instead of blocking before the first frame
+    #[clap(long)]
+    pub async_startup: bool,
+    /// Fail with an error instead of hanging when the shader database hasn't loaded this many
+    /// seconds after the workspace build
+    #[clap(long, value_name = "SECONDS")]
+    pub shader_db_timeout_secs: Option<u64>,
+}
+
+impl StartupOpts {
+    pub fn shader_db_timeout(&self) -> Duration {
+        Duration::from_secs(
+            self.shader_db_timeout_secs
+                .unwrap_or(DEFAULT_SHADER_DB_TIMEOUT_SECS),
+        )
+    }
+}
+
+/// What the loading screen of `--async-startup` shows until the first shader database has been
+/// streamed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadingPhase {
+    BuildingWorkspace,
+    LoadingShaders,
+}
+
+impl LoadingPhase {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::BuildingWorkspace => "Building workspace...",
+            Self::LoadingShaders => "Loading shaders...",
+        }
+    }
+
+    pub fn ui(&self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.spinner();
+            ui.label(self.label());
+        });
+    }
+}
+
+/// Builds the workspace on a background thread so the render loop can keep presenting frames.
+///
+/// Once [`Self::poll()`] returns `true` the shader database can be loaded through the streaming
+/// system like usual; until then the pipelines aren't available yet.
+pub struct AsyncWorkspaceBuild {
+    build: Option<JoinHandle<Result<()>>>,
+}
+
+impl AsyncWorkspaceBuild {
+    pub fn spawn(streaming_system: &Arc<StreamingSystem>, device: &dyn Device) -> Self {
+        let streaming_system = streaming_system.clone();
+        let recipe = workspace_build_recipe(device);
+
+        let build = std::thread::Builder::new()
+            .name("workspace build".to_string())
+            .spawn(move || {
+                let _span = tracing::info_span!("build_workspace").entered();
+                streaming_system.build_workspace(recipe).map_err(Into::into)
+            })
+            .expect("Failed to spawn workspace build thread");
+
+        Self { build: Some(build) }
+    }
+
+    /// A workspace build that has already completed, for when startup is synchronous
+    pub fn finished() -> Self {
+        Self { build: None }
+    }
+
+    /// Whether the build is still going, or finished without [`Self::poll()`] having noticed yet
+    pub fn is_running(&self) -> bool {
+        self.build.is_some()
+    }
+
+    /// Returns `true` once the build has completed, or the error it failed with
+    pub fn poll(&mut self) -> Result<bool> {
+        match &self.build {
+            Some(build) if !build.is_finished() => Ok(false),
+            Some(_) => {
+                let build = self.build.take().unwrap();
+                build
+                    .join()
+                    .map_err(|_| anyhow!("Workspace build thread panicked"))??;
+                log::info!("Workspace build finished, shaders are available");
+                Ok(true)
+            }
+            None => Ok(true),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use anyhow::{Context, Result};
+use breda::{renderer::Device, streaming_system::StreamingSystem};
+use clap::Args;
+
+use crate::{
+    headless::HeadlessRenderer,
+    init_streaming_system,
+    raytracer::RaytracerSettings,
+    scene::{SceneDescription, TriangleScene},
+    CommandlineOpts,
+};
+
+pub const DEFAULT_TURNTABLE_FRAMES: u32 = 120;
+
+/// Height of the orbit, as the Y component of the normalized view direction
+const ELEVATION: f32 = 0.3;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct TurntableOpts {
+    /// Orbit the camera once around the scene center over --frames frames, writing every frame to
+    /// --out, and exit
+    #[clap(long)]
+    pub turntable: bool,
+}
+
+/// View direction of `frame` out of `frames` for a full revolution around the Y axis
+pub fn orbit_direction(frame: u32, frames: u32) -> [f32; 3] {
+    let angle = frame as f32 / frames as f32 * TAU;
+    let horizontal = (1.0 - ELEVATION * ELEVATION).sqrt();
+    // Looking at the scene from a point on the orbit, so the direction points inward
+    [
+        -angle.sin() * horizontal,
+        -ELEVATION,
+        angle.cos() * horizontal,
+    ]
+}
+
+pub fn run_turntable(
+    device: &Arc<dyn Device>,
+    streaming_system: &StreamingSystem,
+    opts: &CommandlineOpts,
+) -> Result<()> {
+    let fence_timeout = opts.fence_timeout.timeout();
+    let frames = opts
+        .headless
+        .frames
+        .unwrap_or(DEFAULT_TURNTABLE_FRAMES)
+        .max(1);
+
+    std::fs::create_dir_all(&opts.headless.out).with_context(|| {
+        format!(
+            "Failed to create output directory `{}`",
+            opts.headless.out.display()
+        )
+    })?;
+
+    init_streaming_system(streaming_system, device, opts.startup.shader_db_timeout())?;
+    let scene = TriangleScene::new(
+        device.as_ref(),
+        &opts.scene,
+        fence_timeout,
+        &SceneDescription::from_opts(&opts.scene)?,
+    )?;
+
+    let mut renderer = HeadlessRenderer::new(
+        device,
+        opts.headless.size(),
+        opts.readback.readback_format,
+        &opts.render_graph_debug,
+        fence_timeout,
+    );
+    let mut settings = RaytracerSettings::new(&opts.raytracer, &mut opts.seed.rng());
+
+    for frame in 0..frames {
+        // The ray framing always covers the scene bounds, so only the direction changes
+        settings.view_direction = Some(orbit_direction(frame, frames));
+
+        let path = opts.headless.frame_path(frame);
+        renderer
+            .render_frame(streaming_system, &scene, &settings, None)?
+            .save(&path)
+            .with_context(|| format!("Failed to write `{}`", path.display()))?;
+    }
+
+    log::info!(
+        "Wrote {frames} turntable frames to `{}`",
+        opts.headless.out.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use breda::egui;
+use clap::Args;
+
+pub const DEFAULT_UI_SCALE: f32 = 1.0;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct UiScaleOpts {
+    /// Scale of all overlay text and widgets, on top of the display's DPI scale. Replaces the scale
+    /// restored from the last run.
+    #[clap(long, value_name = "SCALE")]
+    pub ui_scale: Option<f32>,
+}
+
+/// Runtime-adjustable zoom of the egui overlays.
+///
+/// egui already renders at the display's pixels-per-point, so text stays sharp: this only
+/// multiplies that, to keep text legible from 1080p to 4K regardless of the DPI setting.
+pub struct UiScale {
+    scale: f32,
+}
+
+impl UiScale {
+    /// --ui-scale takes precedence over `restored`, the scale of the last run
+    pub fn new(opts: &UiScaleOpts, restored: f32) -> Self {
+        Self {
+            scale: opts.ui_scale.unwrap_or(restored).clamp(0.5, 4.0),
+        }
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Call every frame before building any windows
+    pub fn apply(&self, ctx: &egui::Context) {
+        if ctx.zoom_factor() != self.scale {
+            ctx.set_zoom_factor(self.scale);
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.add(egui::Slider::new(&mut self.scale, 0.5..=4.0).text("UI scale"));
+            if ui.button("Reset").clicked() {
+                self.scale = DEFAULT_UI_SCALE;
+            }
+        });
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{ensure, Result};
+use breda::renderer::{
+    create_buffer_with_data, Buffer, BufferCreateDesc, CommandBuffer, Device, Fence,
+    MemoryLocation, QueueHandle, QueueSubmitInfo, Semaphore,
+};
+use clap::{Args, ValueEnum};
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+};
+
+/// Default cap on the amount of data recorded into a single upload command buffer
+pub const DEFAULT_MAX_UPLOAD_BATCH_MB: usize = 64;
+
+/// Where buffers with initial data live
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum BufferMemory {
+    /// Fastest for the GPU to access, but not CPU-visible: initial data is copied in through a
+    /// staging buffer
+    #[default]
+    DeviceLocal,
+    /// CPU-mapped memory the data is written to directly, without a staging copy. GPU reads
+    /// are slower, which suits data that is small or rewritten every frame, such as the
+    /// raytracer constants, or inspecting buffer contents while debugging.
+    HostVisible,
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct UploadOpts {
+    /// Maximum size in MiB of the buffer uploads recorded into a single command buffer
+    #[clap(long, value_name = "MB")]
+    pub max_upload_batch_mb: Option<usize>,
+    /// Memory that scene buffers are allocated in
+    #[clap(long, value_enum, default_value_t)]
+    pub buffer_memory: BufferMemory,
+}
+
+impl UploadOpts {
+    pub fn max_batch_bytes(&self) -> usize {
+        self.max_upload_batch_mb
+            .unwrap_or(DEFAULT_MAX_UPLOAD_BATCH_MB)
+            .max(1)
+            * 1024
+            * 1024
+    }
+}
+
+/// The device's dedicated transfer queue if it has one, so that uploads can run concurrently with
+/// graphics work, otherwise the graphics queue
+pub fn upload_queue(device: &dyn Device) -> &QueueHandle {
+    device
+        .get_transfer_queue()
+        .unwrap_or_else(|| device.get_gfx_queue())
+}
+
+/// Returned by [`UploadBatcher::finish()`] once the last batch is submitted. Submissions on the
+/// graphics queue that read the uploaded buffers have to be created with [`Self::submit_info()`].
+pub struct UploadHandoff {
+    /// Signaled by the last batch when it ran on a transfer queue, the graphics queue waits for it
+    /// on the GPU instead of the CPU waiting for the batch
+    semaphore: Option<Semaphore>,
+    fence: Option<Fence>,
+    staging: Vec<Buffer>,
+}
+
+impl UploadHandoff {
+    /// For the first graphics queue submission that reads the uploaded buffers
+    pub fn submit_info(&self) -> QueueSubmitInfo {
+        match &self.semaphore {
+            Some(semaphore) => QueueSubmitInfo::no_sync().with_wait_semaphore(semaphore),
+            None => QueueSubmitInfo::no_sync(),
+        }
+    }
+
+    /// Waits for the last batch and frees its staging buffers. Call after [`Self::submit_info()`]
+    /// was submitted, the batch is usually done by then.
+    pub fn finish(self, fence_timeout: FenceTimeout) -> Result<()> {
+        if let Some(fence) = &self.fence {
+            fence_timeout.wait(fence, "uploading buffers")?;
+        }
+        drop(self.staging);
+        Ok(())
+    }
+}
+
+/// Uploads initial buffer data through staging buffers, splitting the copies over multiple
+/// command buffers so that no single submission exceeds `max_batch_bytes`.
+///
+/// Every time a batch fills up it is submitted and waited on before the next one starts, which
+/// bounds the amount of staging memory alive at any point in time. With
+/// [`BufferMemory::HostVisible`] no staging is involved and data is written in place instead.
+///
+/// Copies go through [`upload_queue()`]. The last batch on a transfer queue isn't waited for on the
+/// CPU, see [`UploadHandoff`].
+pub struct UploadBatcher<'a> {
+    device: &'a dyn Device,
+    memory: BufferMemory,
+    max_batch_bytes: usize,
+    fence_timeout: FenceTimeout,
+    cmd: Option<CommandBuffer>,
+    // Staging buffers have to outlive the submission that copies out of them
+    staging: Vec<Buffer>,
+    batch_bytes: usize,
+    batch_count: usize,
+    total_bytes: usize,
+}
+
+impl<'a> UploadBatcher<'a> {
+    pub fn new(
+        device: &'a dyn Device,
+        memory: BufferMemory,
+        max_batch_bytes: usize,
+        fence_timeout: FenceTimeout,
+    ) -> Self {
+        Self {
+            device,
+            memory,
+            max_batch_bytes,
+            fence_timeout,
+            cmd: None,
+            staging: vec![],
+            batch_bytes: 0,
+            batch_count: 0,
+            total_bytes: 0,
+        }
+    }
+
+    /// Creates a buffer with `desc` and schedules `data` to be copied into it. For
+    /// [`BufferMemory::HostVisible`] the buffer keeps the usage of `desc` but is allocated in
+    /// host-visible memory instead, and the data is written immediately.
+    ///
+    /// The contents are only guaranteed to be on the GPU after [`Self::finish()`] returns.
+    pub fn upload<T: Copy>(
+        &mut self,
+        name: &str,
+        desc: &BufferCreateDesc,
+        data: &[T],
+    ) -> Result<Buffer> {
+        let size = std::mem::size_of_val(data);
+        ensure!(size > 0, "Cannot upload empty buffer `{name}`");
+
+        if self.memory == BufferMemory::HostVisible {
+            log_allocation(ResourceKind::Buffer, name, size, MemoryKind::HostVisible);
+            self.total_bytes += size;
+            let desc = BufferCreateDesc {
+                memory_location: MemoryLocation::CpuToGpu,
+                ..desc.clone()
+            };
+            return Ok(create_buffer_with_data(self.device, name, &desc, data));
+        }
+
+        // A single upload larger than the cap still goes through, just in a batch of its own
+        if self.batch_bytes > 0 && self.batch_bytes + size > self.max_batch_bytes {
+            self.flush()?;
+        }
+
+        let staging = create_buffer_with_data(
+            self.device,
+            &format!("{name} (staging)"),
+            &BufferCreateDesc::cpu_to_gpu(),
+            data,
+        );
+        let buffer = self.device.create_buffer(name, size, desc);
+        log_allocation(ResourceKind::Buffer, name, size, MemoryKind::DeviceLocal);
+
+        let device = self.device;
+        let cmd = self
+            .cmd
+            .get_or_insert_with(|| upload_queue(device).lock().create_command_buffer());
+        cmd.copy_buffer(&staging, 0, &buffer, 0, size);
+
+        self.staging.push(staging);
+        self.batch_bytes += size;
+        self.total_bytes += size;
+
+        Ok(buffer)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let Some(cmd) = self.cmd.take() else {
+            return Ok(());
+        };
+
+        let fence = upload_queue(self.device)
+            .lock()
+            .submit(vec![cmd], QueueSubmitInfo::no_sync());
+        self.fence_timeout.wait(&fence, "uploading buffers")?;
+
+        self.staging.clear();
+        self.batch_bytes = 0;
+        self.batch_count += 1;
+        Ok(())
+    }
+
+    /// Submits the last pending batch. On the graphics queue it is waited for like every other
+    /// batch, on a transfer queue it signals the returned handoff's semaphore instead.
+    pub fn finish(mut self) -> Result<UploadHandoff> {
+        let mut handoff = UploadHandoff {
+            semaphore: None,
+            fence: None,
+            staging: vec![],
+        };
+
+        match self.cmd.take() {
+            Some(cmd) if self.device.get_transfer_queue().is_some() => {
+                let semaphore = self.device.create_semaphore("upload handoff");
+                handoff.fence = Some(upload_queue(self.device).lock().submit(
+                    vec![cmd],
+                    QueueSubmitInfo::no_sync().with_signal_semaphore(&semaphore),
+                ));
+                handoff.semaphore = Some(semaphore);
+                handoff.staging = std::mem::take(&mut self.staging);
+                self.batch_count += 1;
+            }
+            cmd => {
+                self.cmd = cmd;
+                self.flush()?;
+            }
+        }
+
+        log::debug!(
+            "Uploaded {} bytes in {} batch(es) on the {} queue",
+            self.total_bytes,
+            self.batch_count,
+            if self.device.get_transfer_queue().is_some() {
+                "transfer"
+            } else {
+                "graphics"
+            }
+        );
+        Ok(handoff)
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use serde_json::Value;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct UserUniformOpts {
+    /// JSON object with values for the raytracer's `UserUniforms`, reloaded whenever the file
+    /// changes
+    #[clap(long, value_name = "JSON")]
+    pub uniforms: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UniformType {
+    Float,
+    Float2,
+    Float3,
+    Float4,
+    Int,
+    Uint,
+}
+
+impl UniformType {
+    pub fn components(self) -> usize {
+        match self {
+            Self::Float | Self::Int | Self::Uint => 1,
+            Self::Float2 => 2,
+            Self::Float3 => 3,
+            Self::Float4 => 4,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Float => "float",
+            Self::Float2 => "float2",
+            Self::Float3 => "float3",
+            Self::Float4 => "float4",
+            Self::Int => "int",
+            Self::Uint => "uint",
+        }
+    }
+
+    /// Converts a JSON number to the bit pattern of a single component of this type
+    fn component_bits(self, value: &Value) -> Option<u32> {
+        match self {
+            Self::Float | Self::Float2 | Self::Float3 | Self::Float4 => {
+                value.as_f64().map(|v| (v as f32).to_bits())
+            }
+            Self::Int => value
+                .as_i64()
+                .and_then(|v| i32::try_from(v).ok())
+                .map(|v| v as u32),
+            Self::Uint => value.as_u64().and_then(|v| u32::try_from(v).ok()),
+        }
+    }
+}
+
+/// A field of `UserUniforms`, with the bit patterns of its default components
+pub struct UniformField {
+    pub name: &'static str,
+    pub ty: UniformType,
+    pub default: &'static [u32],
+}
+
+const ONE: u32 = 0x3f80_0000;
+
+/// Mirrors `UserUniforms` in `main.ps.hlsl`, fields are tightly packed in this order
+pub const USER_UNIFORM_LAYOUT: &[UniformField] = &[
+    UniformField {
+        name: "tint",
+        ty: UniformType::Float3,
+        default: &[ONE, ONE, ONE],
+    },
+    UniformField {
+        name: "brightness",
+        ty: UniformType::Float,
+        default: &[ONE],
+    },
+];
+
+/// Packed contents of the `UserUniforms` buffer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserUniformValues(pub Vec<u32>);
+
+impl Default for UserUniformValues {
+    fn default() -> Self {
+        Self(
+            USER_UNIFORM_LAYOUT
+                .iter()
+                .flat_map(|field| field.default)
+                .copied()
+                .collect(),
+        )
+    }
+}
+
+impl UserUniformValues {
+    /// Packs a JSON object against [`USER_UNIFORM_LAYOUT`]. Missing fields keep their defaults,
+    /// unknown fields and values that don't match the field's type are errors.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let value = serde_json::from_str::<Value>(json).context("Invalid JSON")?;
+        let Value::Object(object) = value else {
+            bail!("Expected a JSON object of uniform values");
+        };
+
+        if let Some(unknown) = object
+            .keys()
+            .find(|key| !USER_UNIFORM_LAYOUT.iter().any(|field| field.name == *key))
+        {
+            bail!(
+                "Unknown uniform `{unknown}`, expected one of: {}",
+                USER_UNIFORM_LAYOUT
+                    .iter()
+                    .map(|field| field.name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+
+        let mut words = Vec::new();
+        for field in USER_UNIFORM_LAYOUT {
+            let Some(value) = object.get(field.name) else {
+                words.extend_from_slice(field.default);
+                continue;
+            };
+
+            let components = match value {
+                Value::Array(components) => components.as_slice(),
+                scalar => std::slice::from_ref(scalar),
+            };
+            let bits = components
+                .iter()
+                .map(|component| field.ty.component_bits(component))
+                .collect::<Option<Vec<_>>>();
+            match bits {
+                Some(bits) if bits.len() == field.ty.components() => words.extend(bits),
+                _ => bail!(
+                    "Uniform `{}` is declared as {} but got `{value}`",
+                    field.name,
+                    field.ty.name()
+                ),
+            }
+        }
+
+        Ok(Self(words))
+    }
+}
+
+/// How often [`UserUniformFile::poll()`] looks at the file, rather than every frame
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// [`UserUniformValues`] loaded from a file, reloaded whenever its modification time changes
+pub struct UserUniformFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+    /// The last error [`Self::poll()`] logged, so that it isn't logged again until it changes
+    logged_error: Option<String>,
+}
+
+impl UserUniformFile {
+    /// Loads `path`, failing if the initial contents are invalid
+    pub fn load(path: &Path) -> Result<(Self, UserUniformValues)> {
+        let mut file = Self {
+            path: path.to_owned(),
+            modified: None,
+            last_poll: Instant::now(),
+            logged_error: None,
+        };
+        let values = file
+            .reload()?
+            .context("Uniform file has no modification time")?;
+        Ok((file, values))
+    }
+
+    fn modified(&self) -> Result<SystemTime> {
+        Ok(std::fs::metadata(&self.path)?.modified()?)
+    }
+
+    fn reload(&mut self) -> Result<Option<UserUniformValues>> {
+        let modified = self
+            .modified()
+            .with_context(|| format!("Failed to stat `{}`", self.path.display()))?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+        self.modified = Some(modified);
+        // Errors of the new contents are reported even if they read the same
+        self.logged_error = None;
+
+        let json = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read `{}`", self.path.display()))?;
+        UserUniformValues::from_json(&json)
+            .with_context(|| format!("Failed to load uniforms from `{}`", self.path.display()))
+            .map(Some)
+    }
+
+    /// Returns new values if the file changed since the last load, checking at most every
+    /// [`POLL_INTERVAL`]. Errors are logged rather than returned so that a typo while editing
+    /// doesn't end the session, each once until the error or the file changes.
+    pub fn poll(&mut self) -> Option<UserUniformValues> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_poll = Instant::now();
+
+        match self.reload() {
+            Ok(values) => {
+                if values.is_some() {
+                    log::info!("Reloaded uniforms from `{}`", self.path.display());
+                }
+                values
+            }
+            Err(e) => {
+                let error = format!("{e:?}");
+                if self.logged_error.as_ref() != Some(&error) {
+                    log::error!("{error}");
+                    self.logged_error = Some(error);
+                }
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use breda::{
+    renderer::{DebugMessage, DebugMessageSeverity},
+    BredaDesc,
+};
+use clap::Args;
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ValidationOpts {
+    /// Create the device with the graphics API's validation layers enabled and log every message
+    /// they report. This costs performance, and requires the validation layers to be installed,
+    /// for example through the Vulkan SDK.
+    #[clap(long)]
+    pub validation: bool,
+}
+
+impl ValidationOpts {
+    /// Enables validation in `desc` if `--validation` is passed
+    pub fn apply(&self, desc: &mut BredaDesc) {
+        if self.validation {
+            desc.validation = true;
+            desc.debug_message_callback = Some(Arc::new(log_debug_message));
+        }
+    }
+}
+
+/// Errors reported by the validation layers since startup
+static ERROR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Number of errors the validation layers reported so far, always `0` without --validation
+pub fn error_count() -> usize {
+    ERROR_COUNT.load(Ordering::Relaxed)
+}
+
+/// Forwards a message from the validation layers to `log`, at the level matching its severity
+fn log_debug_message(message: &DebugMessage) {
+    let level = match message.severity {
+        DebugMessageSeverity::Error => {
+            ERROR_COUNT.fetch_add(1, Ordering::Relaxed);
+            log::Level::Error
+        }
+        DebugMessageSeverity::Warning => log::Level::Warn,
+        DebugMessageSeverity::Info => log::Level::Info,
+        DebugMessageSeverity::Verbose => log::Level::Trace,
+    };
+    log::log!(target: "validation", level, "{}", message.text);
+}
@@ -0,0 +1,124 @@
+use breda::{
+    egui,
+    render_graph::{RenderGraph, RenderGraphTexture},
+    renderer::{Device, Fence, Format, Texture, TextureCreateDesc},
+};
+use clap::Args;
+
+use crate::{
+    allocations::{log_allocation, MemoryKind, ResourceKind},
+    fence_timeout::FenceTimeout,
+    frame_ring::Retired,
+    pixel_perfect::parse_resolution,
+    raytracer::MainPassBuffers,
+};
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct ViewportPreviewOpts {
+    /// Also render the scene into an offscreen target of this initial size and show it in a
+    /// "Viewport preview" window, resizing the target along with the window
+    #[clap(long, value_name = "WIDTHxHEIGHT", value_parser = parse_resolution)]
+    pub viewport_preview: Option<[u32; 2]>,
+}
+
+/// Offscreen color target registered with the egui renderer
+struct PreviewTarget {
+    texture: Texture,
+    texture_id: egui::TextureId,
+    size: [u32; 2],
+}
+
+impl PreviewTarget {
+    fn new(device: &dyn Device, egui_renderer: &mut breda::egui::Renderer, size: [u32; 2]) -> Self {
+        let texture = device.create_texture(
+            "viewport preview target",
+            &TextureCreateDesc::render_target_2d(size, Format::R8g8b8a8Unorm),
+        );
+        log_allocation(
+            ResourceKind::Texture,
+            "viewport preview target",
+            size[0] as usize * size[1] as usize * 4,
+            MemoryKind::DeviceLocal,
+        );
+        let texture_id = egui_renderer.register_texture(&texture);
+        Self {
+            texture,
+            texture_id,
+            size,
+        }
+    }
+}
+
+/// Renders the scene a second time into a texture that is shown as an image inside an egui
+/// window, as a building block for viewports with gizmos or picture-in-picture views.
+///
+/// The target follows the size of the window's contents. It is recreated when the window is
+/// resized, keeping the replaced targets alive until the frames that may still read them are done.
+pub struct ViewportPreview {
+    target: PreviewTarget,
+    /// Set by [`Self::ui()`], applied by the next [`Self::prepare()`]
+    requested_size: [u32; 2],
+    /// Replaced targets, unregistered from the egui renderer once their frames are done
+    retired: Retired<PreviewTarget>,
+    /// Separate from the main view's, as every ring may only be written once per frame
+    buffers: MainPassBuffers,
+}
+
+impl ViewportPreview {
+    pub fn new(
+        device: &dyn Device,
+        egui_renderer: &mut breda::egui::Renderer,
+        size: [u32; 2],
+        fence_timeout: FenceTimeout,
+    ) -> Self {
+        Self {
+            target: PreviewTarget::new(device, egui_renderer, size),
+            requested_size: size,
+            retired: Retired::default(),
+            buffers: MainPassBuffers::new(device, fence_timeout),
+        }
+    }
+
+    /// Call once per frame before recording into the target. Resizes it to what [`Self::ui()`]
+    /// asked for and returns it imported into `render_graph`, with its size and the buffers to
+    /// pass to [`crate::raytracer::record_main_pass()`].
+    pub fn prepare(
+        &mut self,
+        device: &dyn Device,
+        egui_renderer: &mut breda::egui::Renderer,
+        render_graph: &mut RenderGraph,
+    ) -> (RenderGraphTexture, [u32; 2], &mut MainPassBuffers) {
+        if self.requested_size != self.target.size {
+            let target = PreviewTarget::new(device, egui_renderer, self.requested_size);
+            let replaced = std::mem::replace(&mut self.target, target);
+            self.retired.retire(replaced);
+        }
+
+        (
+            render_graph.import_texture(&self.target.texture),
+            self.target.size,
+            &mut self.buffers,
+        )
+    }
+
+    /// Hands over the fence of the frame's submission, call once per frame
+    pub fn end_frame(&mut self, egui_renderer: &mut breda::egui::Renderer, fence: Fence) {
+        self.retired.end_frame_with(&fence, |target| {
+            egui_renderer.unregister_texture(target.texture_id)
+        });
+        self.buffers.end_frame(fence);
+    }
+
+    /// Shows the target filling the window, and requests the window's size for the next frame
+    pub fn ui(&mut self, ui: &mut egui::Ui) {
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let available = ui.available_size() * pixels_per_point;
+        self.requested_size = [available.x, available.y].map(|pixels| (pixels as u32).max(1));
+
+        let [width, height] = self.target.size;
+        ui.image(egui::load::SizedTexture::new(
+            self.target.texture_id,
+            egui::vec2(width as f32, height as f32) / pixels_per_point,
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use breda::render_loop::v2::opts::{WindowDesc, WindowIcon};
+use clap::Args;
+
+/// Shown in the title bar and taskbar unless --window-icon replaces it
+const DEFAULT_ICON: &[u8] = include_bytes!("../assets/icon.png");
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct WindowIconOpts {
+    /// Image to use as the window's icon instead of the template's, in any format the `image`
+    /// crate decodes. Falls back to the template's icon with a warning when it can't be loaded.
+    /// Ignored where windows have no runtime icon, like on macOS, where the dock shows the app
+    /// bundle's icon, and on Android.
+    #[clap(long, value_name = "PATH")]
+    pub window_icon: Option<PathBuf>,
+}
+
+impl WindowIconOpts {
+    /// Sets the icon of `window_desc`. On platforms without runtime icons nothing is loaded, so
+    /// that a broken --window-icon isn't warned about where it would never be shown.
+    pub fn apply(&self, window_desc: &mut WindowDesc) -> Result<()> {
+        if cfg!(any(
+            target_os = "android",
+            target_os = "ios",
+            target_os = "macos"
+        )) {
+            log::debug!("Windows have no runtime icon on this platform, not setting one");
+            return Ok(());
+        }
+
+        let custom = self.window_icon.as_ref().and_then(|path| {
+            image::open(path)
+                .map_err(|e| {
+                    log::warn!(
+                        "Failed to load window icon `{}`, using the default icon: {e}",
+                        path.display()
+                    );
+                })
+                .ok()
+        });
+        let image = match custom {
+            Some(image) => image,
+            None => image::load_from_memory(DEFAULT_ICON).context("Embedded icon is malformed")?,
+        }
+        .to_rgba8();
+        let [width, height] = image.dimensions().into();
+        window_desc.icon = Some(WindowIcon::from_rgba(image.into_raw(), width, height)?);
+        Ok(())
+    }
+}